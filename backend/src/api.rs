@@ -0,0 +1,732 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Request and response bodies for the HTTP API and WebSocket messages. The
+// OpenAPI document served at `/openapi.json` is generated from these types.
+
+/// Machine-readable reason attached to every error response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request body is not valid JSON or is missing required fields.
+    InvalidBody,
+    InvalidPassword,
+    /// No valid admin session.
+    Unauthorized,
+    /// Signed in, but the user's role or stream grants don't allow this.
+    Forbidden,
+    NotFound,
+    UnknownStream,
+    FileNotFound,
+    /// The request is valid but can't be applied to the stream right now.
+    Conflict,
+    Internal,
+    /// A WebSocket message that isn't valid JSON or has an unknown `type`.
+    InvalidMessage,
+    UnsupportedVersion,
+    /// The config file failed to load or validate.
+    InvalidConfig,
+    /// Too many failed logins; try again after the `Retry-After` seconds.
+    RateLimited,
+    /// The password was right, but the user has two-factor login on; send
+    /// the login again with `totp`.
+    TotpRequired,
+    /// A TOTP or recovery code that didn't match or was already used.
+    InvalidTotp,
+    /// The server or stream has as many listeners as it allows; try again
+    /// after the `Retry-After` seconds.
+    ServerFull,
+    /// Too many open connections from this address.
+    TooManyConnections,
+    /// The request body is larger than `MAX_BODY_BYTES`.
+    BodyTooLarge,
+    /// The request body didn't arrive within `BODY_TIMEOUT_SECS`.
+    RequestTimeout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Human-readable description.
+    pub error: String,
+    pub code: ErrorCode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OkResponse {
+    pub ok: bool,
+}
+
+impl OkResponse {
+    pub const OK: OkResponse = OkResponse { ok: true };
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamSummary {
+    pub id: String,
+    pub name: String,
+    pub paused: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Connected audio listeners.
+    pub listeners: usize,
+    /// Most listeners connected at once since the server started.
+    pub peak_listeners: usize,
+}
+
+/// Who can find and listen to a stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Listed in `/streams`, open to anyone.
+    #[default]
+    Public,
+    /// Open to anyone with the URL, but not listed.
+    Unlisted,
+    /// Not listed, and listening needs a listener login or token, a signed
+    /// URL, or an admin session with access to the stream.
+    Private,
+}
+
+impl Visibility {
+    pub fn is_public(&self) -> bool {
+        *self == Visibility::Public
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AdminStreamSummary {
+    #[serde(flatten)]
+    pub stream: StreamSummary,
+    pub visibility: Visibility,
+    pub playlist: String,
+    pub simulcast_of: Option<String>,
+    /// Source currently on air, e.g. the playlist or a fallback.
+    pub source: String,
+    /// 0 while the primary playlist plays; higher values are fallbacks.
+    pub fallback_level: usize,
+    /// Most recent failover alert, if any.
+    pub alert: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    /// Defaults to `admin`, the account created from `ADMIN_PASSWORD`.
+    #[serde(default)]
+    pub username: Option<String>,
+    pub password: String,
+    /// A code from the authenticator app, or a recovery code. Only for
+    /// users with two-factor login on.
+    #[serde(default)]
+    pub totp: Option<String>,
+}
+
+/// What an admin user may do. Each role includes everything the ones below
+/// it can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Read-only: stream details, stats and queues.
+    Viewer,
+    /// Also skip, pause, resume, seek and edit the queue.
+    Dj,
+    /// Also rename streams, run overrides and reload the config.
+    Operator,
+    /// Also manage users. Always has every stream.
+    Owner,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserInfo {
+    pub username: String,
+    pub role: Role,
+    /// Streams the user may see and act on; every stream when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    /// Whether logins need a TOTP code.
+    #[serde(default)]
+    pub totp: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+}
+
+/// Replaces a user's role and stream grants.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UpdateUserRequest {
+    pub role: Role,
+    /// Every stream when absent.
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PasswordRequest {
+    pub password: String,
+}
+
+/// A TOTP secret waiting for its first code.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TotpSetupResponse {
+    /// Base32, for typing into an authenticator app.
+    pub secret: String,
+    /// `otpauth://` URI, for showing as a QR code.
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    /// A code from the authenticator app; recovery codes also work for
+    /// turning two-factor login off.
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// Each works once in place of a TOTP code. Only ever shown here.
+    pub recovery_codes: Vec<String>,
+}
+
+/// Someone who may listen to private streams with HTTP basic auth.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ListenerInfo {
+    pub username: String,
+    /// Private streams they may listen to; every one when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateListenerRequest {
+    pub username: String,
+    pub password: String,
+    /// Every private stream when absent.
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+}
+
+/// A listener token, without its secret.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ListenerTokenInfo {
+    pub id: String,
+    pub name: String,
+    /// Private streams it opens; every one when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    pub created_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateListenerTokenRequest {
+    /// Who or what the token is for.
+    pub name: String,
+    /// Every private stream when absent.
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+    /// 1-3650. Never expires when absent.
+    #[serde(default)]
+    pub expires_in_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateListenerTokenResponse {
+    /// Send as `?token=`, or as `Authorization: Bearer`. Only ever shown here.
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ListenerTokenInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ListenersResponse {
+    pub listeners: Vec<ListenerInfo>,
+    pub tokens: Vec<ListenerTokenInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SignedUrlRequest {
+    /// At most a year. Defaults to a day.
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SignedUrlResponse {
+    /// Path and query of the stream's audio. The same `expires` and `sig`
+    /// also open its playlist image, events and WebSocket.
+    pub url: String,
+    pub expires_at_ms: u64,
+}
+
+/// An API token, without its secret.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiTokenInfo {
+    pub id: String,
+    pub name: String,
+    /// The user the token acts as.
+    pub username: String,
+    /// The most the token may do; never more than its user's role.
+    pub role: Role,
+    /// Streams the token is limited to, on top of its user's grants.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    pub created_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+    /// Accurate to about a minute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateTokenRequest {
+    /// What the token is for, e.g. the script that uses it.
+    pub name: String,
+    /// Defaults to the user's own role.
+    #[serde(default)]
+    pub role: Option<Role>,
+    /// Defaults to all of the user's streams.
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+    /// 1-3650. Never expires when absent.
+    #[serde(default)]
+    pub expires_in_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreateTokenResponse {
+    /// Send as `Authorization: Bearer <token>`. Only ever shown here.
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+}
+
+/// A signed-in admin session.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SessionInfo {
+    /// For revoking; not the cookie value.
+    pub id: String,
+    pub username: String,
+    pub created_at_ms: u64,
+    /// Accurate to about a minute.
+    pub last_seen_ms: u64,
+    /// Pushed back on every use, up to 30 days after login.
+    pub expires_at_ms: u64,
+    /// The browser or client that signed in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Where it signed in from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RenameRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SeekRequest {
+    pub position_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SeekResponse {
+    pub ok: bool,
+    /// Position actually landed on, at most one frame past the target.
+    pub position_ms: u64,
+}
+
+/// Exactly one of `path` and `source_stream` must be set.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OverrideRequest {
+    /// Audio file to play, from the playlist folder of one of the
+    /// overridden streams.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Stream to relay live.
+    #[serde(default)]
+    pub source_stream: Option<String>,
+    /// Streams to override; all streams when omitted or empty.
+    #[serde(default)]
+    pub streams: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OverrideResponse {
+    pub ok: bool,
+    pub streams: Vec<String>,
+}
+
+/// Track information pushed over the WebSocket, and sent in reply to a
+/// `"metadata"` request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrackMetadata {
+    /// Changes whenever a new file starts.
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub active_file_duration_ms: u64,
+    /// Stream time at which the track started; negative when playback
+    /// started partway into the track.
+    pub active_file_start_time_ms: i64,
+    pub active_file_current_time_ms: u64,
+    pub buffer_size_ms: u64,
+    pub image: Option<String>,
+    pub paused: bool,
+    /// True while an emergency override is on air.
+    #[serde(rename = "override")]
+    pub overridden: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listeners: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_listeners: Option<usize>,
+}
+
+/// A track that went on air, as kept in a stream's recent history.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    pub title: String,
+    pub author: String,
+    /// Unix time in milliseconds.
+    pub started_at_ms: u64,
+    /// Source it played from, e.g. the playlist or a fallback.
+    pub source: String,
+}
+
+/// One audio connection to a stream.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ListenerSession {
+    /// Unix time in milliseconds.
+    pub connected_at_ms: u64,
+    /// So far, for sessions that are still connected.
+    pub duration_ms: u64,
+    pub user_agent: Option<String>,
+    /// Keyed hash of the client address; the key changes on every restart.
+    pub ip_hash: Option<String>,
+    /// Still connected.
+    pub active: bool,
+}
+
+/// How many people heard a track.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TrackAudience {
+    pub title: String,
+    pub author: String,
+    /// Unix time in milliseconds.
+    pub started_at_ms: u64,
+    /// Time on air; still growing for the current track.
+    pub duration_ms: u64,
+    /// Listeners averaged over the time on air.
+    pub average_listeners: f64,
+    pub peak_listeners: usize,
+}
+
+/// Audience figures for a stream since the server started.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamStats {
+    pub stream: String,
+    pub listeners: usize,
+    pub peak_listeners: usize,
+    /// Summed over every session, including connected ones.
+    pub total_listening_hours: f64,
+    /// Most recent first, the last 500 finished sessions after the
+    /// connected ones.
+    pub sessions: Vec<ListenerSession>,
+    /// Current track first, then the last 50 finished ones.
+    pub tracks: Vec<TrackAudience>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QueueAddRequest {
+    /// A file in the stream's playlist folder, relative to it or absolute.
+    pub path: String,
+    /// Position in the queue; appended when omitted.
+    #[serde(default)]
+    pub index: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QueueResponse {
+    /// Files that play next, before the playlist continues.
+    pub items: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamHealth {
+    pub stream: String,
+    /// The player answered within a second.
+    pub player_responsive: bool,
+    /// Unix time in milliseconds of the last audio packet, if any yet.
+    pub last_packet_at_ms: Option<u64>,
+    /// Whether the playlist task is running; null for simulcasts, which
+    /// have none of their own.
+    pub playlist_running: Option<bool>,
+    pub paused: bool,
+    /// No audio was produced recently although the stream isn't paused.
+    pub stalled: bool,
+    /// Whether this stream passes the check that was asked for.
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthResponse {
+    pub ok: bool,
+    /// Renames can't be persisted while this is false; it doesn't affect
+    /// `ok`.
+    pub config_writable: bool,
+    pub streams: Vec<StreamHealth>,
+}
+
+/// Messages a client sends on the `/ws` WebSocket. Everything from `skip`
+/// on requires an admin session.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Optional; fails with `unsupported_version` if the server doesn't speak
+    /// `version`.
+    Hello { version: u32 },
+    Subscribe { streams: Vec<String> },
+    Unsubscribe { streams: Vec<String> },
+    GetMetadata { stream: String },
+    GetHistory {
+        stream: String,
+        /// Most recent first; defaults to everything kept.
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    Ping,
+    Skip { stream: String },
+    Pause { stream: String },
+    Resume { stream: String },
+    Rename { stream: String, name: String },
+    Seek { stream: String, position_ms: u64 },
+    GetQueue { stream: String },
+    QueueAdd {
+        stream: String,
+        path: String,
+        #[serde(default)]
+        index: Option<usize>,
+    },
+    QueueRemove { stream: String, index: usize },
+    QueueClear { stream: String },
+}
+
+/// Replies to `ClientMessage`s on the `/ws` WebSocket.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent once when the socket opens. `admin` is true when the handshake
+    /// carried a valid admin session.
+    Welcome { protocol_version: u32, admin: bool },
+    Subscribed { streams: Vec<String> },
+    Unsubscribed { streams: Vec<String> },
+    Metadata { stream: String, metadata: TrackMetadata },
+    History { stream: String, tracks: Vec<HistoryEntry> },
+    Pong,
+    /// An admin command succeeded.
+    Ok,
+    Seeked { stream: String, position_ms: u64 },
+    Queue { stream: String, items: Vec<String> },
+    Error { code: ErrorCode, message: String },
+}
+
+impl ClientMessage {
+    pub fn is_admin(&self) -> bool {
+        !matches!(
+            self,
+            ClientMessage::Hello { .. }
+                | ClientMessage::Subscribe { .. }
+                | ClientMessage::Unsubscribe { .. }
+                | ClientMessage::GetMetadata { .. }
+                | ClientMessage::GetHistory { .. }
+                | ClientMessage::Ping
+        )
+    }
+}
+
+/// Pushed to every socket subscribed to `stream`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// A new track started, or the current one's timing changed (seek,
+    /// override start or end).
+    TrackChanged { stream: String, metadata: TrackMetadata },
+    Paused { stream: String, paused: bool, metadata: TrackMetadata },
+    Renamed { stream: String, name: String },
+    ListenerCount { stream: String, count: usize },
+    /// The stream switched to another source, e.g. a fallback or a new
+    /// playlist.
+    ScheduleChanged { stream: String, source: String, fallback_level: usize },
+}
+
+impl StreamEvent {
+    /// The serialized `type`, used as the SSE event name.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StreamEvent::TrackChanged { .. } => "track_changed",
+            StreamEvent::Paused { .. } => "paused",
+            StreamEvent::Renamed { .. } => "renamed",
+            StreamEvent::ListenerCount { .. } => "listener_count",
+            StreamEvent::ScheduleChanged { .. } => "schedule_changed",
+        }
+    }
+}
+
+/// Pushed to every admin socket, whatever it is subscribed to.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminEvent {
+    /// A source in the stream's fallback chain failed.
+    Alert { stream: String, message: String },
+    PlaybackError { stream: String, file: String, message: String },
+    ListenerJoined { stream: String, count: usize },
+    ListenerLeft { stream: String, count: usize },
+    /// The stream went on air from a different source.
+    SourceConnected { stream: String, source: String, fallback_level: usize },
+    /// The config file was reloaded, or a reload was rejected.
+    ConfigReloaded { report: ReloadReport },
+}
+
+impl AdminEvent {
+    /// The stream the event is about; `None` for server-wide events.
+    pub fn stream(&self) -> Option<&str> {
+        match self {
+            AdminEvent::Alert { stream, .. }
+            | AdminEvent::PlaybackError { stream, .. }
+            | AdminEvent::ListenerJoined { stream, .. }
+            | AdminEvent::ListenerLeft { stream, .. }
+            | AdminEvent::SourceConnected { stream, .. } => Some(stream),
+            AdminEvent::ConfigReloaded { .. } => None,
+        }
+    }
+}
+
+/// A stream whose config changed in a reload.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StreamChange {
+    pub stream: String,
+    /// Config keys whose value changed, e.g. `name` or `fallback`.
+    pub fields: Vec<String>,
+}
+
+/// Differences between the running streams and the reloaded config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ConfigDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<StreamChange>,
+    pub default_stream_changed: bool,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && !self.default_stream_changed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReloadTrigger {
+    /// SIGHUP.
+    Signal,
+    /// `CONFIG_WATCH_SECS` noticed the file change.
+    File,
+    /// `POST /admin/reload`.
+    Api,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReloadReport {
+    pub at_ms: u64,
+    pub trigger: ReloadTrigger,
+    /// False when the new config was rejected; running streams were left
+    /// untouched.
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Empty when rejected.
+    pub diff: ConfigDiff,
+    /// Changes that were applied but didn't fully take, e.g. a new playlist
+    /// folder without any tracks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// What an admin did, as recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    /// A wrong password or two-factor code.
+    LoginFailed,
+    Logout,
+    Skip,
+    Pause,
+    Resume,
+    Seek,
+    Rename,
+    QueueAdd,
+    QueueRemove,
+    QueueClear,
+    OverrideStart,
+    OverrideEnd,
+    ConfigReload,
+    UserCreate,
+    UserUpdate,
+    UserDelete,
+    PasswordReset,
+    TotpEnable,
+    TotpDisable,
+    TotpReset,
+    TokenCreate,
+    TokenRevoke,
+    SessionRevoke,
+    ListenerSave,
+    ListenerDelete,
+    ListenerTokenCreate,
+    ListenerTokenRevoke,
+    SignedUrl,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub at_ms: u64,
+    pub action: AuditAction,
+    /// Who did it; unset for reloads on SIGHUP or a file change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// The user, token, session or listener acted on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// What changed from, e.g. a stream's old name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<serde_json::Value>,
+    /// What changed to, e.g. a stream's new name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<serde_json::Value>,
+    /// Anything else worth knowing, e.g. why a login failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
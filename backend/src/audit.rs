@@ -0,0 +1,310 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    net::IpAddr,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::{AuditAction, AuditEntry},
+    stats::now_ms,
+    users::User,
+};
+
+/// Entries `GET /admin/audit` returns unless asked for fewer.
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+/// Who did something: a signed-in user and where they were, or the server
+/// itself for reloads on SIGHUP or a file change.
+#[derive(Debug, Clone, Default)]
+pub struct Actor {
+    pub username: Option<String>,
+    pub ip: Option<IpAddr>,
+}
+
+impl Actor {
+    pub fn new(user: &User, ip: Option<IpAddr>) -> Self {
+        Self { username: Some(user.username.clone()), ip }
+    }
+
+    pub fn system() -> Self {
+        Self::default()
+    }
+
+    /// A new entry for `action`, stamped now.
+    pub fn entry(&self, action: AuditAction) -> AuditEntry {
+        AuditEntry {
+            at_ms: now_ms(),
+            action,
+            user: self.username.clone(),
+            ip: self.ip.map(|ip| ip.to_string()),
+            stream: None,
+            target: None,
+            before: None,
+            after: None,
+            detail: None,
+        }
+    }
+}
+
+impl AuditEntry {
+    pub fn stream(mut self, stream_id: &str) -> Self {
+        self.stream = Some(stream_id.to_string());
+        self
+    }
+
+    /// The user, token, session or listener acted on.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn before(mut self, value: impl Serialize) -> Self {
+        self.before = serde_json::to_value(value).ok();
+        self
+    }
+
+    pub fn after(mut self, value: impl Serialize) -> Self {
+        self.after = serde_json::to_value(value).ok();
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// Which entries `GET /admin/audit` wants; every field narrows the result.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub user: Option<String>,
+    pub stream: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since_ms: Option<u64>,
+    pub until_ms: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    /// Parses `user`, `stream`, `action`, `since_ms`, `until_ms` and `limit`
+    /// from a query string; unknown parameters are ignored.
+    pub fn from_query(query: Option<&str>) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for (key, value) in query.unwrap_or("").split('&').filter_map(|kv| kv.split_once('=')) {
+            let (key, value) = (percent_decode(key), percent_decode(value));
+            let (key, value) = (key.as_str(), value.as_str());
+            let number = |value: &str| value.parse::<u64>().map_err(|_| format!("{} must be a number", key));
+            match key {
+                "user" => filter.user = Some(value.to_string()),
+                "stream" => filter.stream = Some(value.to_string()),
+                "action" => {
+                    let action = serde_json::from_value(serde_json::Value::String(value.to_string()))
+                        .map_err(|_| format!("unknown action '{}'", value))?;
+                    filter.action = Some(action);
+                },
+                "since_ms" => filter.since_ms = Some(number(value)?),
+                "until_ms" => filter.until_ms = Some(number(value)?),
+                "limit" => filter.limit = Some(number(value)? as usize),
+                _ => {},
+            }
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.user.as_ref().is_none_or(|u| entry.user.as_ref() == Some(u))
+            && self.stream.as_ref().is_none_or(|s| entry.stream.as_ref() == Some(s))
+            && self.action.is_none_or(|a| entry.action == a)
+            && self.since_ms.is_none_or(|since| entry.at_ms >= since)
+            && self.until_ms.is_none_or(|until| entry.at_ms < until)
+    }
+}
+
+/// Decodes `%XX` escapes and `+` for space. Invalid escapes are kept as
+/// they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            },
+            (b'+', _) => decoded.push(b' '),
+            (b, _) => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Admin actions, one JSON object per line, only ever appended to. The file
+/// is reopened for every entry so it can be rotated underneath us.
+pub struct AuditLog {
+    path: PathBuf,
+    /// Keeps concurrent entries from interleaving.
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), lock: Mutex::new(()) }
+    }
+
+    /// Appends `entry` and echoes it to the `audit` log target. A failed
+    /// write is logged but doesn't fail the action itself.
+    pub fn record(&self, entry: AuditEntry) {
+        let json = |value: &Option<serde_json::Value>| value.as_ref().map(|v| v.to_string()).unwrap_or_default();
+        let user = entry.user.as_deref().unwrap_or("-");
+        let ip = entry.ip.as_deref().unwrap_or("");
+        let stream = entry.stream.as_deref().unwrap_or("");
+        let target = entry.target.as_deref().unwrap_or("");
+        let detail = entry.detail.as_deref().unwrap_or("");
+        if entry.action == AuditAction::LoginFailed {
+            warn!(target: "audit", user, ip, action = ?entry.action, detail, "Admin action");
+        } else {
+            info!(
+                target: "audit",
+                user, ip, stream, subject = target, before = %json(&entry.before), after = %json(&entry.after), detail,
+                action = ?entry.action,
+                "Admin action",
+            );
+        }
+
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit entry: {}", e);
+                return;
+            },
+        };
+        let _guard = self.lock.lock().expect("audit lock");
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            // Owner-only: it names users and where they signed in from.
+            .mode(0o600)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = written {
+            error!("Failed to write audit log {:?}: {}", self.path, e);
+        }
+    }
+
+    /// The newest entries matching `filter` that `visible` lets through,
+    /// newest first.
+    pub fn query(&self, filter: &AuditFilter, visible: impl Fn(&AuditEntry) -> bool) -> Result<Vec<AuditEntry>, String> {
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("read audit log: {}", e)),
+        };
+
+        let mut newest = VecDeque::with_capacity(limit);
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("read audit log: {}", e))?;
+            // Skips a line cut short by a crash rather than failing the lot.
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                continue;
+            };
+            if filter.matches(&entry) && visible(&entry) {
+                if newest.len() == limit {
+                    newest.pop_front();
+                }
+                newest.push_back(entry);
+            }
+        }
+        Ok(newest.into_iter().rev().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(at_ms: u64, user: &str, stream: Option<&str>, action: AuditAction) -> AuditEntry {
+        let mut entry = Actor { username: Some(user.to_string()), ip: None }.entry(action);
+        entry.at_ms = at_ms;
+        entry.stream = stream.map(str::to_string);
+        entry
+    }
+
+    fn log(name: &str, entries: Vec<AuditEntry>) -> (AuditLog, PathBuf) {
+        let path = std::env::temp_dir().join(format!("octopus-audit-{}-{}.jsonl", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let log = AuditLog::new(&path);
+        for entry in entries {
+            log.record(entry);
+        }
+        (log, path)
+    }
+
+    fn times(entries: &[AuditEntry]) -> Vec<u64> {
+        entries.iter().map(|e| e.at_ms).collect()
+    }
+
+    #[test]
+    fn parses_and_decodes_the_query() {
+        let filter = AuditFilter::from_query(Some("user=ana%20b&stream=a%2Db&action=queue_add&since_ms=5&until_ms=9&limit=3&x=y")).unwrap();
+        assert_eq!(filter.user.as_deref(), Some("ana b"));
+        assert_eq!(filter.stream.as_deref(), Some("a-b"));
+        assert_eq!(filter.action, Some(AuditAction::QueueAdd));
+        assert_eq!((filter.since_ms, filter.until_ms, filter.limit), (Some(5), Some(9), Some(3)));
+        assert_eq!(AuditFilter::from_query(Some("user=a+b&stream=100%")).unwrap().stream.as_deref(), Some("100%"));
+        assert!(AuditFilter::from_query(Some("action=explode")).is_err());
+        assert!(AuditFilter::from_query(Some("since_ms=yesterday")).is_err());
+        assert!(AuditFilter::from_query(None).unwrap().user.is_none());
+    }
+
+    #[test]
+    fn filters_newest_first() {
+        let (log, path) = log("filters", vec![
+            entry(1, "ana", Some("main"), AuditAction::Skip),
+            entry(2, "bo", Some("main"), AuditAction::Skip),
+            entry(3, "ana", Some("a-b"), AuditAction::Pause),
+            entry(4, "ana", Some("main"), AuditAction::Skip),
+            entry(5, "ana", None, AuditAction::ConfigReload),
+        ]);
+        let query = |q: &str| log.query(&AuditFilter::from_query(Some(q)).unwrap(), |_| true).unwrap();
+        assert_eq!(times(&query("")), [5, 4, 3, 2, 1]);
+        assert_eq!(times(&query("user=ana")), [5, 4, 3, 1]);
+        assert_eq!(times(&query("stream=a%2Db")), [3]);
+        assert_eq!(times(&query("action=skip&user=ana")), [4, 1]);
+        // `since_ms` is inclusive, `until_ms` exclusive.
+        assert_eq!(times(&query("since_ms=2&until_ms=4")), [3, 2]);
+        let visible = log.query(&AuditFilter::default(), |e| e.stream.as_deref() == Some("main")).unwrap();
+        assert_eq!(times(&visible), [4, 2, 1]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn limit_keeps_the_newest_and_is_clamped() {
+        let (log, path) = log("limit", (1..=5).map(|at| entry(at, "ana", None, AuditAction::Skip)).collect());
+        let query = |q: &str| log.query(&AuditFilter::from_query(Some(q)).unwrap(), |_| true).unwrap();
+        assert_eq!(times(&query("limit=2")), [5, 4]);
+        assert_eq!(times(&query("limit=0")), [5]);
+        assert_eq!(query("limit=5000").len(), 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_missing_log_is_empty() {
+        let log = AuditLog::new(Path::new("/nonexistent/audit.jsonl"));
+        assert!(log.query(&AuditFilter::default(), |_| true).unwrap().is_empty());
+    }
+}
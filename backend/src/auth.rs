@@ -1,72 +1,174 @@
-use std::{collections::HashMap, sync::Mutex, time::{Duration, Instant}};
+use std::net::IpAddr;
 
 use hyper::{header, Request};
 use rand::RngCore;
-use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::{
+    listeners::{Denied, ListenerCredentials, ListenerStore, LISTENER_TOKEN_PREFIX},
+    sessions::{SessionStore, SESSION_MAX_AGE},
+    stats::now_ms,
+    throttle::LoginThrottle,
+    tokens::{self, TokenStore, TOKEN_PREFIX},
+    users::{dummy_hash, verify_password, Permission, Scope, User, UserStore},
+};
 
-const SESSION_TTL: Duration = Duration::from_secs(60 * 60 * 24);
 pub const COOKIE_NAME: &str = "octopus_admin";
 
 pub struct AuthState {
-    password: String,
-    sessions: Mutex<HashMap<String, Instant>>,
+    pub users: UserStore,
+    pub tokens: TokenStore,
+    pub sessions: SessionStore,
+    pub throttle: LoginThrottle,
+    pub listeners: ListenerStore,
+    /// Listener basic auth failures, kept apart from admin logins so
+    /// listeners can't lock admins out.
+    listener_throttle: LoginThrottle,
 }
 
 impl AuthState {
-    pub fn new(password: String) -> Self {
-        Self {
-            password,
-            sessions: Mutex::new(HashMap::new()),
-        }
+    pub fn new(users: UserStore, tokens: TokenStore, sessions: SessionStore, throttle: LoginThrottle, listeners: ListenerStore) -> Self {
+        let listener_throttle = throttle.fresh();
+        Self { users, tokens, sessions, throttle, listeners, listener_throttle }
     }
 
-    pub fn check_password(&self, candidate: &str) -> bool {
-        let a = candidate.as_bytes();
-        let b = self.password.as_bytes();
-        if a.len() != b.len() {
-            // Still do a fixed-length compare to avoid trivial length-based timing leaks.
-            let _ = a.ct_eq(a);
-            return false;
-        }
-        a.ct_eq(b).into()
+    /// The user, if `password` is theirs.
+    pub async fn authenticate(&self, username: &str, password: &str) -> Option<User> {
+        let user = self.users.get(username);
+        // Unknown users are checked against a throwaway hash so they take as
+        // long to reject as a wrong password.
+        let hash = user.as_ref().map_or_else(|| dummy_hash().to_string(), |u| u.password_hash.clone());
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false);
+        user.filter(|_| valid)
+    }
+
+    /// Starts a session and returns its cookie value.
+    pub fn issue_session(&self, username: &str, user_agent: Option<String>, ip: Option<IpAddr>) -> Result<String, String> {
+        self.sessions.create(username, user_agent, ip, now_ms())
+    }
+
+    pub fn revoke(&self, token: &str) -> Result<(), String> {
+        self.sessions.revoke_token(token)
     }
 
-    pub fn issue_session(&self) -> String {
-        let mut bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut bytes);
-        let token = hex(&bytes);
-        let mut sessions = self.sessions.lock().expect("session lock");
-        sessions.insert(token.clone(), Instant::now());
-        token
+    /// Signs the user out everywhere, e.g. after a password reset.
+    pub fn revoke_user(&self, username: &str) -> Result<(), String> {
+        self.sessions.revoke_user(username)
     }
 
-    pub fn revoke(&self, token: &str) {
-        let mut sessions = self.sessions.lock().expect("session lock");
-        sessions.remove(token);
+    /// Sessions that haven't expired yet.
+    pub fn session_count(&self) -> usize {
+        self.sessions.count(now_ms())
     }
 
-    pub fn validate(&self, token: &str) -> bool {
-        let mut sessions = self.sessions.lock().expect("session lock");
-        match sessions.get(token).copied() {
-            Some(issued) if issued.elapsed() < SESSION_TTL => true,
-            Some(_) => {
-                sessions.remove(token);
-                false
+    /// The session's or API token's user, looked up afresh so role changes
+    /// and deletions apply to sessions that are already open. Sessions are
+    /// renewed as they are used.
+    pub fn validate(&self, token: &str) -> Option<User> {
+        if is_api_token(token) {
+            return self.validate_api_token(token);
+        }
+        let session = self.sessions.validate(token, now_ms())?;
+        self.users.get(&session.username)
+    }
+
+    fn validate_api_token(&self, token: &str) -> Option<User> {
+        let (id, secret) = tokens::parse(token)?;
+        let now = now_ms();
+        let token = self.tokens.verify(id, secret, now)?;
+        let user = self.users.get(&token.username)?;
+        self.tokens.touch(id, now);
+        Some(token.scoped(user))
+    }
+
+    /// Whether the credentials open the private stream `stream_id`: admins
+    /// who can view it, listener tokens and logins for it, and URLs signed
+    /// for it. Wrong basic auth passwords count as failed logins.
+    pub async fn authorize_listener(&self, stream_id: &str, credentials: &ListenerCredentials) -> Result<(), Denied> {
+        let now = now_ms();
+        if let Some((expires, sig)) = credentials.signature() {
+            if self.listeners.verify_signature(stream_id, expires, sig, now / 1000) {
+                return Ok(());
+            }
+        }
+        if let Some(token) = credentials.token() {
+            let allowed = match token.starts_with(LISTENER_TOKEN_PREFIX) {
+                true => self.listeners.verify_token(token, stream_id, now),
+                false => self.validate(token).is_some_and(|user| user.can(Permission::View, Scope::Stream(stream_id))),
+            };
+            if allowed {
+                return Ok(());
             }
-            None => false,
         }
+        let Some((username, password)) = credentials.basic() else {
+            return Err(Denied::Unauthorized);
+        };
+        let ip = credentials.ip;
+        if let Some(wait) = self.listener_throttle.check(ip, username) {
+            return Err(Denied::Throttled(wait));
+        }
+        if self.listeners.authenticate(username, password, stream_id).await.is_some() {
+            self.listener_throttle.record_success(username);
+            return Ok(());
+        }
+        let failure = self.listener_throttle.record_failure(ip, username);
+        warn!(
+            target: "audit",
+            user = %username,
+            ip = %ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            stream = %stream_id,
+            failures = failure.count,
+            "Failed listener login",
+        );
+        Err(failure.locked_for.map_or(Denied::Unauthorized, Denied::Throttled))
     }
 }
 
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// `bytes` random bytes, hex-encoded.
+pub fn random_hex(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut buf);
+    hex(&buf)
+}
+
+/// The session cookie, or else an `Authorization: Bearer` session or API
+/// token.
 pub fn extract_token<B>(req: &Request<B>) -> Option<String> {
-    let cookie_header = req.headers().get(header::COOKIE)?.to_str().ok()?;
-    for kv in cookie_header.split(';') {
-        let kv = kv.trim();
-        if let Some(rest) = kv.strip_prefix(&format!("{}=", COOKIE_NAME)) {
-            return Some(rest.to_string());
-        }
+    let cookie = req.headers().get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|cookies| {
+            cookies.split(';').find_map(|kv| kv.trim().strip_prefix(&format!("{}=", COOKIE_NAME)))
+        });
+    if let Some(token) = cookie {
+        return Some(token.to_string());
+    }
+    bearer_token(req)
+}
+
+fn bearer_token<B>(req: &Request<B>) -> Option<String> {
+    req.headers().get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+/// Like `extract_token`, but also accepts a `token` query parameter, since
+/// browsers can't set headers on a WebSocket handshake. The cookie is
+/// skipped unless `cookie` is set.
+pub fn extract_handshake_token<B>(req: &Request<B>, cookie: bool) -> Option<String> {
+    let token = if cookie { extract_token(req) } else { bearer_token(req) };
+    if token.is_some() {
+        return token;
     }
-    None
+    req.uri().query()?
+        .split('&')
+        .find_map(|kv| kv.strip_prefix("token="))
+        .map(|token| token.to_string())
 }
 
 pub fn cookie_header_value(token: &str) -> String {
@@ -74,7 +176,7 @@ pub fn cookie_header_value(token: &str) -> String {
         "{}={}; HttpOnly; SameSite=Strict; Path=/; Max-Age={}",
         COOKIE_NAME,
         token,
-        SESSION_TTL.as_secs()
+        SESSION_MAX_AGE.as_secs()
     )
 }
 
@@ -85,7 +187,7 @@ pub fn cookie_clear_value() -> String {
     )
 }
 
-fn hex(bytes: &[u8]) -> String {
+pub fn hex(bytes: &[u8]) -> String {
     let mut s = String::with_capacity(bytes.len() * 2);
     for b in bytes {
         s.push_str(&format!("{:02x}", b));
@@ -2,11 +2,120 @@ use std::{collections::HashSet, fs, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
 
+use crate::api::Visibility;
+
+const DEFAULT_FAILOVER_RETRY_SECS: u64 = 60;
+const DEFAULT_TONE_HZ: f32 = 440.0;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamConfig {
     pub id: String,
     pub name: String,
+    /// Required unless the stream is a simulcast of another one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub playlist: String,
+    /// Share another stream's player: same decode and timeline, but its own
+    /// id, name and listeners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simulcast_of: Option<String>,
+    /// Encoder bitrate for this stream's listeners. Uses the encoder default
+    /// when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate_kbps: Option<u32>,
+    /// Sources tried in order when the playlist is empty, keeps failing, or
+    /// goes silent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<FallbackSource>,
+    /// Fail over after this many seconds of continuous silence. Disabled when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub silence_threshold_secs: Option<u64>,
+    /// How long a fallback plays before the chain is retried from the top.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failover_retry_secs: Option<u64>,
+    /// On restart, seek back into the track that was playing instead of
+    /// starting it from the beginning.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub resume_mid_track: bool,
+    /// Whether the stream is listed, and whether listening needs
+    /// credentials.
+    #[serde(default, skip_serializing_if = "Visibility::is_public")]
+    pub visibility: Visibility,
+    /// Audio listeners beyond this many get a 503. Unlimited when unset,
+    /// apart from `MAX_LISTENERS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_listeners: Option<usize>,
+    /// What happens to a listener whose connection can't keep up.
+    #[serde(default, skip_serializing_if = "SlowListenerPolicy::is_drop")]
+    pub slow_listener: SlowListenerPolicy,
+}
+
+impl StreamConfig {
+    /// The id of the stream that owns the player this stream plays from.
+    pub fn source_id(&self) -> &str {
+        self.simulcast_of.as_deref().unwrap_or(&self.id)
+    }
+
+    pub fn failover_retry_secs(&self) -> u64 {
+        self.failover_retry_secs.unwrap_or(DEFAULT_FAILOVER_RETRY_SECS)
+    }
+}
+
+/// What to do with a listener whose buffer of unsent audio fills up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowListenerPolicy {
+    /// Disconnect it.
+    #[default]
+    Drop,
+    /// Leave out the audio it can't take and carry on from the live
+    /// position once its buffer has drained.
+    SkipAhead,
+    /// Step it down to a lower bitrate each time it falls behind, skipping
+    /// ahead while it catches up.
+    LowerBitrate,
+}
+
+impl SlowListenerPolicy {
+    pub fn is_drop(&self) -> bool {
+        *self == SlowListenerPolicy::Drop
+    }
+}
+
+/// A source a stream falls back to after its own playlist. There is no
+/// live source yet, as nothing can ingest a live feed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FallbackSource {
+    /// Another playlist directory, e.g. an emergency playlist.
+    Playlist { path: String },
+    /// Relay the output of another configured stream.
+    Stream { id: String },
+    /// A generated sine tone.
+    Tone {
+        #[serde(default = "default_tone_hz")]
+        frequency_hz: f32,
+    },
+    /// Generated silence, keeping listeners connected.
+    Silence,
+}
+
+impl FallbackSource {
+    pub fn label(&self) -> String {
+        match self {
+            FallbackSource::Playlist { path } => format!("playlist {}", path),
+            FallbackSource::Stream { id } => format!("stream {}", id),
+            FallbackSource::Tone { frequency_hz } => format!("tone {} Hz", frequency_hz),
+            FallbackSource::Silence => "silence".to_string(),
+        }
+    }
+}
+
+fn default_tone_hz() -> f32 {
+    DEFAULT_TONE_HZ
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,6 +144,67 @@ impl StreamsConfig {
                 return Err(format!("duplicate stream id: {}", s.id));
             }
         }
+        for s in &self.streams {
+            if let Some(bitrate) = s.bitrate_kbps {
+                if !(6..=510).contains(&bitrate) {
+                    return Err(format!("stream '{}' bitrate_kbps must be between 6 and 510", s.id));
+                }
+            }
+            match &s.simulcast_of {
+                Some(source) => {
+                    let target = self.streams.iter().find(|t| &t.id == source);
+                    match target {
+                        None => {
+                            return Err(format!("stream '{}' is a simulcast of unknown stream '{}'", s.id, source));
+                        }
+                        Some(t) if t.simulcast_of.is_some() => {
+                            return Err(format!(
+                                "stream '{}' is a simulcast of '{}', which is itself a simulcast",
+                                s.id, source
+                            ));
+                        }
+                        Some(_) => {}
+                    }
+                    if !s.fallback.is_empty() || s.silence_threshold_secs.is_some() || s.resume_mid_track {
+                        return Err(format!(
+                            "stream '{}' is a simulcast; configure playout options on '{}' instead",
+                            s.id, source
+                        ));
+                    }
+                }
+                None if s.playlist.is_empty() => {
+                    return Err(format!("stream '{}' needs a playlist", s.id));
+                }
+                None => {}
+            }
+            for fallback in &s.fallback {
+                match fallback {
+                    FallbackSource::Stream { id } if id == &s.id => {
+                        return Err(format!("stream '{}' cannot fall back to itself", s.id));
+                    }
+                    FallbackSource::Stream { id } if !seen.contains(id) => {
+                        return Err(format!(
+                            "stream '{}' falls back to unknown stream '{}'",
+                            s.id, id
+                        ));
+                    }
+                    FallbackSource::Tone { frequency_hz } if !(0.0..=20_000.0).contains(frequency_hz) => {
+                        return Err(format!(
+                            "stream '{}' has an out-of-range tone frequency: {}",
+                            s.id, frequency_hz
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            if s.max_listeners == Some(0) {
+                return Err(format!("stream '{}' max_listeners must be positive", s.id));
+            }
+            if s.failover_retry_secs == Some(0) {
+                return Err(format!("stream '{}' failover_retry_secs must be positive", s.id));
+            }
+        }
+        self.check_fallback_loops()?;
         if let Some(default) = &self.default_stream {
             if !self.streams.iter().any(|s| &s.id == default) {
                 return Err(format!(
@@ -46,11 +216,157 @@ impl StreamsConfig {
         Ok(())
     }
 
-    pub fn save(&self, path: &Path) -> Result<(), String> {
+    /// Relaying a stream that ends up relaying the relay back never plays
+    /// anything, so reject fallbacks that loop. A simulcast plays from its
+    /// source's player, so falling back to one means falling back to its
+    /// source.
+    fn check_fallback_loops(&self) -> Result<(), String> {
+        let source_of = |id: &str| {
+            self.streams.iter().find(|s| s.id == id).map_or(id, |s| s.source_id()).to_string()
+        };
+        let relays = |id: &str| -> Vec<String> {
+            self.streams.iter()
+                .filter(|s| s.id == id)
+                .flat_map(|s| &s.fallback)
+                .filter_map(|f| match f {
+                    FallbackSource::Stream { id } => Some(source_of(id)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        for start in self.streams.iter().filter(|s| s.simulcast_of.is_none()) {
+            // Walks every relay path from `start`; the config is small.
+            let mut paths = vec![vec![start.id.clone()]];
+            while let Some(path) = paths.pop() {
+                let last = path.last().expect("paths are never empty");
+                for next in relays(last) {
+                    if next == start.id {
+                        let mut chain = path.clone();
+                        chain.push(next);
+                        return Err(format!("stream fallbacks form a loop: {}", chain.join(" -> ")));
+                    }
+                    if !path.contains(&next) {
+                        let mut longer = path.clone();
+                        longer.push(next);
+                        paths.push(longer);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), String> {
         let serialized = toml::to_string_pretty(self).map_err(|e| format!("serialize config: {}", e))?;
         let tmp: PathBuf = path.with_extension("toml.tmp");
-        fs::write(&tmp, serialized).map_err(|e| format!("write tmp config: {}", e))?;
-        fs::rename(&tmp, path).map_err(|e| format!("rename tmp config: {}", e))?;
+        tokio::fs::write(&tmp, serialized).await.map_err(|e| format!("write tmp config: {}", e))?;
+        tokio::fs::rename(&tmp, path).await.map_err(|e| format!("rename tmp config: {}", e))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(raw: &str) -> Result<StreamsConfig, String> {
+        let config: StreamsConfig = toml::from_str(raw).map_err(|e| e.to_string())?;
+        config.validate().map(|()| config)
+    }
+
+    #[test]
+    fn accepts_fallback_chains() {
+        config(r#"
+            [[stream]]
+            id = "a"
+            name = "A"
+            playlist = "a"
+            fallback = [{ kind = "stream", id = "b" }, { kind = "silence" }]
+
+            [[stream]]
+            id = "b"
+            name = "B"
+            playlist = "b"
+            fallback = [{ kind = "stream", id = "c" }]
+
+            [[stream]]
+            id = "c"
+            name = "C"
+            playlist = "c"
+        "#).unwrap();
+    }
+
+    #[test]
+    fn rejects_falling_back_to_itself() {
+        let e = config(r#"
+            [[stream]]
+            id = "a"
+            name = "A"
+            playlist = "a"
+            fallback = [{ kind = "stream", id = "a" }]
+        "#).unwrap_err();
+        assert!(e.contains("cannot fall back to itself"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_falling_back_to_its_own_simulcast() {
+        let e = config(r#"
+            [[stream]]
+            id = "a"
+            name = "A"
+            playlist = "a"
+            fallback = [{ kind = "stream", id = "a-low" }]
+
+            [[stream]]
+            id = "a-low"
+            name = "A low"
+            simulcast_of = "a"
+        "#).unwrap_err();
+        assert!(e.contains("a -> a"), "{}", e);
+    }
+
+    #[test]
+    fn rejects_fallback_loops() {
+        let e = config(r#"
+            [[stream]]
+            id = "a"
+            name = "A"
+            playlist = "a"
+            fallback = [{ kind = "stream", id = "b" }]
+
+            [[stream]]
+            id = "b"
+            name = "B"
+            playlist = "b"
+            fallback = [{ kind = "stream", id = "a" }]
+        "#).unwrap_err();
+        assert!(e.contains("loop"), "{}", e);
+
+        let e = config(r#"
+            [[stream]]
+            id = "a"
+            name = "A"
+            playlist = "a"
+            fallback = [{ kind = "stream", id = "b-low" }]
+
+            [[stream]]
+            id = "b"
+            name = "B"
+            playlist = "b"
+            fallback = [{ kind = "silence" }, { kind = "stream", id = "c" }]
+
+            [[stream]]
+            id = "b-low"
+            name = "B low"
+            simulcast_of = "b"
+
+            [[stream]]
+            id = "c"
+            name = "C"
+            playlist = "c"
+            fallback = [{ kind = "stream", id = "a" }]
+        "#).unwrap_err();
+        assert!(e.contains("a -> b -> c -> a"), "{}", e);
+    }
+}
@@ -0,0 +1,567 @@
+use std::{path::Path, sync::Arc};
+
+use tokio::{fs, sync::RwLock};
+use tracing::error;
+
+use crate::{
+    AppState, StreamEntry,
+    api::{
+        ApiTokenInfo, AuditAction, CreateListenerRequest, CreateListenerTokenRequest, CreateListenerTokenResponse, CreateTokenRequest,
+        CreateTokenResponse, CreateUserRequest, ErrorCode, ListenerInfo, RecoveryCodesResponse, Role, SessionInfo,
+        SignedUrlResponse, StreamEvent, StreamStats, TotpSetupResponse, UpdateUserRequest, UserInfo,
+    },
+    audit::Actor,
+    auth::random_hex,
+    config::StreamsConfig,
+    listeners::{Listener, ListenerToken, LISTENER_TOKEN_PREFIX},
+    stats::now_ms,
+    tokens::{self, ApiToken, TOKEN_PREFIX},
+    totp::{Totp, Used},
+    users::{self, Permission, Scope, User},
+    ws_server::{broadcast_metadata, broadcast_paused},
+};
+
+// Admin actions shared by the HTTP API and admin WebSocket commands.
+
+#[derive(Debug)]
+pub struct ControlError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ControlError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    fn internal(message: String) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl From<String> for ControlError {
+    fn from(message: String) -> Self {
+        Self::internal(message)
+    }
+}
+
+async fn get_entry(app: &AppState, stream_id: &str) -> Result<Arc<RwLock<StreamEntry>>, ControlError> {
+    app.registry.read().await.get(stream_id).cloned()
+        .ok_or_else(|| ControlError::new(ErrorCode::UnknownStream, format!("unknown stream: {}", stream_id)))
+}
+
+/// The entry owning the stream's player and queue, which for a simulcast is
+/// its source stream. `user` needs `permission` on both, or access to a
+/// simulcast would control its source and every other simulcast of it.
+async fn get_controlled_entry(app: &AppState, user: &User, permission: Permission, stream_id: &str) -> Result<Arc<RwLock<StreamEntry>>, ControlError> {
+    let source_id = get_entry(app, stream_id).await?.read().await.config.source_id().to_string();
+    if let Some(denied) = [stream_id, &source_id].into_iter().find(|id| !user.can(permission, Scope::Stream(id))) {
+        return Err(ControlError::new(ErrorCode::Forbidden, format!("no access to stream '{}'", denied)));
+    }
+    get_entry(app, &source_id).await
+}
+
+pub async fn skip(app: &AppState, actor: &Actor, user: &User, stream_id: &str) -> Result<(), ControlError> {
+    let player = get_controlled_entry(app, user, Permission::Control, stream_id).await?.read().await.player.clone();
+    let skipped = player.get_metadata().await.ok().flatten().map(|m| m.title);
+    player.skip().await.map_err(ControlError::internal)?;
+    app.audit.record(actor.entry(AuditAction::Skip).stream(stream_id).before(skipped));
+    Ok(())
+}
+
+pub async fn pause(app: &AppState, actor: &Actor, user: &User, stream_id: &str) -> Result<(), ControlError> {
+    let player = get_controlled_entry(app, user, Permission::Control, stream_id).await?.read().await.player.clone();
+    let was_paused = player.is_paused().await.unwrap_or(false);
+    player.pause().await.map_err(ControlError::internal)?;
+    // Broadcast updated metadata so listeners see the paused flag.
+    broadcast_paused(&app.registry, stream_id).await;
+    app.audit.record(actor.entry(AuditAction::Pause).stream(stream_id).before(was_paused).after(true));
+    Ok(())
+}
+
+pub async fn resume(app: &AppState, actor: &Actor, user: &User, stream_id: &str) -> Result<(), ControlError> {
+    let player = get_controlled_entry(app, user, Permission::Control, stream_id).await?.read().await.player.clone();
+    let was_paused = player.is_paused().await.unwrap_or(false);
+    player.resume().await.map_err(ControlError::internal)?;
+    broadcast_paused(&app.registry, stream_id).await;
+    app.audit.record(actor.entry(AuditAction::Resume).stream(stream_id).before(was_paused).after(false));
+    Ok(())
+}
+
+/// Returns the position actually landed on.
+pub async fn seek(app: &AppState, actor: &Actor, user: &User, stream_id: &str, position_ms: u64) -> Result<u64, ControlError> {
+    let player = get_controlled_entry(app, user, Permission::Control, stream_id).await?.read().await.player.clone();
+    let landed_ms = player.seek(position_ms).await
+        .map_err(|e| ControlError::new(ErrorCode::Conflict, e))?;
+    // Fresh timing metadata keeps listeners' progress display in sync.
+    broadcast_metadata(&app.registry, stream_id).await;
+    app.audit.record(actor.entry(AuditAction::Seek).stream(stream_id).after(landed_ms));
+    Ok(landed_ms)
+}
+
+/// Renames the stream and persists the change to the config file.
+pub async fn rename(app: &AppState, actor: &Actor, stream_id: &str, name: String) -> Result<(), ControlError> {
+    if name.trim().is_empty() {
+        return Err(ControlError::new(ErrorCode::InvalidBody, "name required"));
+    }
+
+    let entry_arc = get_entry(app, stream_id).await?;
+    let previous = {
+        let mut entry = entry_arc.write().await;
+        let previous = std::mem::replace(&mut entry.config.name, name.clone());
+        entry.events_tx.send(StreamEvent::Renamed { stream: stream_id.to_string(), name: name.clone() });
+        previous
+    };
+    app.audit.record(actor.entry(AuditAction::Rename).stream(stream_id).before(previous).after(name));
+
+    // Persist to disk: snapshot the registry into a fresh StreamsConfig and save.
+    if let Err(e) = persist_config(app).await {
+        error!(stream_id, "Failed to persist config after rename: {}", e);
+    }
+    Ok(())
+}
+
+pub async fn stats(app: &AppState, stream_id: &str) -> Result<StreamStats, ControlError> {
+    let entry = get_entry(app, stream_id).await?;
+    let audience = entry.read().await.audience.clone();
+    Ok(audience.snapshot(stream_id))
+}
+
+pub async fn get_queue(app: &AppState, user: &User, stream_id: &str) -> Result<Vec<String>, ControlError> {
+    let entry = get_controlled_entry(app, user, Permission::View, stream_id).await?;
+    let queue = entry.read().await.queue.iter().cloned().collect();
+    Ok(queue)
+}
+
+/// Inserts `path` at `index` in the up-next queue, or appends it. The file
+/// must be in the stream's playlist folder.
+pub async fn queue_add(app: &AppState, actor: &Actor, user: &User, stream_id: &str, path: String, index: Option<usize>) -> Result<Vec<String>, ControlError> {
+    let entry = get_controlled_entry(app, user, Permission::Control, stream_id).await?;
+    let playlist = entry.read().await.config.playlist.clone();
+    let path = playlist_file(&playlist, &path).await?;
+
+    let mut entry = entry.write().await;
+    let before: Vec<String> = entry.queue.iter().cloned().collect();
+    let index = index.unwrap_or(entry.queue.len()).min(entry.queue.len());
+    entry.queue.insert(index, path);
+    let after: Vec<String> = entry.queue.iter().cloned().collect();
+    app.audit.record(actor.entry(AuditAction::QueueAdd).stream(stream_id).before(before).after(&after));
+    Ok(after)
+}
+
+/// `path`, relative to the playlist folder `playlist` or absolute, if it is
+/// a file inside that folder. Links and `..` are resolved first, so it
+/// can't point anywhere else, and files outside get the same error as
+/// missing ones.
+pub async fn playlist_file(playlist: &str, path: &str) -> Result<String, ControlError> {
+    let not_found = || ControlError::new(ErrorCode::FileNotFound, format!("no such file in the playlist folder: {}", path));
+    let root = fs::canonicalize(playlist).await.map_err(|_| not_found())?;
+    let file = fs::canonicalize(Path::new(playlist).join(path)).await.map_err(|_| not_found())?;
+    let is_file = fs::metadata(&file).await.is_ok_and(|m| m.is_file());
+    if !is_file || !file.starts_with(&root) {
+        return Err(not_found());
+    }
+    Ok(file.to_string_lossy().into_owned())
+}
+
+pub async fn queue_remove(app: &AppState, actor: &Actor, user: &User, stream_id: &str, index: usize) -> Result<Vec<String>, ControlError> {
+    let entry = get_controlled_entry(app, user, Permission::Control, stream_id).await?;
+    let mut entry = entry.write().await;
+    let before: Vec<String> = entry.queue.iter().cloned().collect();
+    if entry.queue.remove(index).is_none() {
+        return Err(ControlError::new(ErrorCode::NotFound, format!("no queue item at index {}", index)));
+    }
+    let after: Vec<String> = entry.queue.iter().cloned().collect();
+    app.audit.record(actor.entry(AuditAction::QueueRemove).stream(stream_id).before(before).after(&after));
+    Ok(after)
+}
+
+pub async fn queue_clear(app: &AppState, actor: &Actor, user: &User, stream_id: &str) -> Result<(), ControlError> {
+    let entry = get_controlled_entry(app, user, Permission::Control, stream_id).await?;
+    let before: Vec<String> = entry.write().await.queue.drain(..).collect();
+    app.audit.record(actor.entry(AuditAction::QueueClear).stream(stream_id).before(before).after(Vec::<String>::new()));
+    Ok(())
+}
+
+async fn registry_config(app: &AppState) -> StreamsConfig {
+    let registry = app.registry.read().await;
+    let mut streams = Vec::with_capacity(registry.len());
+    for (_, entry_arc) in registry.iter() {
+        let entry = entry_arc.read().await;
+        streams.push(entry.config.clone());
+    }
+    drop(registry);
+    StreamsConfig {
+        default_stream: app.default_stream.read().await.clone(),
+        streams,
+    }
+}
+
+pub async fn persist_config(app: &AppState) -> Result<(), String> {
+    registry_config(app).await.save(&app.config_path).await
+}
+
+/// Like `persist_config`, but leaves the file, and any comments in it, alone
+/// when it already matches the running config. Returns whether it wrote.
+pub async fn persist_config_if_changed(app: &AppState) -> Result<bool, String> {
+    let normalized = |mut cfg: StreamsConfig| {
+        cfg.streams.sort_by(|a, b| a.id.cmp(&b.id));
+        toml::to_string(&cfg).ok()
+    };
+    let running = normalized(registry_config(app).await);
+    let on_disk = StreamsConfig::load(&app.config_path).ok().and_then(normalized);
+    if running.is_some() && running == on_disk {
+        return Ok(false);
+    }
+    persist_config(app).await.map(|()| true)
+}
+
+fn unknown_user(username: &str) -> ControlError {
+    ControlError::new(ErrorCode::NotFound, format!("unknown user: {}", username))
+}
+
+async fn hash_password(password: String) -> Result<String, ControlError> {
+    users::validate_password(&password).map_err(|e| ControlError::new(ErrorCode::InvalidBody, e))?;
+    tokio::task::spawn_blocking(move || users::hash_password(&password))
+        .await
+        .map_err(|e| ControlError::internal(e.to_string()))?
+        .map_err(ControlError::internal)
+}
+
+/// Tokens last at most about ten years.
+const MAX_TOKEN_DAYS: u64 = 3650;
+/// Signed URLs last at most a year.
+const MAX_SIGNED_URL_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// When a token made at `created_at_ms` that lasts `days` runs out.
+fn token_expiry(created_at_ms: u64, days: Option<u64>) -> Result<Option<u64>, ControlError> {
+    let Some(days) = days else {
+        return Ok(None);
+    };
+    if days == 0 || days > MAX_TOKEN_DAYS {
+        return Err(ControlError::new(ErrorCode::InvalidBody, format!("expires_in_days must be 1-{}", MAX_TOKEN_DAYS)));
+    }
+    Ok(Some(created_at_ms + days * 24 * 60 * 60 * 1000))
+}
+
+/// Owners always have every stream; anyone else may only be granted streams
+/// that exist.
+async fn stream_grants(app: &AppState, role: Role, streams: Option<Vec<String>>) -> Result<Option<Vec<String>>, ControlError> {
+    known_streams(app, streams.filter(|_| role != Role::Owner)).await
+}
+
+/// The streams sorted and deduplicated, if they all exist.
+async fn known_streams(app: &AppState, streams: Option<Vec<String>>) -> Result<Option<Vec<String>>, ControlError> {
+    let Some(mut streams) = streams else {
+        return Ok(None);
+    };
+    streams.sort();
+    streams.dedup();
+    let registry = app.registry.read().await;
+    if let Some(unknown) = streams.iter().find(|id| !registry.contains_key(*id)) {
+        return Err(ControlError::new(ErrorCode::UnknownStream, format!("unknown stream: {}", unknown)));
+    }
+    Ok(Some(streams))
+}
+
+fn last_owner() -> ControlError {
+    ControlError::new(ErrorCode::Conflict, "at least one owner must remain")
+}
+
+pub async fn create_user(app: &AppState, request: CreateUserRequest) -> Result<UserInfo, ControlError> {
+    users::validate_username(&request.username).map_err(|e| ControlError::new(ErrorCode::InvalidBody, e))?;
+    let streams = stream_grants(app, request.role, request.streams).await?;
+    let user = User {
+        username: request.username,
+        password_hash: hash_password(request.password).await?,
+        role: request.role,
+        streams,
+        totp: None,
+    };
+    let info = user.info();
+    app.auth.users.update(|all| {
+        if all.contains_key(&user.username) {
+            return Err(ControlError::new(ErrorCode::Conflict, format!("user '{}' already exists", user.username)));
+        }
+        all.insert(user.username.clone(), user);
+        Ok(())
+    })?;
+    Ok(info)
+}
+
+pub async fn update_user(app: &AppState, username: &str, request: UpdateUserRequest) -> Result<UserInfo, ControlError> {
+    let streams = stream_grants(app, request.role, request.streams).await?;
+    app.auth.users.update(|all| {
+        let user = all.get_mut(username).ok_or_else(|| unknown_user(username))?;
+        user.role = request.role;
+        user.streams = streams;
+        let info = user.info();
+        if !users::has_owner(all) {
+            return Err(last_owner());
+        }
+        Ok(info)
+    })
+}
+
+pub fn delete_user(app: &AppState, username: &str) -> Result<(), ControlError> {
+    app.auth.users.update(|all| {
+        all.remove(username).ok_or_else(|| unknown_user(username))?;
+        if !users::has_owner(all) {
+            return Err(last_owner());
+        }
+        Ok(())
+    })?;
+    app.auth.revoke_user(username)?;
+    app.auth.tokens.remove_user(username)?;
+    Ok(())
+}
+
+/// Sets a new password and signs the user out everywhere.
+pub async fn reset_password(app: &AppState, username: &str, password: String) -> Result<(), ControlError> {
+    if app.auth.users.get(username).is_none() {
+        return Err(unknown_user(username));
+    }
+    let hash = hash_password(password).await?;
+    app.auth.users.update(|all| {
+        all.get_mut(username).ok_or_else(|| unknown_user(username))?.password_hash = hash;
+        Ok::<_, ControlError>(())
+    })?;
+    app.auth.revoke_user(username)?;
+    Ok(())
+}
+
+fn invalid_totp() -> ControlError {
+    ControlError::new(ErrorCode::InvalidTotp, "invalid or already used code")
+}
+
+/// Starts two-factor enrollment with a fresh secret, replacing one that was
+/// never confirmed. Logins don't need codes until `confirm_totp`.
+pub fn begin_totp(app: &AppState, username: &str) -> Result<TotpSetupResponse, ControlError> {
+    app.auth.users.update(|all| {
+        let user = all.get_mut(username).ok_or_else(|| unknown_user(username))?;
+        if user.has_totp() {
+            return Err(ControlError::new(ErrorCode::Conflict, "two-factor login is already on; turn it off first"));
+        }
+        let totp = Totp::generate();
+        let setup = TotpSetupResponse { secret: totp.secret.clone(), uri: totp.provisioning_uri(username) };
+        user.totp = Some(totp);
+        Ok(setup)
+    })
+}
+
+/// Turns two-factor login on once a code shows the app has the secret, and
+/// returns the recovery codes.
+pub fn confirm_totp(app: &AppState, username: &str, code: &str) -> Result<RecoveryCodesResponse, ControlError> {
+    app.auth.users.update(|all| {
+        let user = all.get_mut(username).ok_or_else(|| unknown_user(username))?;
+        let totp = user.totp.as_mut()
+            .filter(|t| !t.confirmed)
+            .ok_or_else(|| ControlError::new(ErrorCode::Conflict, "no two-factor setup in progress"))?;
+        if !totp.verify_code(code, now_ms() / 1000) {
+            return Err(invalid_totp());
+        }
+        totp.confirmed = true;
+        Ok(RecoveryCodesResponse { recovery_codes: totp.new_recovery_codes() })
+    })
+}
+
+/// Checks a login's second factor. Recovery codes are spent, and TOTP codes
+/// can't be used twice.
+pub fn verify_totp(app: &AppState, username: &str, code: &str) -> Result<Used, ControlError> {
+    app.auth.users.update(|all| {
+        let totp = all.get_mut(username)
+            .and_then(|u| u.totp.as_mut())
+            .filter(|t| t.confirmed)
+            .ok_or_else(invalid_totp)?;
+        totp.verify(code, now_ms() / 1000).ok_or_else(invalid_totp)
+    })
+}
+
+/// Turns two-factor login off, given a current TOTP or recovery code. An
+/// unconfirmed setup is dropped whatever the code.
+pub fn disable_totp(app: &AppState, username: &str, code: &str) -> Result<(), ControlError> {
+    app.auth.users.update(|all| {
+        let user = all.get_mut(username).ok_or_else(|| unknown_user(username))?;
+        match user.totp.as_mut() {
+            None => return Err(ControlError::new(ErrorCode::Conflict, "two-factor login is off")),
+            Some(totp) if totp.confirmed => {
+                totp.verify(code, now_ms() / 1000).ok_or_else(invalid_totp)?;
+            },
+            Some(_) => {},
+        }
+        user.totp = None;
+        Ok(())
+    })
+}
+
+/// Turns another user's two-factor login off, e.g. after they lost their
+/// authenticator and recovery codes.
+pub fn reset_totp(app: &AppState, username: &str) -> Result<(), ControlError> {
+    app.auth.users.update(|all| {
+        all.get_mut(username).ok_or_else(|| unknown_user(username))?.totp = None;
+        Ok(())
+    })
+}
+
+/// The caller's own tokens, or everyone's for users who manage users.
+pub fn list_tokens(app: &AppState, user: &User) -> Vec<ApiTokenInfo> {
+    let all = user.can(Permission::ManageUsers, Scope::AllStreams);
+    app.auth.tokens.list(|t| all || t.username == user.username)
+}
+
+/// A token acting as `user`, with at most their role and streams. The
+/// secret is only returned here.
+pub async fn create_token(app: &AppState, user: &User, request: CreateTokenRequest) -> Result<CreateTokenResponse, ControlError> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(ControlError::new(ErrorCode::InvalidBody, "name must be 1-64 characters"));
+    }
+    let role = request.role.unwrap_or(user.role);
+    if role > user.role {
+        return Err(ControlError::new(ErrorCode::Forbidden, "a token can't have a higher role than its user"));
+    }
+    if role == Role::Owner && request.streams.is_some() {
+        return Err(ControlError::new(ErrorCode::InvalidBody, "owner tokens always have every stream; pick a lower role"));
+    }
+    let created_at_ms = now_ms();
+    let expires_at_ms = token_expiry(created_at_ms, request.expires_in_days)?;
+    let streams = stream_grants(app, role, request.streams).await?;
+    if let Some(denied) = streams.iter().flatten().find(|id| !user.can_access(id)) {
+        return Err(ControlError::new(ErrorCode::Forbidden, format!("no access to stream '{}'", denied)));
+    }
+
+    let (id, secret) = (random_hex(6), random_hex(32));
+    let token = ApiToken {
+        id: id.clone(),
+        name,
+        username: user.username.clone(),
+        secret_hash: tokens::hash_secret(&secret),
+        role,
+        streams,
+        created_at_ms,
+        expires_at_ms,
+        last_used_ms: None,
+    };
+    let info = token.info();
+    app.auth.tokens.insert(token)?;
+    Ok(CreateTokenResponse { token: format!("{}{}_{}", TOKEN_PREFIX, id, secret), info })
+}
+
+/// Users can revoke their own tokens; users who manage users, anyone's.
+pub fn revoke_token(app: &AppState, user: &User, id: &str) -> Result<ApiTokenInfo, ControlError> {
+    let token = app.auth.tokens.get(id)
+        .filter(|t| t.username == user.username || user.can(Permission::ManageUsers, Scope::AllStreams))
+        .ok_or_else(|| ControlError::new(ErrorCode::NotFound, format!("unknown token: {}", id)))?;
+    app.auth.tokens.remove(id)?;
+    Ok(token.info())
+}
+
+/// The caller's own sessions, or everyone's for users who manage users.
+/// `current` is the caller's cookie value, to mark their own session.
+pub fn list_sessions(app: &AppState, user: &User, current: Option<&str>) -> Vec<SessionInfo> {
+    let all = user.can(Permission::ManageUsers, Scope::AllStreams);
+    app.auth.sessions.list(current, now_ms(), |s| all || s.username == user.username)
+}
+
+/// Users can sign out their own sessions; users who manage users, anyone's.
+pub fn revoke_session(app: &AppState, user: &User, id: &str) -> Result<SessionInfo, ControlError> {
+    let session = app.auth.sessions.get(id)
+        .filter(|s| s.username == user.username || user.can(Permission::ManageUsers, Scope::AllStreams))
+        .ok_or_else(|| ControlError::new(ErrorCode::NotFound, format!("unknown session: {}", id)))?;
+    app.auth.sessions.revoke_id(id)?;
+    Ok(session.info(false))
+}
+
+/// Adds a listener for private streams, or replaces one with the same name.
+pub async fn create_listener(app: &AppState, request: CreateListenerRequest) -> Result<ListenerInfo, ControlError> {
+    users::validate_username(&request.username).map_err(|e| ControlError::new(ErrorCode::InvalidBody, e))?;
+    let listener = Listener {
+        username: request.username,
+        password_hash: hash_password(request.password).await?,
+        streams: known_streams(app, request.streams).await?,
+    };
+    let info = listener.info();
+    app.auth.listeners.insert_listener(listener)?;
+    Ok(info)
+}
+
+pub fn delete_listener(app: &AppState, username: &str) -> Result<(), ControlError> {
+    match app.auth.listeners.remove_listener(username)? {
+        true => Ok(()),
+        false => Err(ControlError::new(ErrorCode::NotFound, format!("unknown listener: {}", username))),
+    }
+}
+
+/// A token for private streams. The secret is only returned here.
+pub async fn create_listener_token(app: &AppState, request: CreateListenerTokenRequest) -> Result<CreateListenerTokenResponse, ControlError> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(ControlError::new(ErrorCode::InvalidBody, "name must be 1-64 characters"));
+    }
+    let created_at_ms = now_ms();
+    let expires_at_ms = token_expiry(created_at_ms, request.expires_in_days)?;
+    let (id, secret) = (random_hex(6), random_hex(32));
+    let token = ListenerToken {
+        id: id.clone(),
+        name,
+        secret_hash: tokens::hash_secret(&secret),
+        streams: known_streams(app, request.streams).await?,
+        created_at_ms,
+        expires_at_ms,
+    };
+    let info = token.info();
+    app.auth.listeners.insert_token(token)?;
+    Ok(CreateListenerTokenResponse { token: format!("{}{}_{}", LISTENER_TOKEN_PREFIX, id, secret), info })
+}
+
+pub fn revoke_listener_token(app: &AppState, id: &str) -> Result<(), ControlError> {
+    match app.auth.listeners.remove_token(id)? {
+        true => Ok(()),
+        false => Err(ControlError::new(ErrorCode::NotFound, format!("unknown listener token: {}", id))),
+    }
+}
+
+/// A link to the stream's audio that works without credentials until it
+/// expires, a day from now by default.
+pub async fn signed_url(app: &AppState, stream_id: &str, expires_in_secs: Option<u64>) -> Result<SignedUrlResponse, ControlError> {
+    get_entry(app, stream_id).await?;
+    let expires_in_secs = expires_in_secs.unwrap_or(24 * 60 * 60);
+    if expires_in_secs == 0 || expires_in_secs > MAX_SIGNED_URL_SECS {
+        return Err(ControlError::new(ErrorCode::InvalidBody, format!("expires_in_secs must be 1-{}", MAX_SIGNED_URL_SECS)));
+    }
+    let expires = now_ms() / 1000 + expires_in_secs;
+    let query = app.auth.listeners.sign(stream_id, expires)?;
+    Ok(SignedUrlResponse {
+        url: format!("/streams/{}/audio?{}", stream_id, query),
+        expires_at_ms: expires * 1000,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn playlist_files_stay_inside_the_folder() {
+        let dir = std::env::temp_dir().join(format!("octopus-playlist-{}", random_hex(6)));
+        let playlist = dir.join("playlist");
+        fs::create_dir_all(playlist.join("sub")).await.unwrap();
+        fs::write(playlist.join("a.opus"), b"").await.unwrap();
+        fs::write(playlist.join("sub/b.opus"), b"").await.unwrap();
+        fs::write(dir.join("secret.opus"), b"").await.unwrap();
+        let root = playlist.to_str().unwrap();
+        let canonical = fs::canonicalize(&playlist).await.unwrap();
+
+        let a = playlist_file(root, "a.opus").await.unwrap();
+        assert_eq!(Path::new(&a), canonical.join("a.opus"));
+        assert!(playlist_file(root, "sub/b.opus").await.is_ok());
+        assert!(playlist_file(root, playlist.join("a.opus").to_str().unwrap()).await.is_ok());
+
+        for outside in ["../secret.opus", dir.join("secret.opus").to_str().unwrap(), "/etc/passwd", "missing.opus", "sub", ""] {
+            let e = playlist_file(root, outside).await.unwrap_err();
+            assert_eq!(e.code, ErrorCode::FileNotFound, "{}", outside);
+        }
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
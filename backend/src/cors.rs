@@ -0,0 +1,131 @@
+use std::env;
+
+use hyper::{header, Request};
+use tracing::info;
+
+/// Methods preflights allow; every route's are among them.
+pub const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
+pub const ALLOWED_HEADERS: &str = "Content-Type, Authorization, Last-Event-ID";
+/// How long browsers may cache a preflight, in seconds.
+pub const PREFLIGHT_MAX_AGE: u32 = 600;
+
+/// Browser origins, besides the server's own, that may send the session
+/// cookie and read credentialed responses, from `CORS_ALLOWED_ORIGINS`.
+/// Any other origin gets `Access-Control-Allow-Origin: *`, which is enough
+/// for the public endpoints but never carries cookies, and is refused on
+/// state-changing admin requests.
+#[derive(Debug, Default)]
+pub struct CorsPolicy(Vec<String>);
+
+impl CorsPolicy {
+    /// A comma-separated list of origins, e.g.
+    /// `https://radio.example.com, http://localhost:5173`.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|origin| {
+                let normalized = origin.trim_end_matches('/').to_ascii_lowercase();
+                let (scheme, host) = normalized.split_once("://").ok_or_else(|| format!("'{}' has no scheme", origin))?;
+                if !matches!(scheme, "http" | "https") || host.is_empty() || host.contains(['/', '*']) {
+                    return Err(format!("'{}' is not an origin like https://example.com", origin));
+                }
+                Ok(normalized)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+
+    pub fn from_env() -> Self {
+        let Ok(list) = env::var("CORS_ALLOWED_ORIGINS") else {
+            return Self::default();
+        };
+        let policy = Self::parse(&list).unwrap_or_else(|e| panic!("Invalid CORS_ALLOWED_ORIGINS: {}", e));
+        if !policy.0.is_empty() {
+            info!("Allowing credentialed requests from {}", policy.0.join(", "));
+        }
+        policy
+    }
+
+    /// Whether `origin` is listed, or is the server's own as named by the
+    /// request's `Host`.
+    pub fn allows(&self, origin: &str, host: Option<&str>) -> bool {
+        let origin = origin.trim_end_matches('/').to_ascii_lowercase();
+        let own = origin.split_once("://")
+            .is_some_and(|(_, authority)| host.is_some_and(|h| h.eq_ignore_ascii_case(authority)));
+        own || self.0.contains(&origin)
+    }
+
+    /// Whether the request's `Origin`, if any, is allowed. Requests without
+    /// one don't come from a browser on another site, e.g. scripts or
+    /// same-origin navigation.
+    pub fn allows_request<B>(&self, req: &Request<B>) -> bool {
+        match origin(req) {
+            Some(origin) => self.allows(origin, req.headers().get(header::HOST).and_then(|v| v.to_str().ok())),
+            None => true,
+        }
+    }
+}
+
+pub fn origin<B>(req: &Request<B>) -> Option<&str> {
+    req.headers().get(header::ORIGIN).and_then(|v| v.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(origin: Option<&str>, host: &str) -> Request<()> {
+        let mut builder = Request::builder().uri("/admin/streams").header(header::HOST, host);
+        if let Some(origin) = origin {
+            builder = builder.header(header::ORIGIN, origin);
+        }
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn parses_origin_lists() {
+        let policy = CorsPolicy::parse(" https://Radio.example.com/ , http://localhost:5173,, ").unwrap();
+        assert_eq!(policy.0, ["https://radio.example.com", "http://localhost:5173"]);
+        assert!(CorsPolicy::parse("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn rejects_things_that_are_not_origins() {
+        assert!(CorsPolicy::parse("radio.example.com").is_err());
+        assert!(CorsPolicy::parse("ftp://radio.example.com").is_err());
+        assert!(CorsPolicy::parse("https://").is_err());
+        assert!(CorsPolicy::parse("https://*.example.com").is_err());
+        assert!(CorsPolicy::parse("https://example.com/admin").is_err());
+        assert!(CorsPolicy::parse("https://ok.example.com, nope").is_err());
+    }
+
+    #[test]
+    fn allows_listed_origins_and_the_servers_own() {
+        let policy = CorsPolicy::parse("https://radio.example.com").unwrap();
+        assert!(policy.allows("https://radio.example.com", None));
+        assert!(policy.allows("HTTPS://RADIO.EXAMPLE.COM/", None));
+        assert!(policy.allows("http://octopus.local:8080", Some("octopus.local:8080")));
+        assert!(CorsPolicy::default().allows("https://octopus.local", Some("OCTOPUS.local")));
+    }
+
+    #[test]
+    fn denies_other_origins() {
+        let policy = CorsPolicy::parse("https://radio.example.com").unwrap();
+        assert!(!policy.allows("https://evil.example.com", Some("octopus.local")));
+        assert!(!policy.allows("http://radio.example.com", None));
+        assert!(!policy.allows("https://radio.example.com.evil.net", None));
+        assert!(!policy.allows("https://octopus.local:9999", Some("octopus.local:8080")));
+        assert!(!policy.allows("null", Some("octopus.local")));
+        assert!(!CorsPolicy::default().allows("https://radio.example.com", None));
+    }
+
+    #[test]
+    fn requests_without_an_origin_are_allowed() {
+        let policy = CorsPolicy::parse("https://radio.example.com").unwrap();
+        assert!(policy.allows_request(&request(None, "octopus.local")));
+        assert!(policy.allows_request(&request(Some("https://radio.example.com"), "octopus.local")));
+        assert!(policy.allows_request(&request(Some("https://octopus.local"), "octopus.local")));
+        assert!(!policy.allows_request(&request(Some("https://evil.example.com"), "octopus.local")));
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+
+use serde::{Serialize, Serializer};
+use tokio::sync::broadcast;
+
+use crate::api::StreamEvent;
+
+/// A stream event numbered in the order its stream emitted it. Ids start
+/// over when the server restarts.
+#[derive(Debug, Clone)]
+pub struct SequencedEvent {
+    pub id: u64,
+    pub event: StreamEvent,
+}
+
+// WebSocket clients get the bare event; only the SSE feed exposes ids.
+impl Serialize for SequencedEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.event.serialize(serializer)
+    }
+}
+
+/// Broadcasts a stream's events, numbering each one.
+#[derive(Debug, Clone)]
+pub struct StreamEvents {
+    tx: broadcast::Sender<SequencedEvent>,
+    last_id: Arc<AtomicU64>,
+}
+
+impl StreamEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx, last_id: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn send(&self, event: StreamEvent) {
+        let id = self.last_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = self.tx.send(SequencedEvent { id, event });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Id of the most recent event, or 0 if none was sent yet.
+    pub fn last_id(&self) -> u64 {
+        self.last_id.load(Ordering::SeqCst)
+    }
+}
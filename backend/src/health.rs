@@ -0,0 +1,89 @@
+use std::{
+    sync::atomic::Ordering,
+    time::Duration,
+};
+
+use tokio::{fs, time::timeout};
+
+use crate::{
+    AppState,
+    api::{HealthResponse, StreamHealth},
+    config::StreamConfig,
+    stats::now_ms,
+};
+
+/// How long a player gets to answer before it counts as unresponsive.
+const PLAYER_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long an unpaused stream may go without producing audio.
+const STALL_AFTER: Duration = Duration::from_secs(15);
+
+/// Which endpoint is asking: liveness only fails on a dead player, readiness
+/// also on a stalled stream or a stopped playlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Live,
+    Ready,
+}
+
+async fn config_writable(app: &AppState) -> bool {
+    // Opening for writing without truncating leaves the file untouched.
+    fs::OpenOptions::new().write(true).open(&app.config_path).await.is_ok()
+}
+
+/// Every stream counts towards `ok`, but only those `show` accepts are
+/// listed.
+pub async fn report(app: &AppState, check: Check, show: impl Fn(&StreamConfig) -> bool) -> HealthResponse {
+    let now = now_ms();
+
+    let entries: Vec<_> = app.registry.read().await.iter()
+        .map(|(id, entry)| (id.clone(), entry.clone()))
+        .collect();
+
+    let mut all_ok = true;
+    let mut streams = Vec::with_capacity(entries.len());
+    for (id, entry_arc) in entries {
+        let (player, playlist_running, shown) = {
+            let e = entry_arc.read().await;
+            let running = match &e.task {
+                Some(task) => Some(!task.is_finished()),
+                None if e.config.simulcast_of.is_some() => None,
+                None => Some(false),
+            };
+            (e.player.clone(), running, show(&e.config))
+        };
+
+        let paused = timeout(PLAYER_TIMEOUT, player.is_paused()).await;
+        let player_responsive = matches!(paused, Ok(Ok(_)));
+        let paused = matches!(paused, Ok(Ok(true)));
+
+        let last_packet_at_ms = Some(player.metrics.last_packet_at_ms.load(Ordering::Relaxed)).filter(|ms| *ms > 0);
+        let stalled = !paused && last_packet_at_ms
+            .is_none_or(|at| now.saturating_sub(at) > STALL_AFTER.as_millis() as u64);
+
+        let ok = match check {
+            Check::Live => player_responsive,
+            Check::Ready => player_responsive && !stalled && playlist_running != Some(false),
+        };
+
+        all_ok &= ok;
+        if !shown {
+            continue;
+        }
+        streams.push(StreamHealth {
+            stream: id,
+            player_responsive,
+            last_packet_at_ms,
+            playlist_running,
+            paused,
+            stalled,
+            ok,
+        });
+    }
+    streams.sort_by(|a, b| a.stream.cmp(&b.stream));
+
+    HealthResponse {
+        ok: all_ok,
+        config_writable: config_writable(app).await,
+        streams,
+    }
+}
@@ -1,21 +1,113 @@
-use std::{convert::Infallible, io::{Cursor, Read, Seek, SeekFrom}, net::SocketAddr, path::Path, sync::Arc};
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, StreamBody};
+use std::{
+    convert::Infallible,
+    io::{Cursor, Read, Seek, SeekFrom},
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, LengthLimitError, Limited, StreamBody};
 use hyper::{body::{self, Bytes, Frame}, header::{self, HeaderValue}, server::conn::http1, service::service_fn, Method, Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioIo, TokioTimer};
 use ogg::{PacketWriteEndInfo, PacketWriter};
-use tokio::{fs, net::TcpListener, sync::mpsc};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{fs, net::TcpListener, sync::{broadcast::{self, error::RecvError}, mpsc}};
 use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role},
+    WebSocketStream,
+};
+use tracing::{debug, info, warn};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+    Modify, OpenApi,
+};
 
 use crate::{
-    AppState,
-    auth::{cookie_clear_value, cookie_header_value, extract_token},
-    opus_player::{OPUS_COMMENTS, OPUS_HEAD, OpusPlayerEvent, OpusPlayerHandle},
+    AppState, StreamEntry,
+    api::{
+        AdminStreamSummary, AuditAction, AuditEntry, ErrorCode, ErrorResponse, HealthResponse, LoginRequest, OkResponse, OverrideRequest,
+        OverrideResponse, QueueAddRequest, QueueResponse, ReloadReport, ReloadTrigger, RenameRequest, SeekRequest, SeekResponse, StreamEvent,
+        StreamStats, StreamSummary, TrackMetadata, AdminEvent, ClientMessage, HistoryEntry, ServerMessage,
+        CreateUserRequest, PasswordRequest, UpdateUserRequest, UserInfo,
+        ApiTokenInfo, CreateTokenRequest, CreateTokenResponse, SessionInfo,
+        RecoveryCodesResponse, TotpCodeRequest, TotpSetupResponse,
+        CreateListenerRequest, CreateListenerTokenRequest, CreateListenerTokenResponse, ListenerInfo, ListenersResponse,
+        SignedUrlRequest, SignedUrlResponse, Visibility,
+    },
+    audit::{Actor, AuditFilter},
+    auth::{COOKIE_NAME, cookie_clear_value, cookie_header_value, extract_token, is_api_token},
+    control::{self, ControlError},
+    config::{SlowListenerPolicy, StreamConfig},
+    cors,
+    events::StreamEvents,
+    logging::{self, AccessEntry, AccessLog},
+    health::{self, Check},
+    limits::ConnectionSlot,
+    listeners::{Denied, ListenerCredentials, BASIC_REALM},
+    metrics::{self, route_label},
+    stats::Audience,
+    reload,
+    proxy::ClientIp,
+    totp::Used,
+    opus_player::{OPUS_COMMENTS, OPUS_HEAD, OpusPlayerEvent, OpusPlayerHandle, PlaybackSource},
+    users::{Permission, Scope, User, DEFAULT_USERNAME},
+    ws_server::{broadcast_metadata, entry_metadata, handle_socket, is_socket_path},
 };
 
+/// How long a listener turned away at a cap is asked to wait.
+const SERVER_FULL_RETRY_SECS: u64 = 30;
+
 pub struct HTTPServerContext {
     pub app: Arc<AppState>,
 }
 
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Octopus", description = "Streaming radio server"),
+    paths(
+        list_streams, stream_audio, stream_events, playlist_image, openapi_json, metrics_text, healthz, readyz,
+        admin_login, admin_logout, admin_list_streams, admin_skip, admin_pause, admin_resume,
+        admin_seek, admin_rename, admin_reload, admin_last_reload, admin_all_stats, admin_stats, admin_get_queue, admin_queue_add, admin_queue_remove, admin_queue_clear,
+        admin_override_start, admin_override_end, admin_audit,
+        admin_me, admin_list_users, admin_create_user, admin_update_user, admin_delete_user, admin_reset_password,
+        admin_totp_begin, admin_totp_confirm, admin_totp_disable, admin_reset_totp,
+        admin_list_listeners, admin_create_listener, admin_delete_listener, admin_create_listener_token,
+        admin_revoke_listener_token, admin_signed_url,
+        admin_list_tokens, admin_create_token, admin_revoke_token, admin_list_sessions, admin_revoke_session,
+    ),
+    // WebSocket messages, which no HTTP path references.
+    components(schemas(TrackMetadata, ClientMessage, ServerMessage, StreamEvent, AdminEvent, HistoryEntry)),
+    modifiers(&AdminSession),
+)]
+struct ApiDoc;
+
+struct AdminSession;
+
+impl Modify for AdminSession {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "admin_session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(COOKIE_NAME))),
+        );
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+
+        // Every admin endpoint takes an API token instead of the cookie,
+        // except the ones that manage tokens and two-factor login.
+        let cookie_only = |path: &str| path.starts_with("/admin/tokens") || path.starts_with("/admin/me/totp");
+        for (_, item) in openapi.paths.paths.iter_mut().filter(|(path, _)| !cookie_only(path)) {
+            let operations = [&mut item.get, &mut item.post, &mut item.put, &mut item.patch, &mut item.delete];
+            for security in operations.into_iter().flatten().filter_map(|op| op.security.as_mut()) {
+                security.push(SecurityRequirement::new("api_token", Vec::<String>::new()));
+            }
+        }
+    }
+}
+
 pub async fn init_http_server(
     port: u16,
     ctx: HTTPServerContext,
@@ -26,33 +118,126 @@ pub async fn init_http_server(
 
     let ctx_arc = Arc::from(ctx);
 
-    println!("Server is up and running on port {}", port);
+    info!("Server is up and running on port {}", port);
+    let shutdown = ctx_arc.app.shutdown.clone();
     loop {
-        let (stream, socket) = listener.accept().await?;
+        let (stream, socket) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.wait() => return Ok(()),
+        };
 
         let io = TokioIo::new(stream);
 
+        // Connections through a trusted proxy all share its address, so
+        // their requests are counted per client in `main_handler` instead.
+        let peer = socket.ip().to_canonical();
+        let slot = if ctx_arc.app.trusted_proxies.contains(peer) {
+            None
+        } else {
+            match ctx_arc.app.limits.connection(peer) {
+                Some(slot) => Some(slot),
+                None => {
+                    debug!("Refusing connection from {}: too many open", peer);
+                    tokio::task::spawn(refuse_connection(io));
+                    continue;
+                },
+            }
+        };
+
         let cloned_ctx = ctx_arc.clone();
+        let connection = cloned_ctx.app.metrics.http_connected();
+        let shutdown = shutdown.clone();
+        let header_timeout = cloned_ctx.app.limits.header_timeout;
 
-        let service = service_fn(move |req| {
+        let service = service_fn(move |mut req: Request<body::Incoming>| {
             let cloned_ctx = cloned_ctx.clone();
-            println!("{} {} from {}", req.method(), req.uri().path(), socket.ip());
+            req.extensions_mut().insert(socket);
             main_handler(cloned_ctx, req)
         });
 
         tokio::task::spawn(async move {
-            if let Err(err) = http1::Builder::new()
+            let _connection = (connection, slot);
+            let conn = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(header_timeout)
                 .serve_connection(io, service)
-                .await
-            {
-                eprintln!("Error serving connection: {:?}", err);
+                .with_upgrades();
+            tokio::pin!(conn);
+            // On shutdown, finish the response in flight and then close,
+            // rather than waiting for the next request on a kept-alive
+            // connection.
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                _ = shutdown.wait() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            };
+            if let Err(err) = result {
+                debug!("Error serving connection from {}: {:?}", socket.ip(), err);
             }
         });
     }
 }
 
+/// Answers every request on a connection over `MAX_CONNECTIONS_PER_IP` with
+/// a 429, then closes it.
+async fn refuse_connection(io: TokioIo<tokio::net::TcpStream>) {
+    let service = service_fn(|_req: Request<body::Incoming>| async {
+        let mut response = too_many_connections();
+        apply_cors_headers(&mut response, None, false);
+        Ok::<_, Infallible>(response)
+    });
+    let _ = http1::Builder::new()
+        .timer(TokioTimer::new())
+        .header_read_timeout(Duration::from_secs(5))
+        .keep_alive(false)
+        .serve_connection(io, service)
+        .await;
+}
+
+/// Counts a connected audio listener for as long as it is alive.
+struct ListenerGuard {
+    stream_id: String,
+    session: u64,
+    audience: Audience,
+    events_tx: StreamEvents,
+    admin_tx: broadcast::Sender<AdminEvent>,
+}
+
+impl ListenerGuard {
+    /// None if the stream already has its `max_listeners`.
+    fn new(entry: &StreamEntry, user_agent: Option<String>, ip: Option<IpAddr>) -> Option<Self> {
+        let stream = entry.config.id.clone();
+        let (session, now) = entry.audience.join(entry.config.max_listeners, user_agent, ip)?;
+        info!(stream_id = %stream, listener_id = session, listeners = now, "Listener connected");
+        entry.events_tx.send(StreamEvent::ListenerCount { stream: stream.clone(), count: now });
+        let _ = entry.admin_tx.send(AdminEvent::ListenerJoined { stream: stream.clone(), count: now });
+        Some(Self {
+            stream_id: stream,
+            session,
+            audience: entry.audience.clone(),
+            events_tx: entry.events_tx.clone(),
+            admin_tx: entry.admin_tx.clone(),
+        })
+    }
+}
+
+impl Drop for ListenerGuard {
+    fn drop(&mut self) {
+        let now = self.audience.leave(self.session);
+        info!(stream_id = %self.stream_id, listener_id = self.session, listeners = now, "Listener disconnected");
+        let stream = self.stream_id.clone();
+        self.events_tx.send(StreamEvent::ListenerCount { stream: stream.clone(), count: now });
+        let _ = self.admin_tx.send(AdminEvent::ListenerLeft { stream, count: now });
+    }
+}
+
 const SERIAL: u32 = 61;
 
+/// How often an idle SSE feed sends a comment.
+const SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+
 struct OggStream<'a> {
     writer: PacketWriter<'a, Cursor<Vec<u8>>>,
     output_buffer: Vec<u8>,
@@ -86,35 +271,84 @@ impl<'a> OggStream<'a> {
 
 async fn main_handler(
     ctx: Arc<HTTPServerContext>,
-    req: Request<body::Incoming>
+    mut req: Request<body::Incoming>
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, Infallible> {
-    let origin = req.headers().get(header::ORIGIN)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
+    // A request through a trusted proxy counts against its client's
+    // `MAX_CONNECTIONS_PER_IP` for as long as its response, or the socket it
+    // upgrades to, lasts.
+    let mut slot = Ok(None);
+    if let Some(peer) = req.extensions().get::<SocketAddr>().map(|addr| addr.ip()) {
+        let ip = ctx.app.trusted_proxies.client_ip(peer, req.headers());
+        req.extensions_mut().insert(ClientIp(ip));
+        if ctx.app.trusted_proxies.contains(peer) {
+            slot = ctx.app.limits.connection(ip).map(|slot| Some(Arc::new(slot))).ok_or(ip);
+        }
+    }
+    if let Ok(Some(slot)) = &slot {
+        req.extensions_mut().insert(slot.clone());
+    }
+
+    let origin = cors::origin(&req).map(str::to_string);
+    let credentialed = origin.is_some() && ctx.app.cors.allows_request(&req);
 
-    let mut response = route_request(ctx, req).await;
-    apply_cors_headers(&mut response, origin.as_deref());
+    let method = req.method().clone();
+    let route = route_label(req.uri().path());
+    let started = Instant::now();
+    let app = ctx.app.clone();
+
+    let header_str = |name| req.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let mut access = AccessLog::new(AccessEntry {
+        ip: client_ip(&req),
+        method: method.to_string(),
+        target: logging::redacted_target(req.uri().path(), req.uri().query()),
+        version: format!("{:?}", req.version()),
+        status: 0,
+        referer: header_str(header::REFERER),
+        user_agent: header_str(header::USER_AGENT),
+    });
+
+    let mut response = match slot {
+        Ok(_) => route_request(ctx, req).await,
+        Err(ip) => {
+            debug!("Refusing request from {}: too many open", ip);
+            too_many_connections()
+        },
+    };
+    let slot = slot.ok().flatten();
+    apply_cors_headers(&mut response, origin.as_deref(), credentialed);
+    app.metrics.record_request(method.as_str(), route, response.status().as_u16(), started.elapsed());
+
+    access.set_status(response.status().as_u16());
+    let response = response.map(|body| {
+        body.map_frame(move |frame| {
+            let _slot = &slot;
+            if let Some(data) = frame.data_ref() {
+                access.add_bytes(data.len());
+            }
+            frame
+        }).boxed()
+    });
     Ok(response)
 }
 
-fn apply_cors_headers<B>(response: &mut Response<B>, origin: Option<&str>) {
+/// Credentialed requests need their exact Origin echoed back, so allowed
+/// origins get that; everyone else gets `*`, which browsers never send
+/// cookies to or share credentialed responses with.
+fn apply_cors_headers<B>(response: &mut Response<B>, origin: Option<&str>, credentialed: bool) {
     let headers = response.headers_mut();
-    // For credentialed requests the spec forbids using `*`; we must echo the
-    // exact Origin back. We do that for every response when an Origin header
-    // is present — the response then works for both credentialed and
-    // uncredentialed clients.
-    match origin.and_then(|o| HeaderValue::from_str(o).ok()) {
+    match origin.filter(|_| credentialed).and_then(|o| HeaderValue::from_str(o).ok()) {
         Some(value) => {
             headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, value);
-            headers.insert(header::VARY, HeaderValue::from_static("Origin"));
             headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
         }
         None => {
             headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+            headers.remove(header::ACCESS_CONTROL_ALLOW_CREDENTIALS);
         }
     }
-    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, PATCH, OPTIONS"));
-    headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("Content-Type, Authorization"));
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static(cors::ALLOWED_METHODS));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static(cors::ALLOWED_HEADERS));
 }
 
 async fn route_request(
@@ -128,9 +362,37 @@ async fn route_request(
         return cors_preflight();
     }
 
+    // Browsers attach the session cookie to cross-site form posts and
+    // fetches too, so only allowed origins may change anything.
+    if path.starts_with("/admin/") && method != Method::GET && method != Method::HEAD && !ctx.app.cors.allows_request(&req) {
+        let origin = cors::origin(&req).unwrap_or_default();
+        warn!(origin, path, "Refused cross-origin admin request");
+        return forbidden(format!("requests from {} aren't allowed; see CORS_ALLOWED_ORIGINS", origin));
+    }
+
+    if method == Method::GET && is_websocket_upgrade(&req) && is_socket_path(&path) {
+        return websocket_upgrade(&ctx, req);
+    }
+
     // Public stream listing
     if method == Method::GET && path == "/streams" {
-        return list_streams_response(&ctx, false).await;
+        return list_streams(&ctx).await;
+    }
+
+    if method == Method::GET && path == "/openapi.json" {
+        return openapi_json();
+    }
+
+    if method == Method::GET && path == "/metrics" {
+        return metrics_text(&ctx, &req).await;
+    }
+
+    if method == Method::GET && path == "/healthz" {
+        return healthz(&ctx, &req).await;
+    }
+
+    if method == Method::GET && path == "/readyz" {
+        return readyz(&ctx, &req).await;
     }
 
     // /streams/{id}/audio  or  /streams/{id}/playlist-image
@@ -139,15 +401,26 @@ async fn route_request(
         let id = parts.next().unwrap_or("").to_string();
         let suffix = parts.next().unwrap_or("");
 
+        if let Err(response) = authorize_listener(&ctx, &id, &req).await {
+            return response;
+        }
+
         if !id.is_empty() && (suffix == "audio" || suffix.is_empty()) {
             if method == Method::GET {
-                return stream_audio_response(&ctx, &id).await;
+                return stream_audio(&ctx, &id, &req).await;
             }
         }
 
+        if !id.is_empty() && suffix == "events" && method == Method::GET {
+            let last_event_id = req.headers().get("Last-Event-ID")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok());
+            return stream_events(&ctx, &id, last_event_id).await;
+        }
+
         if !id.is_empty() && suffix == "playlist-image" {
             if method == Method::GET || method == Method::HEAD {
-                return playlist_image_response(&ctx, &id).await;
+                return playlist_image(&ctx, &id).await;
             }
         }
     }
@@ -159,18 +432,154 @@ async fn route_request(
     if method == Method::POST && path == "/admin/logout" {
         return admin_logout(&ctx, req).await;
     }
+    if method == Method::GET && path == "/admin/me" {
+        return match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => admin_me(&user),
+            Err(response) => response,
+        };
+    }
+    if path == "/admin/me/totp" || path == "/admin/me/totp/confirm" {
+        let user = match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+        if extract_token(&req).is_some_and(|t| is_api_token(&t)) {
+            return forbidden("API tokens can't change two-factor login; sign in instead");
+        }
+        let actor = Actor::new(&user, client_ip(&req));
+        match (method.clone(), path.as_str()) {
+            (Method::POST, "/admin/me/totp") => return admin_totp_begin(&ctx, &user),
+            (Method::DELETE, "/admin/me/totp") => return admin_totp_disable(&ctx, &actor, &user, req).await,
+            (Method::POST, "/admin/me/totp/confirm") => return admin_totp_confirm(&ctx, &actor, &user, req).await,
+            _ => {}
+        }
+    }
     if method == Method::GET && path == "/admin/streams" {
-        if !require_session(&ctx, &req) {
-            return unauthorized();
+        return match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => admin_list_streams(&ctx, &user).await,
+            Err(response) => response,
+        };
+    }
+
+    if method == Method::GET && path == "/admin/stats" {
+        return match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => admin_all_stats(&ctx, &user).await,
+            Err(response) => response,
+        };
+    }
+
+    if method == Method::GET && path == "/admin/audit" {
+        return match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => admin_audit(&ctx, user, &req).await,
+            Err(response) => response,
+        };
+    }
+
+    if path == "/admin/reload" {
+        let actor = match require_session(&ctx, &req, Permission::Manage, Scope::AllStreams) {
+            Ok(user) => Actor::new(&user, client_ip(&req)),
+            Err(response) => return response,
+        };
+        match method {
+            Method::POST => return admin_reload(&ctx, &actor).await,
+            Method::GET => return admin_last_reload(&ctx).await,
+            _ => {}
         }
-        return list_streams_response(&ctx, true).await;
     }
 
-    if let Some(rest) = path.strip_prefix("/admin/streams/") {
-        if !require_session(&ctx, &req) {
-            return unauthorized();
+    if path == "/admin/override" {
+        // Which streams a new override needs depends on its body, so that
+        // handler checks the rest itself.
+        let scope = if method == Method::POST { Scope::AnyStream } else { Scope::AllStreams };
+        let user = match require_session(&ctx, &req, Permission::Manage, scope) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+        let actor = Actor::new(&user, client_ip(&req));
+        match method {
+            Method::POST => return admin_override_start(&ctx, &actor, &user, req).await,
+            Method::DELETE => return admin_override_end(&ctx, &actor).await,
+            _ => {}
+        }
+    }
+
+    if path == "/admin/users" || path.starts_with("/admin/users/") {
+        let actor = match require_session(&ctx, &req, Permission::ManageUsers, Scope::AllStreams) {
+            Ok(user) => Actor::new(&user, client_ip(&req)),
+            Err(response) => return response,
+        };
+
+        let rest = path.trim_start_matches("/admin/users").trim_start_matches('/');
+        let mut parts = rest.splitn(2, '/');
+        let username = parts.next().unwrap_or("").to_string();
+        let action = parts.next().unwrap_or("");
+
+        match (method.clone(), username.as_str(), action) {
+            (Method::GET, "", "") => return admin_list_users(&ctx),
+            (Method::POST, "", "") => return admin_create_user(&ctx, &actor, req).await,
+            (Method::PUT, _, "") if !username.is_empty() => return admin_update_user(&ctx, &actor, &username, req).await,
+            (Method::DELETE, _, "") if !username.is_empty() => return admin_delete_user(&ctx, &actor, &username),
+            (Method::POST, _, "password") if !username.is_empty() => return admin_reset_password(&ctx, &actor, &username, req).await,
+            (Method::DELETE, _, "totp") if !username.is_empty() => return admin_reset_totp(&ctx, &actor, &username),
+            _ => {}
+        }
+    }
+
+    if path == "/admin/tokens" || path.starts_with("/admin/tokens/") {
+        let user = match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+        // Otherwise a leaked token could mint replacements for itself.
+        if extract_token(&req).is_some_and(|t| is_api_token(&t)) {
+            return forbidden("API tokens can't manage tokens; sign in instead");
+        }
+
+        let actor = Actor::new(&user, client_ip(&req));
+        let id = path.trim_start_matches("/admin/tokens").trim_start_matches('/').to_string();
+        match (method.clone(), id.is_empty()) {
+            (Method::GET, true) => return admin_list_tokens(&ctx, &user),
+            (Method::POST, true) => return admin_create_token(&ctx, &actor, &user, req).await,
+            (Method::DELETE, false) if !id.contains('/') => return admin_revoke_token(&ctx, &actor, &user, &id),
+            _ => {}
+        }
+    }
+
+    if path == "/admin/sessions" || path.starts_with("/admin/sessions/") {
+        let user = match require_session(&ctx, &req, Permission::View, Scope::AnyStream) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+
+        let id = path.trim_start_matches("/admin/sessions").trim_start_matches('/').to_string();
+        match (method.clone(), id.is_empty()) {
+            (Method::GET, true) => return admin_list_sessions(&ctx, &user, &req),
+            (Method::DELETE, false) if !id.contains('/') => {
+                return admin_revoke_session(&ctx, &Actor::new(&user, client_ip(&req)), &user, &id);
+            },
+            _ => {}
+        }
+    }
+
+    if path == "/admin/listeners" || path.starts_with("/admin/listeners/") || path.starts_with("/admin/listener-tokens") {
+        let actor = match require_session(&ctx, &req, Permission::ManageUsers, Scope::AllStreams) {
+            Ok(user) => Actor::new(&user, client_ip(&req)),
+            Err(response) => return response,
+        };
+
+        let username = path.strip_prefix("/admin/listeners/").unwrap_or("").to_string();
+        let token_id = path.strip_prefix("/admin/listener-tokens/").unwrap_or("").to_string();
+        match (method.clone(), path.as_str()) {
+            (Method::GET, "/admin/listeners") => return admin_list_listeners(&ctx),
+            (Method::POST, "/admin/listeners") => return admin_create_listener(&ctx, &actor, req).await,
+            (Method::POST, "/admin/listener-tokens") => return admin_create_listener_token(&ctx, &actor, req).await,
+            (Method::DELETE, _) if !username.is_empty() && !username.contains('/') => return admin_delete_listener(&ctx, &actor, &username),
+            (Method::DELETE, _) if !token_id.is_empty() && !token_id.contains('/') => return admin_revoke_listener_token(&ctx, &actor, &token_id),
+            _ => {}
         }
+    }
 
+    if let Some(rest) = path.strip_prefix("/admin/streams/") {
         let mut parts = rest.splitn(2, '/');
         let id = parts.next().unwrap_or("").to_string();
         let action = parts.next().unwrap_or("");
@@ -179,11 +588,33 @@ async fn route_request(
             return not_found();
         }
 
+        let permission = match (&method, action) {
+            (&Method::GET, _) => Permission::View,
+            (&Method::PATCH, "") | (&Method::POST, "signed-url") => Permission::Manage,
+            _ => Permission::Control,
+        };
+        let user = match require_session(&ctx, &req, permission, Scope::Stream(&id)) {
+            Ok(user) => user,
+            Err(response) => return response,
+        };
+        let actor = Actor::new(&user, client_ip(&req));
+
         match (method.clone(), action) {
-            (Method::POST, "skip") => return admin_skip(&ctx, &id).await,
-            (Method::POST, "pause") => return admin_pause(&ctx, &id).await,
-            (Method::POST, "resume") => return admin_resume(&ctx, &id).await,
-            (Method::PATCH, "") => return admin_rename(&ctx, &id, req).await,
+            (Method::POST, "skip") => return admin_skip(&ctx, &actor, &user, &id).await,
+            (Method::POST, "pause") => return admin_pause(&ctx, &actor, &user, &id).await,
+            (Method::POST, "resume") => return admin_resume(&ctx, &actor, &user, &id).await,
+            (Method::POST, "seek") => return admin_seek(&ctx, &actor, &user, &id, req).await,
+            (Method::POST, "signed-url") => return admin_signed_url(&ctx, &actor, &id, req).await,
+            (Method::PATCH, "") => return admin_rename(&ctx, &actor, &id, req).await,
+            (Method::GET, "stats") => return admin_stats(&ctx, &id).await,
+            (Method::GET, "queue") => return admin_get_queue(&ctx, &user, &id).await,
+            (Method::POST, "queue") => return admin_queue_add(&ctx, &actor, &user, &id, req).await,
+            (Method::DELETE, "queue") => return admin_queue_clear(&ctx, &actor, &user, &id).await,
+            (Method::DELETE, action) => {
+                if let Some(Ok(index)) = action.strip_prefix("queue/").map(str::parse) {
+                    return admin_queue_remove(&ctx, &actor, &user, &id, index).await;
+                }
+            },
             _ => {}
         }
     }
@@ -191,80 +622,158 @@ async fn route_request(
     not_found()
 }
 
-async fn list_streams_response(ctx: &Arc<HTTPServerContext>, include_playlist: bool) -> Response<BoxBody<Bytes, hyper::Error>> {
+#[utoipa::path(
+    get,
+    path = "/streams",
+    responses((status = 200, description = "The public streams; unlisted and private ones are left out", body = [StreamSummary])),
+)]
+async fn list_streams(ctx: &Arc<HTTPServerContext>) -> Response<BoxBody<Bytes, hyper::Error>> {
     let registry = ctx.app.registry.read().await;
 
     let mut items = Vec::with_capacity(registry.len());
-    for (_, entry_arc) in registry.iter() {
+    for entry_arc in registry.values() {
         let entry = entry_arc.read().await;
-        let paused = entry.player.is_paused().await.unwrap_or(false);
-        let metadata = entry.player.get_metadata().await.ok().flatten();
+        if !entry.config.visibility.is_public() {
+            continue;
+        }
+        items.push(stream_summary(&entry).await);
+    }
+    drop(registry);
 
-        let title_field = match metadata.as_ref() {
-            Some(m) => format!(r#","title":"{}""#, json_escape(&m.title)),
-            None => String::new(),
-        };
-        let author_field = match metadata.as_ref() {
-            Some(m) => format!(r#","author":"{}""#, json_escape(&m.author)),
-            None => String::new(),
-        };
-        let playlist_field = if include_playlist {
-            format!(r#","playlist":"{}""#, json_escape(&entry.config.playlist))
-        } else {
-            String::new()
-        };
+    json_ok(&items)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/streams",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The streams the user has access to, with playout details", body = [AdminStreamSummary]),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+async fn admin_list_streams(ctx: &Arc<HTTPServerContext>, user: &User) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let registry = ctx.app.registry.read().await;
 
-        items.push(format!(
-            r#"{{"id":"{}","name":"{}","paused":{}{}{}{}}}"#,
-            json_escape(&entry.config.id),
-            json_escape(&entry.config.name),
-            paused,
-            title_field,
-            author_field,
-            playlist_field,
-        ));
+    let mut items = Vec::with_capacity(registry.len());
+    for (id, entry_arc) in registry.iter() {
+        if !user.can_access(id) {
+            continue;
+        }
+        let entry = entry_arc.read().await;
+        items.push(AdminStreamSummary {
+            stream: stream_summary(&entry).await,
+            visibility: entry.config.visibility,
+            playlist: entry.config.playlist.clone(),
+            simulcast_of: entry.config.simulcast_of.clone(),
+            source: entry.failover.source.clone(),
+            fallback_level: entry.failover.level,
+            alert: entry.failover.last_alert.clone(),
+        });
     }
     drop(registry);
 
-    let body = format!("[{}]", items.join(","));
-    json_ok(body)
+    json_ok(&items)
 }
 
-async fn stream_audio_response(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let player = match get_player(ctx, stream_id).await {
-        Some(p) => p,
+async fn stream_summary(entry: &StreamEntry) -> StreamSummary {
+    let paused = entry.player.is_paused().await.unwrap_or(false);
+    let metadata = entry.player.get_metadata().await.ok().flatten();
+    let (listeners, peak_listeners) = entry.audience.counts();
+
+    StreamSummary {
+        id: entry.config.id.clone(),
+        name: entry.config.name.clone(),
+        paused,
+        title: metadata.as_ref().map(|m| m.title.clone()),
+        author: metadata.as_ref().map(|m| m.author.clone()),
+        listeners,
+        peak_listeners,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/streams/{id}/audio",
+    params(("id" = String, Path, description = "Stream id")),
+    responses(
+        (status = 200, description = "Endless Ogg Opus stream", content_type = "audio/ogg"),
+        (status = 401, description = "The stream is private; see `WWW-Authenticate`", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 503, description = "Too many listeners; see `Retry-After`", body = ErrorResponse),
+    ),
+)]
+async fn stream_audio(ctx: &Arc<HTTPServerContext>, stream_id: &str, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let entry_arc = match ctx.app.registry.read().await.get(stream_id).cloned() {
+        Some(e) => e,
         None => return not_found(),
     };
+    let (player, bitrate_kbps, slow, guard, slot, metrics, stopped) = {
+        let e = entry_arc.read().await;
+        let Some(slot) = ctx.app.limits.listener() else {
+            return server_full("server has as many listeners as it allows");
+        };
+        let user_agent = req.headers().get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let Some(guard) = ListenerGuard::new(&e, user_agent, client_ip(req)) else {
+            return server_full("stream has as many listeners as it allows");
+        };
+        (e.player.clone(), e.config.bitrate_kbps, e.config.slow_listener, guard, slot, e.metrics.clone(), e.stopped.clone())
+    };
 
     let (tx, rx) = mpsc::channel(500);
     let mut ogg_stream = OggStream::new();
 
-    let stream = ReceiverStream::new(rx)
+    // Each item is a whole Ogg page, so ending the body on shutdown, or when
+    // a reload removes the stream, leaves the listener with a complete last
+    // page.
+    let shutdown = ctx.app.shutdown.clone();
+    let ended = async move {
+        tokio::select! {
+            _ = shutdown.wait() => {},
+            _ = stopped.wait() => {},
+        }
+    };
+    let packets = futures_util::StreamExt::take_until(ReceiverStream::new(rx), ended);
+    let stream = packets
         .map(move |player_event| {
+            // The body stream lives exactly as long as the connection.
+            let _ = (&guard, &slot);
             match player_event {
                 OpusPlayerEvent::AudioData { raw_opus_data, granule_position } => {
                     let ogg_data = ogg_stream.encode(raw_opus_data, granule_position);
+                    metrics.add_bytes_sent(ogg_data.len());
                     Ok(Frame::data(Bytes::from(ogg_data.to_vec())))
                 },
             }
         });
 
     let player_for_task = player.clone();
+    // The listener may hang up, or a reload stop the player, at any point
+    // here; either just ends the response.
     tokio::spawn(async move {
-        tx.send(OpusPlayerEvent::AudioData { raw_opus_data: OPUS_HEAD.to_vec(), granule_position: 0 })
-            .await
-            .expect("Should send opus head");
-        tx.send(OpusPlayerEvent::AudioData { raw_opus_data: OPUS_COMMENTS.to_vec(), granule_position: 0 })
-            .await
-            .expect("Should send opus comments");
+        for header in [OPUS_HEAD, OPUS_COMMENTS] {
+            let event = OpusPlayerEvent::AudioData { raw_opus_data: header.to_vec(), granule_position: 0 };
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
 
-        let headstart_events = player_for_task.get_headstart_data().await.expect("Should get headstart data");
+        let headstart_events = match player_for_task.get_headstart_data(bitrate_kbps).await {
+            Ok(events) => events,
+            Err(e) => return debug!("Listener not started, no headstart data: {}", e),
+        };
 
         for event in headstart_events {
-            tx.send(event).await.expect("Should send headstart data");
+            if tx.send(event).await.is_err() {
+                return;
+            }
         }
 
-        player_for_task.register_listener(tx).await.expect("Should register listener");
+        if let Err(e) = player_for_task.register_listener(tx, bitrate_kbps, slow).await {
+            debug!("Listener not registered: {}", e);
+        }
     });
 
     let stream_body = StreamBody::new(stream);
@@ -272,15 +781,97 @@ async fn stream_audio_response(ctx: &Arc<HTTPServerContext>, stream_id: &str) ->
     Response::builder()
         .header("Connection", "keep-alive")
         .header("Content-Type", "audio/ogg")
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS")
-        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Authorization")
-        .header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
         .body(BoxBody::new(stream_body))
         .expect("Should build body")
 }
 
-async fn playlist_image_response(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+#[utoipa::path(
+    get,
+    path = "/streams/{id}/events",
+    params(
+        ("id" = String, Path, description = "Stream id"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Id of the last event received before reconnecting"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events feed of `StreamEvent`s, named by their `type`", content_type = "text/event-stream"),
+        (status = 401, description = "The stream is private", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn stream_events(ctx: &Arc<HTTPServerContext>, stream_id: &str, last_event_id: Option<u64>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let entry_arc = match ctx.app.registry.read().await.get(stream_id).cloned() {
+        Some(e) => e,
+        None => return unknown_stream(stream_id),
+    };
+    // Subscribe before reading the last id so nothing falls in between.
+    let (mut events_rx, last_id, stopped) = {
+        let e = entry_arc.read().await;
+        let events_rx = e.events_tx.subscribe();
+        (events_rx, e.events_tx.last_id(), e.stopped.clone())
+    };
+
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+    let stream_id = stream_id.to_string();
+    let shutdown = ctx.app.shutdown.clone();
+    tokio::spawn(async move {
+        // A client that missed something, or is new, gets the current track
+        // as a `track_changed` carrying the latest id.
+        let replayed = last_event_id != Some(last_id);
+        if replayed {
+            if let Ok(metadata) = entry_metadata(&entry_arc).await {
+                let event = StreamEvent::TrackChanged { stream: stream_id.clone(), metadata };
+                if tx.send(sse_event(last_id, &event)).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        let mut keepalive = tokio::time::interval(SSE_KEEPALIVE);
+        keepalive.tick().await;
+        loop {
+            let chunk = tokio::select! {
+                // Dropping the sender ends the response.
+                _ = shutdown.wait() => break,
+                _ = stopped.wait() => break,
+                event = events_rx.recv() => match event {
+                    Ok(event) if replayed && event.id <= last_id => continue,
+                    Ok(event) => sse_event(event.id, &event.event),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                // Comments keep proxies from timing out an idle feed.
+                _ = keepalive.tick() => Bytes::from_static(b": keepalive\n\n"),
+            };
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(|chunk| Ok(Frame::data(chunk)));
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(BoxBody::new(StreamBody::new(stream)))
+        .expect("Should build body")
+}
+
+fn sse_event(id: u64, event: &StreamEvent) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!("id: {}\nevent: {}\ndata: {}\n\n", id, event.kind(), data))
+}
+
+#[utoipa::path(
+    get,
+    path = "/streams/{id}/playlist-image",
+    params(("id" = String, Path, description = "Stream id")),
+    responses(
+        (status = 200, description = "The playlist's `playlist.jpg`", content_type = "image/jpeg"),
+        (status = 401, description = "The stream is private; see `WWW-Authenticate`", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn playlist_image(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
     let player = match get_player(ctx, stream_id).await {
         Some(p) => p,
         None => return not_found(),
@@ -288,7 +879,7 @@ async fn playlist_image_response(ctx: &Arc<HTTPServerContext>, stream_id: &str)
 
     let playlist_path = match player.get_playlist_path().await {
         Ok(Some(p)) => p,
-        _ => return cors_response(StatusCode::NOT_FOUND, empty()),
+        _ => return not_found(),
     };
 
     let image_path = Path::new(&playlist_path).join("playlist.jpg");
@@ -297,149 +888,1195 @@ async fn playlist_image_response(ctx: &Arc<HTTPServerContext>, stream_id: &str)
         Ok(image_data) => Response::builder()
             .status(StatusCode::OK)
             .header(header::CONTENT_TYPE, "image/jpeg")
-            .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
             .header(header::CACHE_CONTROL, "public, max-age=3600")
             .body(full(image_data))
             .expect("Should build response"),
-        Err(_) => cors_response(StatusCode::NOT_FOUND, empty()),
+        Err(_) => not_found(),
     }
 }
 
-async fn admin_login(ctx: &Arc<HTTPServerContext>, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let body = match req.collect().await {
-        Ok(c) => c.to_bytes(),
-        Err(_) => return cors_response(StatusCode::BAD_REQUEST, empty()),
+fn is_websocket_upgrade(req: &Request<body::Incoming>) -> bool {
+    let has_token = |name, token: &str| {
+        req.headers().get_all(name).iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim().eq_ignore_ascii_case(token))
     };
+    has_token(header::CONNECTION, "upgrade") && has_token(header::UPGRADE, "websocket")
+}
 
-    let password = extract_json_string_field(&body, "password").unwrap_or_default();
+/// Completes a WebSocket handshake on the HTTP port and hands the socket to
+/// the WebSocket server once hyper releases the connection.
+fn websocket_upgrade(ctx: &Arc<HTTPServerContext>, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let accept = match req.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => return error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidBody, "missing Sec-WebSocket-Key"),
+    };
 
-    if !ctx.app.auth.check_password(&password) {
-        return cors_response(StatusCode::UNAUTHORIZED, full(r#"{"error":"invalid password"}"#));
-    }
+    let path = req.uri().path().to_string();
+    // A page on another site could otherwise drive an admin socket with the
+    // visitor's cookie; its tokens still work.
+    let credentials = ListenerCredentials::from_request(&req, ctx.app.cors.allows_request(&req));
+    let app = ctx.app.clone();
+    let slot = req.extensions().get::<Arc<ConnectionSlot>>().cloned();
+    tokio::spawn(async move {
+        let _slot = slot;
+        match hyper::upgrade::on(req).await {
+            Ok(upgraded) => {
+                let ws_stream = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+                handle_socket(app, ws_stream, &path, credentials).await;
+            },
+            Err(e) => warn!("WebSocket upgrade failed: {}", e),
+        }
+    });
 
-    let token = ctx.app.auth.issue_session();
     Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json")
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
-        .header(header::SET_COOKIE, cookie_header_value(&token))
-        .body(full(r#"{"ok":true}"#))
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(empty())
         .expect("Should build response")
 }
 
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text exposition format", content_type = "text/plain"),
+    ),
+)]
+async fn metrics_text(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")
+        .body(full(metrics::render(&ctx.app, monitored_streams(ctx, req)).await))
+        .expect("Should build response")
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Every player is responsive", body = HealthResponse),
+        (status = 503, description = "A player stopped answering", body = HealthResponse),
+    ),
+)]
+async fn healthz(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    health_check(ctx, req, Check::Live).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Every stream is producing audio", body = HealthResponse),
+        (status = 503, description = "A stream is stalled, its player is unresponsive, or its playlist stopped", body = HealthResponse),
+    ),
+)]
+async fn readyz(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    health_check(ctx, req, Check::Ready).await
+}
+
+async fn health_check(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>, check: Check) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let report = health::report(&ctx.app, check, monitored_streams(ctx, req)).await;
+    let status = if report.ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    json_response(status, &report)
+}
+
+/// The streams `/metrics`, `/healthz` and `/readyz` name: public ones, plus
+/// those the request's admin session or token can access. Unlisted and
+/// private ids stay hidden from everyone else, as in `/streams`.
+fn monitored_streams(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>) -> impl Fn(&StreamConfig) -> bool {
+    let viewer = extract_token(req).and_then(|token| ctx.app.auth.validate(&token));
+    move |config| config.visibility.is_public() || viewer.as_ref().is_some_and(|user| user.can_access(&config.id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "This document")),
+)]
+fn openapi_json() -> Response<BoxBody<Bytes, hyper::Error>> {
+    match ApiDoc::openapi().to_pretty_json() {
+        Ok(doc) => json_body_response(StatusCode::OK, full(doc)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Sets the session cookie", body = OkResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, description = "Wrong password or two-factor code, or `totp_required` when a code is needed", body = ErrorResponse),
+        (status = 429, description = "Too many failed logins from this address or for this user; see `Retry-After`", body = ErrorResponse),
+    ),
+)]
+async fn admin_login(ctx: &Arc<HTTPServerContext>, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let user_agent = req.headers().get(header::USER_AGENT).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let ip = client_ip(&req);
+    let LoginRequest { username, password, totp } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let username = username.unwrap_or_else(|| DEFAULT_USERNAME.to_string());
+    let ip_label = ip.map(|ip| ip.to_string()).unwrap_or_default();
+    let throttle = &ctx.app.auth.throttle;
+    if let Some(wait) = throttle.check(ip, &username) {
+        debug!(user = %username, ip = %ip_label, "Admin login throttled");
+        return too_many_attempts(wait);
+    }
+    let actor = Actor { username: Some(username.clone()), ip };
+    let failed = |code: ErrorCode, error: &str, reason: &str| {
+        let failure = throttle.record_failure(ip, &username);
+        let mut detail = format!("wrong {}, {} in a row", reason, failure.count);
+        if let Some(lockout) = failure.locked_for {
+            detail.push_str(&format!(", locked for {:?}", lockout));
+        }
+        ctx.app.audit.record(actor.entry(AuditAction::LoginFailed).detail(detail));
+        match failure.locked_for {
+            Some(lockout) => too_many_attempts(lockout),
+            None => error_response(StatusCode::UNAUTHORIZED, code, error),
+        }
+    };
+    let Some(user) = ctx.app.auth.authenticate(&username, &password).await else {
+        return failed(ErrorCode::InvalidPassword, "invalid username or password", "password");
+    };
+    let mut login = actor.entry(AuditAction::Login);
+    if user.has_totp() {
+        let Some(code) = totp else {
+            return error_response(StatusCode::UNAUTHORIZED, ErrorCode::TotpRequired, "two-factor code required");
+        };
+        match control::verify_totp(&ctx.app, &user.username, &code) {
+            Ok(Used::Code) => {},
+            Ok(Used::RecoveryCode { remaining }) => {
+                login = login.detail(format!("recovery code used, {} left", remaining));
+            },
+            Err(e) if e.code == ErrorCode::InvalidTotp => return failed(ErrorCode::InvalidTotp, "invalid two-factor code", "totp"),
+            Err(e) => return control_error(e),
+        }
+    }
+    throttle.record_success(&username);
+    ctx.app.audit.record(login);
+
+    let token = match ctx.app.auth.issue_session(&user.username, user_agent, ip) {
+        Ok(token) => token,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e),
+    };
+    let mut response = json_ok(&OkResponse::OK);
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&cookie_header_value(&token)).expect("Should build cookie header"),
+    );
+    response
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/logout",
+    responses((status = 200, description = "Clears the session cookie", body = OkResponse)),
+)]
 async fn admin_logout(ctx: &Arc<HTTPServerContext>, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
     if let Some(token) = extract_token(&req) {
-        ctx.app.auth.revoke(&token);
+        if let Some(user) = ctx.app.auth.validate(&token) {
+            ctx.app.audit.record(Actor::new(&user, client_ip(&req)).entry(AuditAction::Logout));
+        }
+        if let Err(e) = ctx.app.auth.revoke(&token) {
+            warn!("Failed to save logout: {}", e);
+        }
     }
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/json")
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
-        .header(header::SET_COOKIE, cookie_clear_value())
-        .body(full(r#"{"ok":true}"#))
-        .expect("Should build response")
+    let mut response = json_ok(&OkResponse::OK);
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&cookie_clear_value()).expect("Should build cookie header"),
+    );
+    response
 }
 
-async fn admin_skip(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let player = match get_player(ctx, stream_id).await {
-        Some(p) => p,
-        None => return not_found(),
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/skip",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_skip(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::skip(&ctx.app, actor, user, stream_id).await {
+        Ok(()) => json_ok(&OkResponse::OK),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/pause",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_pause(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::pause(&ctx.app, actor, user, stream_id).await {
+        Ok(()) => json_ok(&OkResponse::OK),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/resume",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_resume(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::resume(&ctx.app, actor, user, stream_id).await {
+        Ok(()) => json_ok(&OkResponse::OK),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/seek",
+    params(("id" = String, Path, description = "Stream id")),
+    request_body = SeekRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = SeekResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "Nothing seekable is playing, an override is on air, or the position is past the end", body = ErrorResponse),
+    ),
+)]
+async fn admin_seek(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let SeekRequest { position_ms } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    if let Err(e) = player.skip().await {
-        return cors_response(StatusCode::INTERNAL_SERVER_ERROR, full(format!(r#"{{"error":"{}"}}"#, json_escape(&e))));
+
+    match control::seek(&ctx.app, actor, user, stream_id, position_ms).await {
+        Ok(landed_ms) => json_ok(&SeekResponse { ok: true, position_ms: landed_ms }),
+        Err(e) => control_error(e),
     }
-    cors_response(StatusCode::OK, full(r#"{"ok":true}"#))
 }
 
-async fn admin_pause(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let entry_arc = match ctx.app.registry.read().await.get(stream_id).cloned() {
-        Some(e) => e,
-        None => return not_found(),
+#[utoipa::path(
+    patch,
+    path = "/admin/streams/{id}",
+    params(("id" = String, Path, description = "Stream id")),
+    request_body = RenameRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Renamed and persisted to the config file", body = OkResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_rename(ctx: &Arc<HTTPServerContext>, actor: &Actor, stream_id: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let RenameRequest { name } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    let (player, metadata_tx, stream_name) = {
-        let e = entry_arc.read().await;
-        (e.player.clone(), e.metadata_tx.clone(), e.config.name.clone())
+
+    match control::rename(&ctx.app, actor, stream_id, name).await {
+        Ok(()) => json_ok(&OkResponse::OK),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The config was reloaded; the diff lists what changed", body = ReloadReport),
+        (status = 400, description = "The config was rejected and nothing changed", body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+async fn admin_reload(ctx: &Arc<HTTPServerContext>, actor: &Actor) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let report = reload::reload(&ctx.app, actor, ReloadTrigger::Api).await;
+    match report.error {
+        Some(error) => error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidConfig, error),
+        None => json_ok(&report),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/reload",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The latest reload, whatever triggered it", body = ReloadReport),
+        (status = 401, body = ErrorResponse),
+        (status = 404, description = "No reload since startup", body = ErrorResponse),
+    ),
+)]
+async fn admin_last_reload(ctx: &Arc<HTTPServerContext>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match ctx.app.last_reload.lock().await.clone() {
+        Some(report) => json_ok(&report),
+        None => not_found(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Audience figures for every stream the user has access to", body = [StreamStats]),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+async fn admin_all_stats(ctx: &Arc<HTTPServerContext>, user: &User) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let registry = ctx.app.registry.read().await;
+
+    let mut items = Vec::with_capacity(registry.len());
+    for (id, entry_arc) in registry.iter().filter(|(id, _)| user.can_access(id)) {
+        items.push(entry_arc.read().await.audience.snapshot(id));
+    }
+    drop(registry);
+    items.sort_by(|a, b| a.stream.cmp(&b.stream));
+
+    json_ok(&items)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/streams/{id}/stats",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = StreamStats),
+        (status = 401, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_stats(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::stats(&ctx.app, stream_id).await {
+        Ok(stats) => json_ok(&stats),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/streams/{id}/queue",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = QueueResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_get_queue(ctx: &Arc<HTTPServerContext>, user: &User, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::get_queue(&ctx.app, user, stream_id).await {
+        Ok(items) => json_ok(&QueueResponse { items }),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/queue",
+    params(("id" = String, Path, description = "Stream id")),
+    request_body = QueueAddRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The queue after the insert", body = QueueResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_queue_add(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let QueueAddRequest { path, index } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    if let Err(e) = player.pause().await {
-        return cors_response(StatusCode::INTERNAL_SERVER_ERROR, full(format!(r#"{{"error":"{}"}}"#, json_escape(&e))));
+
+    match control::queue_add(&ctx.app, actor, user, stream_id, path, index).await {
+        Ok(items) => json_ok(&QueueResponse { items }),
+        Err(e) => control_error(e),
     }
-    // Broadcast updated metadata so listeners see the paused flag.
-    if let Ok(json) = crate::ws_server::get_metadata_json(&player, Some(&stream_name), Some(stream_id)).await {
-        let _ = metadata_tx.send(json);
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/streams/{id}/queue/{index}",
+    params(
+        ("id" = String, Path, description = "Stream id"),
+        ("index" = usize, Path, description = "Position in the queue"),
+    ),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The queue after the removal", body = QueueResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_queue_remove(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str, index: usize) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::queue_remove(&ctx.app, actor, user, stream_id, index).await {
+        Ok(items) => json_ok(&QueueResponse { items }),
+        Err(e) => control_error(e),
     }
-    cors_response(StatusCode::OK, full(r#"{"ok":true}"#))
 }
 
-async fn admin_resume(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let entry_arc = match ctx.app.registry.read().await.get(stream_id).cloned() {
-        Some(e) => e,
-        None => return not_found(),
+#[utoipa::path(
+    delete,
+    path = "/admin/streams/{id}/queue",
+    params(("id" = String, Path, description = "Stream id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "No access to the stream or, for a simulcast, its source", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_queue_clear(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::queue_clear(&ctx.app, actor, user, stream_id).await {
+        Ok(()) => json_ok(&OkResponse::OK),
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/override",
+    request_body = OverrideRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Override is on air on the listed streams", body = OverrideResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_override_start(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: OverrideRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    let (player, metadata_tx, stream_name) = {
-        let e = entry_arc.read().await;
-        (e.player.clone(), e.metadata_tx.clone(), e.config.name.clone())
+
+    let mut path = request.path.filter(|p| !p.trim().is_empty());
+    let source_stream = request.source_stream.filter(|s| !s.trim().is_empty());
+
+    let mut targets = match request.streams {
+        Some(ids) if !ids.is_empty() => ids,
+        _ if !user.has_all_streams() => return forbidden("overriding every stream needs access to all of them"),
+        _ => ctx.app.registry.read().await.keys().cloned().collect(),
     };
-    if let Err(e) = player.resume().await {
-        return cors_response(StatusCode::INTERNAL_SERVER_ERROR, full(format!(r#"{{"error":"{}"}}"#, json_escape(&e))));
+    targets.sort();
+    targets.dedup();
+
+    // Simulcasts share their source's player, so override each player once.
+    let mut players: Vec<(String, OpusPlayerHandle)> = Vec::with_capacity(targets.len());
+    let mut source_ids: Vec<String> = Vec::with_capacity(targets.len());
+    for id in &targets {
+        let entry = match ctx.app.registry.read().await.get(id).cloned() {
+            Some(e) => e,
+            None => return unknown_stream(id),
+        };
+        let e = entry.read().await;
+        let source_id = e.config.source_id().to_string();
+        // A simulcast's player also feeds its source stream.
+        if let Some(denied) = [id, &source_id].into_iter().find(|s| !user.can_access(s)) {
+            return forbidden(format!("no access to stream '{}'", denied));
+        }
+        if !players.iter().any(|(existing, _)| existing == &source_id) {
+            players.push((source_id.clone(), e.player.clone()));
+        }
+        source_ids.push(source_id);
     }
-    if let Ok(json) = crate::ws_server::get_metadata_json(&player, Some(&stream_name), Some(stream_id)).await {
-        let _ = metadata_tx.send(json);
+
+    let source_player = match (&mut path, &source_stream) {
+        (Some(path), None) => {
+            // Only files from the playlist folder of a stream being overridden.
+            let mut playlists = Vec::with_capacity(players.len());
+            for (source_id, _) in &players {
+                if let Some(entry) = ctx.app.registry.read().await.get(source_id).cloned() {
+                    playlists.push(entry.read().await.config.playlist.clone());
+                }
+            }
+            let mut resolved = None;
+            for playlist in &playlists {
+                if let Ok(file) = control::playlist_file(playlist, path).await {
+                    resolved = Some(file);
+                    break;
+                }
+            }
+            match resolved {
+                Some(file) => *path = file,
+                None => return error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::FileNotFound,
+                    "override file not found in the overridden streams' playlist folders",
+                ),
+            }
+            None
+        },
+        (None, Some(source_id)) => {
+            if targets.contains(source_id) || players.iter().any(|(id, _)| id == source_id) {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    ErrorCode::InvalidBody,
+                    "source_stream cannot be one of the overridden streams",
+                );
+            }
+            if !user.can_access(source_id) {
+                return forbidden(format!("no access to stream '{}'", source_id));
+            }
+            match get_player(ctx, source_id).await {
+                Some(p) => Some(p),
+                None => return unknown_stream(source_id),
+            }
+        },
+        _ => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidBody,
+                "exactly one of path or source_stream required",
+            );
+        },
+    };
+
+    let mut switched: Vec<(String, OpusPlayerHandle)> = Vec::with_capacity(players.len());
+    let mut failure = None;
+    for (id, player) in players {
+        let source = match (&path, &source_player) {
+            (Some(path), _) => PlaybackSource::File(path.clone()),
+            (None, Some(source_player)) => {
+                let (feed_tx, feed) = mpsc::channel(500);
+                if let Err(e) = source_player.register_listener(feed_tx, None, SlowListenerPolicy::Drop).await {
+                    failure = Some(e);
+                    break;
+                }
+                let label = format!("Relay of {}", source_stream.as_deref().unwrap_or_default());
+                PlaybackSource::Relay { feed, label }
+            },
+            (None, None) => unreachable!("validated above"),
+        };
+
+        let handles = match player.start_override(source).await {
+            Ok(h) => h,
+            Err(e) => {
+                failure = Some(e);
+                break;
+            },
+        };
+        switched.push((id.clone(), player));
+
+        // Announce the override when it goes on air and again when the
+        // stream returns to its regular programme.
+        let registry = ctx.app.registry.clone();
+        tokio::spawn(async move {
+            if handles.started.await.is_ok() {
+                broadcast_metadata(&registry, &id).await;
+            }
+            let result = handles.result.await;
+            info!(target: "audit", stream_id = %id, "Emergency override ended: {:?}", result);
+            broadcast_metadata(&registry, &id).await;
+        });
+    }
+
+    let on_air: Vec<&String> = targets.iter()
+        .zip(&source_ids)
+        .filter(|(_, source_id)| switched.iter().any(|(id, _)| id == *source_id))
+        .map(|(id, _)| id)
+        .collect();
+    let source_label = path.or(source_stream.map(|s| format!("stream {}", s))).unwrap_or_default();
+    for id in &on_air {
+        ctx.app.audit.record(actor.entry(AuditAction::OverrideStart).stream(id).after(&source_label));
     }
-    cors_response(StatusCode::OK, full(r#"{"ok":true}"#))
+
+    // All or nothing: the players already switched go back to their regular
+    // programme.
+    if let Some(e) = failure {
+        for (id, player) in &switched {
+            if let Err(e) = player.end_override().await {
+                warn!(stream_id = %id, "Could not roll back the override: {}", e);
+            }
+        }
+        for id in &on_air {
+            ctx.app.audit.record(actor.entry(AuditAction::OverrideEnd).stream(id));
+        }
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e);
+    }
+
+    json_ok(&OverrideResponse { ok: true, streams: targets })
 }
 
-async fn admin_rename(ctx: &Arc<HTTPServerContext>, stream_id: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
-    let body = match req.collect().await {
-        Ok(c) => c.to_bytes(),
-        Err(_) => return cors_response(StatusCode::BAD_REQUEST, empty()),
+#[utoipa::path(
+    delete,
+    path = "/admin/override",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Every stream is back on its regular programme", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+async fn admin_override_end(ctx: &Arc<HTTPServerContext>, actor: &Actor) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let players: Vec<OpusPlayerHandle> = {
+        let registry = ctx.app.registry.read().await;
+        let mut players = Vec::with_capacity(registry.len());
+        for entry in registry.values() {
+            players.push(entry.read().await.player.clone());
+        }
+        players
     };
 
-    let new_name = match extract_json_string_field(&body, "name") {
-        Some(n) if !n.trim().is_empty() => n,
-        _ => return cors_response(StatusCode::BAD_REQUEST, full(r#"{"error":"name required"}"#)),
+    for player in players {
+        if let Err(e) = player.end_override().await {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e);
+        }
+    }
+
+    ctx.app.audit.record(actor.entry(AuditAction::OverrideEnd));
+    json_ok(&OkResponse::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    params(
+        ("user" = Option<String>, Query, description = "Who did it"),
+        ("stream" = Option<String>, Query, description = "Stream id"),
+        ("action" = Option<AuditAction>, Query, description = "What they did, e.g. `pause`"),
+        ("since_ms" = Option<u64>, Query, description = "From this time on"),
+        ("until_ms" = Option<u64>, Query, description = "Before this time"),
+        ("limit" = Option<usize>, Query, description = "At most this many; 100 unless set, 1000 at most"),
+    ),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Matching entries, newest first. Only owners see everything; others see their streams' entries and their own", body = [AuditEntry]),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+async fn admin_audit(ctx: &Arc<HTTPServerContext>, user: User, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let filter = match AuditFilter::from_query(req.uri().query()) {
+        Ok(filter) => filter,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidBody, e),
     };
 
-    let entry_arc = match ctx.app.registry.read().await.get(stream_id).cloned() {
-        Some(e) => e,
-        None => return not_found(),
+    let everything = user.can(Permission::ManageUsers, Scope::AllStreams);
+    let visible = move |entry: &AuditEntry| {
+        everything
+            || entry.stream.as_ref().is_some_and(|s| user.can_access(s))
+            || entry.user.as_ref() == Some(&user.username)
     };
+    // The log is read front to back, so keep it off the async workers.
+    let app = ctx.app.clone();
+    match tokio::task::spawn_blocking(move || app.audit.query(&filter, visible)).await {
+        Ok(Ok(entries)) => json_ok(&entries),
+        Ok(Err(e)) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Internal, e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/me",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The signed-in user", body = UserInfo),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+fn admin_me(user: &User) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_ok(&user.info())
+}
 
-    // Update in-memory name and broadcast.
-    {
-        let mut entry = entry_arc.write().await;
-        entry.config.name = new_name.clone();
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Every admin user", body = [UserInfo]),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+    ),
+)]
+fn admin_list_users(ctx: &Arc<HTTPServerContext>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_ok(&ctx.app.auth.users.list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users",
+    request_body = CreateUserRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 201, description = "Created", body = UserInfo),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, description = "A granted stream doesn't exist", body = ErrorResponse),
+        (status = 409, description = "The username is taken", body = ErrorResponse),
+    ),
+)]
+async fn admin_create_user(ctx: &Arc<HTTPServerContext>, actor: &Actor, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: CreateUserRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let username = request.username.clone();
+    match control::create_user(&ctx.app, request).await {
+        Ok(info) => {
+            ctx.app.audit.record(actor.entry(AuditAction::UserCreate).target(&username).after(&info));
+            json_response(StatusCode::CREATED, &info)
+        },
+        Err(e) => control_error(e),
     }
-    let (player, metadata_tx, stream_name) = {
-        let e = entry_arc.read().await;
-        (e.player.clone(), e.metadata_tx.clone(), e.config.name.clone())
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{username}",
+    params(("username" = String, Path, description = "Username")),
+    request_body = UpdateUserRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Role and stream grants replaced", body = UserInfo),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "It would leave no owner", body = ErrorResponse),
+    ),
+)]
+async fn admin_update_user(ctx: &Arc<HTTPServerContext>, actor: &Actor, username: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: UpdateUserRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    if let Ok(json) = crate::ws_server::get_metadata_json(&player, Some(&stream_name), Some(stream_id)).await {
-        let _ = metadata_tx.send(json);
+
+    let before = ctx.app.auth.users.get(username).map(|u| u.info());
+    match control::update_user(&ctx.app, username, request).await {
+        Ok(info) => {
+            ctx.app.audit.record(actor.entry(AuditAction::UserUpdate).target(username).before(before).after(&info));
+            json_ok(&info)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{username}",
+    params(("username" = String, Path, description = "Username")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Deleted and signed out", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 409, description = "It would leave no owner", body = ErrorResponse),
+    ),
+)]
+fn admin_delete_user(ctx: &Arc<HTTPServerContext>, actor: &Actor, username: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let before = ctx.app.auth.users.get(username).map(|u| u.info());
+    match control::delete_user(&ctx.app, username) {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::UserDelete).target(username).before(before));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
     }
+}
 
-    // Persist to disk: snapshot the registry into a fresh StreamsConfig and save.
-    if let Err(e) = persist_config(&ctx.app).await {
-        eprintln!("Failed to persist config after rename: {}", e);
+#[utoipa::path(
+    post,
+    path = "/admin/users/{username}/password",
+    params(("username" = String, Path, description = "Username")),
+    request_body = PasswordRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Password changed; the user is signed out everywhere", body = OkResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_reset_password(ctx: &Arc<HTTPServerContext>, actor: &Actor, username: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let PasswordRequest { password } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::reset_password(&ctx.app, username, password).await {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::PasswordReset).target(username));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
     }
+}
 
-    cors_response(StatusCode::OK, full(r#"{"ok":true}"#))
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{username}/totp",
+    params(("username" = String, Path, description = "Username")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Two-factor login turned off for the user", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+fn admin_reset_totp(ctx: &Arc<HTTPServerContext>, actor: &Actor, username: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::reset_totp(&ctx.app, username) {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::TotpReset).target(username).before(true).after(false));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
 }
 
-async fn persist_config(app: &AppState) -> Result<(), String> {
-    let registry = app.registry.read().await;
-    let mut streams = Vec::with_capacity(registry.len());
-    for (_, entry_arc) in registry.iter() {
-        let entry = entry_arc.read().await;
-        streams.push(entry.config.clone());
+#[utoipa::path(
+    post,
+    path = "/admin/me/totp",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "A new secret; two-factor login is on once confirmed with a code from it", body = TotpSetupResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Called with an API token", body = ErrorResponse),
+        (status = 409, description = "Two-factor login is already on", body = ErrorResponse),
+    ),
+)]
+fn admin_totp_begin(ctx: &Arc<HTTPServerContext>, user: &User) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::begin_totp(&ctx.app, &user.username) {
+        Ok(setup) => json_ok(&setup),
+        Err(e) => control_error(e),
     }
-    drop(registry);
-    let cfg = crate::config::StreamsConfig {
-        default_stream: app.default_stream.clone(),
-        streams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/me/totp/confirm",
+    request_body = TotpCodeRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Two-factor login is on; the recovery codes are only shown in this response", body = RecoveryCodesResponse),
+        (status = 400, description = "The code doesn't match", body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Called with an API token", body = ErrorResponse),
+        (status = 409, description = "No setup in progress", body = ErrorResponse),
+    ),
+)]
+async fn admin_totp_confirm(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let TotpCodeRequest { code } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::confirm_totp(&ctx.app, &user.username, &code) {
+        Ok(codes) => {
+            ctx.app.audit.record(actor.entry(AuditAction::TotpEnable).target(&user.username).before(false).after(true));
+            json_ok(&codes)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/me/totp",
+    request_body = TotpCodeRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Two-factor login is off", body = OkResponse),
+        (status = 400, description = "The code doesn't match", body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Called with an API token", body = ErrorResponse),
+        (status = 409, description = "Two-factor login is already off", body = ErrorResponse),
+    ),
+)]
+async fn admin_totp_disable(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let TotpCodeRequest { code } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::disable_totp(&ctx.app, &user.username, &code) {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::TotpDisable).target(&user.username).before(true).after(false));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/tokens",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The user's API tokens; everyone's for owners", body = [ApiTokenInfo]),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Called with an API token", body = ErrorResponse),
+    ),
+)]
+fn admin_list_tokens(ctx: &Arc<HTTPServerContext>, user: &User) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_ok(&control::list_tokens(&ctx.app, user))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/tokens",
+    request_body = CreateTokenRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 201, description = "Created; the token is only shown in this response", body = CreateTokenResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "A role or stream the user doesn't have, or called with an API token", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_create_token(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: CreateTokenRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::create_token(&ctx.app, user, request).await {
+        Ok(created) => {
+            ctx.app.audit.record(actor.entry(AuditAction::TokenCreate).target(&created.info.id).after(&created.info));
+            json_response(StatusCode::CREATED, &created)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/tokens/{id}",
+    params(("id" = String, Path, description = "Token id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Revoked; requests with it fail from now on", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "Called with an API token", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+fn admin_revoke_token(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::revoke_token(&ctx.app, user, id) {
+        Ok(token) => {
+            ctx.app.audit.record(actor.entry(AuditAction::TokenRevoke).target(id).before(&token));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/sessions",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "The user's signed-in sessions, most recently used first; everyone's for owners", body = [SessionInfo]),
+        (status = 401, body = ErrorResponse),
+    ),
+)]
+fn admin_list_sessions(ctx: &Arc<HTTPServerContext>, user: &User, req: &Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let current = extract_token(req);
+    json_ok(&control::list_sessions(&ctx.app, user, current.as_deref()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/sessions/{id}",
+    params(("id" = String, Path, description = "Session id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Signed out; the session's cookie no longer works", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+fn admin_revoke_session(ctx: &Arc<HTTPServerContext>, actor: &Actor, user: &User, id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::revoke_session(&ctx.app, user, id) {
+        Ok(session) => {
+            ctx.app.audit.record(actor.entry(AuditAction::SessionRevoke).target(id).before(&session));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/listeners",
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Listener logins and tokens for private streams, without secrets", body = ListenersResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+    ),
+)]
+fn admin_list_listeners(ctx: &Arc<HTTPServerContext>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_ok(&ctx.app.auth.listeners.list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/listeners",
+    request_body = CreateListenerRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 201, description = "Created, or replaced the listener with that name", body = ListenerInfo),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, description = "A listed stream doesn't exist", body = ErrorResponse),
+    ),
+)]
+async fn admin_create_listener(ctx: &Arc<HTTPServerContext>, actor: &Actor, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: CreateListenerRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
     };
-    cfg.save(&app.config_path)
+
+    match control::create_listener(&ctx.app, request).await {
+        Ok(info) => {
+            ctx.app.audit.record(actor.entry(AuditAction::ListenerSave).target(&info.username).after(&info));
+            json_response(StatusCode::CREATED, &info)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/listeners/{username}",
+    params(("username" = String, Path, description = "Listener username")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Deleted; connected players keep playing until they reconnect", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+fn admin_delete_listener(ctx: &Arc<HTTPServerContext>, actor: &Actor, username: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::delete_listener(&ctx.app, username) {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::ListenerDelete).target(username));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/listener-tokens",
+    request_body = CreateListenerTokenRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 201, description = "Created; the token is only shown in this response", body = CreateListenerTokenResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, description = "A listed stream doesn't exist", body = ErrorResponse),
+    ),
+)]
+async fn admin_create_listener_token(ctx: &Arc<HTTPServerContext>, actor: &Actor, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let request: CreateListenerTokenRequest = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::create_listener_token(&ctx.app, request).await {
+        Ok(created) => {
+            ctx.app.audit.record(actor.entry(AuditAction::ListenerTokenCreate).target(&created.info.id).after(&created.info));
+            json_response(StatusCode::CREATED, &created)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/listener-tokens/{id}",
+    params(("id" = String, Path, description = "Token id")),
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "Revoked", body = OkResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+fn admin_revoke_listener_token(ctx: &Arc<HTTPServerContext>, actor: &Actor, id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    match control::revoke_listener_token(&ctx.app, id) {
+        Ok(()) => {
+            ctx.app.audit.record(actor.entry(AuditAction::ListenerTokenRevoke).target(id));
+            json_ok(&OkResponse::OK)
+        },
+        Err(e) => control_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/streams/{id}/signed-url",
+    params(("id" = String, Path, description = "Stream id")),
+    request_body = SignedUrlRequest,
+    security(("admin_session" = [])),
+    responses(
+        (status = 200, description = "A link that opens the stream without credentials until it expires", body = SignedUrlResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+    ),
+)]
+async fn admin_signed_url(ctx: &Arc<HTTPServerContext>, actor: &Actor, stream_id: &str, req: Request<body::Incoming>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let SignedUrlRequest { expires_in_secs } = match read_json(ctx, req).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    match control::signed_url(&ctx.app, stream_id, expires_in_secs).await {
+        Ok(signed) => {
+            ctx.app.audit.record(actor.entry(AuditAction::SignedUrl).stream(stream_id).after(signed.expires_at_ms));
+            json_ok(&signed)
+        },
+        Err(e) => control_error(e),
+    }
 }
 
 async fn get_player(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Option<OpusPlayerHandle> {
@@ -448,96 +2085,152 @@ async fn get_player(ctx: &Arc<HTTPServerContext>, stream_id: &str) -> Option<Opu
     Some(player)
 }
 
-fn require_session(ctx: &Arc<HTTPServerContext>, req: &Request<body::Incoming>) -> bool {
-    match extract_token(req) {
-        Some(token) => ctx.app.auth.validate(&token),
-        None => false,
+/// The signed-in user, if their role and stream grants allow `permission`
+/// on `scope`; otherwise the 401 or 403 to send.
+#[allow(clippy::result_large_err)]
+fn require_session(
+    ctx: &Arc<HTTPServerContext>,
+    req: &Request<body::Incoming>,
+    permission: Permission,
+    scope: Scope,
+) -> Result<User, Response<BoxBody<Bytes, hyper::Error>>> {
+    let user = extract_token(req)
+        .and_then(|token| ctx.app.auth.validate(&token))
+        .ok_or_else(unauthorized)?;
+    if !user.can(permission, scope) {
+        return Err(forbidden("your role or stream grants don't allow this"));
     }
+    Ok(user)
+}
+
+/// For private streams, the 401 or 429 to send unless the request carries
+/// credentials for the stream. Unknown streams are left to the handlers.
+async fn authorize_listener(ctx: &Arc<HTTPServerContext>, stream_id: &str, req: &Request<body::Incoming>) -> Result<(), Response<BoxBody<Bytes, hyper::Error>>> {
+    let Some(entry) = ctx.app.registry.read().await.get(stream_id).cloned() else {
+        return Ok(());
+    };
+    if entry.read().await.config.visibility != Visibility::Private {
+        return Ok(());
+    }
+    match ctx.app.auth.authorize_listener(stream_id, &ListenerCredentials::from_request(req, true)).await {
+        Ok(()) => Ok(()),
+        Err(Denied::Throttled(wait)) => Err(too_many_attempts(wait)),
+        Err(Denied::Unauthorized) => {
+            let mut response = error_response(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "this stream is private");
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static(BASIC_REALM));
+            Err(response)
+        },
+    }
+}
+
+/// Reads and parses a JSON body of at most `MAX_BODY_BYTES`, arriving
+/// within `BODY_TIMEOUT_SECS`, or returns the error response to send.
+async fn read_json<T: DeserializeOwned>(ctx: &Arc<HTTPServerContext>, req: Request<body::Incoming>) -> Result<T, Response<BoxBody<Bytes, hyper::Error>>> {
+    let limits = &ctx.app.limits;
+    let collected = tokio::time::timeout(limits.body_timeout, Limited::new(req.into_body(), limits.max_body_bytes).collect()).await;
+    let body = match collected {
+        Ok(Ok(c)) => c.to_bytes(),
+        Ok(Err(e)) if e.is::<LengthLimitError>() => {
+            let message = format!("request body is larger than {} bytes", limits.max_body_bytes);
+            return Err(error_response(StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::BodyTooLarge, message));
+        },
+        Ok(Err(e)) => return Err(error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidBody, e.to_string())),
+        Err(_) => return Err(error_response(StatusCode::REQUEST_TIMEOUT, ErrorCode::RequestTimeout, "request body timed out")),
+    };
+    serde_json::from_slice(&body)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, ErrorCode::InvalidBody, e.to_string()))
+}
+
+fn control_error(e: ControlError) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let status = match e.code {
+        // A wrong code while signed in isn't a reason to sign in again;
+        // logins answer it with a 401 themselves.
+        ErrorCode::InvalidBody | ErrorCode::FileNotFound | ErrorCode::InvalidMessage | ErrorCode::InvalidConfig | ErrorCode::InvalidTotp => StatusCode::BAD_REQUEST,
+        ErrorCode::InvalidPassword | ErrorCode::Unauthorized | ErrorCode::TotpRequired => StatusCode::UNAUTHORIZED,
+        ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+        ErrorCode::NotFound | ErrorCode::UnknownStream => StatusCode::NOT_FOUND,
+        ErrorCode::Conflict | ErrorCode::UnsupportedVersion => StatusCode::CONFLICT,
+        ErrorCode::RateLimited | ErrorCode::TooManyConnections => StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::ServerFull => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+        ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, e.code, e.message)
+}
+
+/// 429 with a `Retry-After` in whole seconds, rounded up.
+fn too_many_attempts(wait: Duration) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let mut response = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::RateLimited,
+        format!("too many failed logins, try again in {} s", secs),
+    );
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    response
+}
+
+/// 429 for a client over `MAX_CONNECTIONS_PER_IP`.
+fn too_many_connections() -> Response<BoxBody<Bytes, hyper::Error>> {
+    error_response(StatusCode::TOO_MANY_REQUESTS, ErrorCode::TooManyConnections, "too many open connections")
+}
+
+/// 503 for a listener turned away at a listener cap.
+fn server_full(error: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let mut response = error_response(StatusCode::SERVICE_UNAVAILABLE, ErrorCode::ServerFull, error);
+    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(SERVER_FULL_RETRY_SECS));
+    response
+}
+
+fn client_ip<B>(req: &Request<B>) -> Option<IpAddr> {
+    req.extensions().get::<ClientIp>().map(|ip| ip.0)
 }
 
 fn unauthorized() -> Response<BoxBody<Bytes, hyper::Error>> {
-    cors_response(StatusCode::UNAUTHORIZED, full(r#"{"error":"unauthorized"}"#))
+    error_response(StatusCode::UNAUTHORIZED, ErrorCode::Unauthorized, "unauthorized")
+}
+
+fn forbidden(error: impl Into<String>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    error_response(StatusCode::FORBIDDEN, ErrorCode::Forbidden, error)
 }
 
 fn not_found() -> Response<BoxBody<Bytes, hyper::Error>> {
-    cors_response(StatusCode::NOT_FOUND, empty())
+    error_response(StatusCode::NOT_FOUND, ErrorCode::NotFound, "not found")
+}
+
+fn unknown_stream(stream_id: &str) -> Response<BoxBody<Bytes, hyper::Error>> {
+    error_response(StatusCode::NOT_FOUND, ErrorCode::UnknownStream, format!("unknown stream: {}", stream_id))
+}
+
+fn error_response(status: StatusCode, code: ErrorCode, error: impl Into<String>) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_response(status, &ErrorResponse { error: error.into(), code })
 }
 
+/// The origin, methods and headers are filled in by `apply_cors_headers`,
+/// like on every other response.
 fn cors_preflight() -> Response<BoxBody<Bytes, hyper::Error>> {
     let mut response = Response::new(empty());
     *response.status_mut() = StatusCode::NO_CONTENT;
-    let h = response.headers_mut();
-    h.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    h.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, PATCH, OPTIONS"));
-    h.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("Content-Type, Authorization"));
-    h.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+    response.headers_mut().insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(cors::PREFLIGHT_MAX_AGE));
     response
 }
 
-fn cors_response(status: StatusCode, body: BoxBody<Bytes, hyper::Error>) -> Response<BoxBody<Bytes, hyper::Error>> {
+fn json_body_response(status: StatusCode, body: BoxBody<Bytes, hyper::Error>) -> Response<BoxBody<Bytes, hyper::Error>> {
     Response::builder()
         .status(status)
-        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-        .header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
         .header(header::CONTENT_TYPE, "application/json")
         .body(body)
         .expect("Should build response")
 }
 
-fn json_ok(body: String) -> Response<BoxBody<Bytes, hyper::Error>> {
-    cors_response(StatusCode::OK, full(body))
-}
-
-fn json_escape(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '"' => out.push_str("\\\""),
-            '\\' => out.push_str("\\\\"),
-            '\n' => out.push_str("\\n"),
-            '\r' => out.push_str("\\r"),
-            '\t' => out.push_str("\\t"),
-            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
-            c => out.push(c),
-        }
-    }
-    out
-}
-
-/// Minimal JSON string-field extractor. Looks for `"field":"value"` in the body.
-/// Handles backslash escapes for `\"` and `\\`. Returns None if not found.
-fn extract_json_string_field(body: &[u8], field: &str) -> Option<String> {
-    let s = std::str::from_utf8(body).ok()?;
-    let needle = format!("\"{}\"", field);
-    let start = s.find(&needle)? + needle.len();
-    let rest = &s[start..];
-    let colon = rest.find(':')? + 1;
-    let rest = &rest[colon..];
-    let trimmed = rest.trim_start();
-    let bytes = trimmed.as_bytes();
-    if bytes.first().copied() != Some(b'"') {
-        return None;
-    }
-    let mut out = String::new();
-    let mut iter = trimmed[1..].chars();
-    while let Some(c) = iter.next() {
-        match c {
-            '\\' => {
-                let next = iter.next()?;
-                match next {
-                    '"' => out.push('"'),
-                    '\\' => out.push('\\'),
-                    'n' => out.push('\n'),
-                    'r' => out.push('\r'),
-                    't' => out.push('\t'),
-                    other => out.push(other),
-                }
-            },
-            '"' => return Some(out),
-            other => out.push(other),
-        }
-    }
-    None
+fn json_response<T: Serialize>(status: StatusCode, value: &T) -> Response<BoxBody<Bytes, hyper::Error>> {
+    let body = serde_json::to_vec(value).expect("API types always serialize");
+    json_body_response(status, full(body))
+}
+
+fn json_ok<T: Serialize>(value: &T) -> Response<BoxBody<Bytes, hyper::Error>> {
+    json_response(StatusCode::OK, value)
 }
 
 fn empty() -> BoxBody<Bytes, hyper::Error> {
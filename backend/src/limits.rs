@@ -0,0 +1,107 @@
+use std::{
+    collections::HashMap,
+    env,
+    net::IpAddr,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex},
+    time::Duration,
+};
+
+use tracing::warn;
+
+const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Caps on connections, request bodies and audio listeners, from the
+/// environment. Per-stream listener caps are in the stream config.
+#[derive(Debug)]
+pub struct Limits {
+    /// `MAX_LISTENERS`: audio listeners across every stream.
+    pub max_listeners: Option<usize>,
+    /// `MAX_CONNECTIONS_PER_IP`: open connections from one address. Through
+    /// a trusted proxy, each client's open requests and sockets count
+    /// instead of the proxy's connections.
+    pub max_connections_per_ip: Option<usize>,
+    /// `HEADER_TIMEOUT_SECS`: how long a request's headers may take.
+    pub header_timeout: Duration,
+    /// `BODY_TIMEOUT_SECS`: how long an admin request's body may take.
+    pub body_timeout: Duration,
+    /// `MAX_BODY_BYTES`
+    pub max_body_bytes: usize,
+    listeners: Arc<AtomicUsize>,
+    connections: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// A positive whole number from the environment variable `name`. Anything
+/// else is ignored with a warning, leaving the default.
+pub fn positive_env(name: &str) -> Option<u64> {
+    let v = env::var(name).ok()?;
+    match v.parse::<u64>() {
+        Ok(n) if n > 0 => Some(n),
+        _ => {
+            warn!("Ignoring invalid {} {:?}", name, v);
+            None
+        },
+    }
+}
+
+impl Limits {
+    pub fn from_env() -> Self {
+        Self {
+            max_listeners: positive_env("MAX_LISTENERS").map(|n| n as usize),
+            max_connections_per_ip: positive_env("MAX_CONNECTIONS_PER_IP").map(|n| n as usize),
+            header_timeout: positive_env("HEADER_TIMEOUT_SECS").map_or(DEFAULT_HEADER_TIMEOUT, Duration::from_secs),
+            body_timeout: positive_env("BODY_TIMEOUT_SECS").map_or(DEFAULT_BODY_TIMEOUT, Duration::from_secs),
+            max_body_bytes: positive_env("MAX_BODY_BYTES").map_or(DEFAULT_MAX_BODY_BYTES, |n| n as usize),
+            listeners: Arc::default(),
+            connections: Arc::default(),
+        }
+    }
+
+    /// Counts a connection from `ip` until the slot is dropped, unless the
+    /// address already has `MAX_CONNECTIONS_PER_IP` open.
+    pub fn connection(&self, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut connections = self.connections.lock().expect("connections lock");
+        let open = connections.entry(ip).or_insert(0);
+        if self.max_connections_per_ip.is_some_and(|max| *open >= max) {
+            return None;
+        }
+        *open += 1;
+        Some(ConnectionSlot { ip, connections: self.connections.clone() })
+    }
+
+    /// Counts an audio listener until the slot is dropped, unless there are
+    /// `MAX_LISTENERS` already.
+    pub fn listener(&self) -> Option<ListenerSlot> {
+        let max = self.max_listeners.unwrap_or(usize::MAX);
+        self.listeners
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < max).then_some(n + 1))
+            .ok()
+            .map(|_| ListenerSlot(self.listeners.clone()))
+    }
+}
+
+pub struct ConnectionSlot {
+    ip: IpAddr,
+    connections: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().expect("connections lock");
+        if let Some(open) = connections.get_mut(&self.ip) {
+            *open -= 1;
+            if *open == 0 {
+                connections.remove(&self.ip);
+            }
+        }
+    }
+}
+
+pub struct ListenerSlot(Arc<AtomicUsize>);
+
+impl Drop for ListenerSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{net::IpAddr, path::Path, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use hyper::{header, Request};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+use crate::{
+    api::{ListenerInfo, ListenerTokenInfo, ListenersResponse},
+    auth::{extract_handshake_token, hex, random_hex},
+    proxy::ClientIp,
+    store::TomlStore,
+    tokens::hash_secret,
+    users::{dummy_hash, verify_password},
+};
+
+/// Marks a credential as a listener token rather than an admin one.
+pub const LISTENER_TOKEN_PREFIX: &str = "lst_";
+
+/// Sent with 401s so players and browsers ask for a login.
+pub const BASIC_REALM: &str = "Basic realm=\"Octopus\", charset=\"UTF-8\"";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Listener {
+    pub username: String,
+    pub password_hash: String,
+    /// Every private stream when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+}
+
+impl Listener {
+    pub fn info(&self) -> ListenerInfo {
+        ListenerInfo { username: self.username.clone(), streams: self.streams.clone() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListenerToken {
+    pub id: String,
+    pub name: String,
+    /// SHA-256 of the secret part.
+    pub secret_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    pub created_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+}
+
+impl ListenerToken {
+    pub fn info(&self) -> ListenerTokenInfo {
+        ListenerTokenInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            streams: self.streams.clone(),
+            created_at_ms: self.created_at_ms,
+            expires_at_ms: self.expires_at_ms,
+        }
+    }
+}
+
+fn allows(streams: &Option<Vec<String>>, stream_id: &str) -> bool {
+    streams.as_ref().is_none_or(|s| s.iter().any(|id| id == stream_id))
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ListenersFile {
+    /// Key for signed URLs, made on first use unless `LISTENER_URL_SECRET`
+    /// is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url_secret: Option<String>,
+    #[serde(default, rename = "listener")]
+    listeners: Vec<Listener>,
+    #[serde(default, rename = "token")]
+    tokens: Vec<ListenerToken>,
+}
+
+/// Why a private stream turned a request away.
+pub enum Denied {
+    Unauthorized,
+    /// Too many wrong basic auth passwords.
+    Throttled(Duration),
+}
+
+/// Whatever a request offered for a private stream: an admin session or
+/// token, a listener token, basic auth, or a signed URL's `expires` and
+/// `sig`.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerCredentials {
+    token: Option<String>,
+    basic: Option<(String, String)>,
+    signature: Option<(u64, String)>,
+    pub ip: Option<IpAddr>,
+}
+
+impl ListenerCredentials {
+    /// `cookie` is false for requests from origins that may not use the
+    /// admin session cookie.
+    pub fn from_request<B>(req: &Request<B>, cookie: bool) -> Self {
+        let basic = req.headers().get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Basic "))
+            .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|pair| pair.split_once(':').map(|(u, p)| (u.to_string(), p.to_string())));
+        let param = |name: &str| {
+            req.uri().query()?.split('&').find_map(|kv| kv.strip_prefix(name)?.strip_prefix('=')).map(str::to_string)
+        };
+        let signature = param("expires").and_then(|e| e.parse().ok()).zip(param("sig"));
+        Self {
+            token: extract_handshake_token(req, cookie),
+            basic,
+            signature,
+            ip: req.extensions().get::<ClientIp>().map(|ip| ip.0),
+        }
+    }
+
+    /// The admin session or token, or listener token.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    pub fn basic(&self) -> Option<(&str, &str)> {
+        self.basic.as_ref().map(|(u, p)| (u.as_str(), p.as_str()))
+    }
+
+    pub fn signature(&self) -> Option<(u64, &str)> {
+        self.signature.as_ref().map(|(expires, sig)| (*expires, sig.as_str()))
+    }
+}
+
+/// Who may listen to private streams besides admins, kept in a TOML file
+/// next to the users file.
+pub struct ListenerStore {
+    /// From `LISTENER_URL_SECRET`; overrides the file's.
+    url_secret: Option<String>,
+    data: TomlStore<ListenersFile>,
+}
+
+impl ListenerStore {
+    /// A missing file loads as no listeners.
+    pub fn load(path: &Path, url_secret: Option<String>) -> Result<Self, String> {
+        Ok(Self { url_secret, data: TomlStore::load(path, "listeners")? })
+    }
+
+    /// The listener, if `password` is theirs and they may hear `stream_id`.
+    /// Slow on purpose, whether or not the username exists.
+    pub async fn authenticate(&self, username: &str, password: &str, stream_id: &str) -> Option<Listener> {
+        let listener = self.data.lock().listeners.iter().find(|l| l.username == username).cloned();
+        let hash = listener.as_ref().map_or_else(|| dummy_hash().to_string(), |l| l.password_hash.clone());
+        let password = password.to_string();
+        let valid = tokio::task::spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false);
+        listener.filter(|l| valid && allows(&l.streams, stream_id))
+    }
+
+    /// Whether `lst_<id>_<secret>` is a live token for `stream_id`.
+    pub fn verify_token(&self, token: &str, stream_id: &str, now_ms: u64) -> bool {
+        let Some((id, secret)) = token.strip_prefix(LISTENER_TOKEN_PREFIX).and_then(|t| t.split_once('_')) else {
+            return false;
+        };
+        let data = self.data.lock();
+        let Some(token) = data.tokens.iter().find(|t| t.id == id) else {
+            return false;
+        };
+        let matches: bool = hash_secret(secret).as_bytes().ct_eq(token.secret_hash.as_bytes()).into();
+        matches && token.expires_at_ms.is_none_or(|at| now_ms < at) && allows(&token.streams, stream_id)
+    }
+
+    /// `expires` and `sig` query parameters for `stream_id`, good until
+    /// `expires` (Unix seconds).
+    pub fn sign(&self, stream_id: &str, expires: u64) -> Result<String, String> {
+        let secret = match self.url_secret() {
+            Some(secret) => secret,
+            None => {
+                let secret = random_hex(32);
+                self.update(|data| data.url_secret = Some(secret.clone()))?;
+                info!("Created the signed URL key in {:?}", self.data.path());
+                secret
+            },
+        };
+        Ok(format!("expires={}&sig={}", expires, signature(&secret, stream_id, expires)))
+    }
+
+    pub fn verify_signature(&self, stream_id: &str, expires: u64, sig: &str, now_secs: u64) -> bool {
+        let Some(secret) = self.url_secret() else {
+            return false;
+        };
+        let expected = signature(&secret, stream_id, expires);
+        now_secs < expires && bool::from(expected.as_bytes().ct_eq(sig.as_bytes()))
+    }
+
+    fn url_secret(&self) -> Option<String> {
+        self.url_secret.clone().or_else(|| self.data.lock().url_secret.clone())
+    }
+
+    pub fn list(&self) -> ListenersResponse {
+        let data = self.data.lock();
+        ListenersResponse {
+            listeners: data.listeners.iter().map(Listener::info).collect(),
+            tokens: data.tokens.iter().map(ListenerToken::info).collect(),
+        }
+    }
+
+    pub fn insert_listener(&self, listener: Listener) -> Result<(), String> {
+        self.update(|data| {
+            data.listeners.retain(|l| l.username != listener.username);
+            data.listeners.push(listener);
+            data.listeners.sort_by(|a, b| a.username.cmp(&b.username));
+        })
+    }
+
+    /// False if there was no such listener.
+    pub fn remove_listener(&self, username: &str) -> Result<bool, String> {
+        let mut removed = false;
+        self.update(|data| {
+            let before = data.listeners.len();
+            data.listeners.retain(|l| l.username != username);
+            removed = data.listeners.len() < before;
+        })?;
+        Ok(removed)
+    }
+
+    pub fn insert_token(&self, token: ListenerToken) -> Result<(), String> {
+        self.update(|data| data.tokens.push(token))
+    }
+
+    /// False if there was no such token.
+    pub fn remove_token(&self, id: &str) -> Result<bool, String> {
+        let mut removed = false;
+        self.update(|data| {
+            let before = data.tokens.len();
+            data.tokens.retain(|t| t.id != id);
+            removed = data.tokens.len() < before;
+        })?;
+        Ok(removed)
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ListenersFile)) -> Result<(), String> {
+        self.data.update(|data| {
+            change(data);
+            Ok(())
+        })
+    }
+}
+
+/// HMAC-SHA256 over the stream and expiry, hex-encoded.
+fn signature(secret: &str, stream_id: &str, expires: u64) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+    mac.update(format!("{}:{}", stream_id, expires).as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(secret: &str) -> ListenerStore {
+        ListenerStore::load(Path::new("/nonexistent/listeners.toml"), Some(secret.to_string())).unwrap()
+    }
+
+    fn sig(query: &str) -> (u64, String) {
+        let (expires, sig) = query.split_once('&').unwrap();
+        (expires.strip_prefix("expires=").unwrap().parse().unwrap(), sig.strip_prefix("sig=").unwrap().to_string())
+    }
+
+    #[test]
+    fn signed_urls_verify_until_they_expire() {
+        let store = store("key");
+        let (expires, sig) = sig(&store.sign("main", 1_000).unwrap());
+        assert_eq!(expires, 1_000);
+        assert!(store.verify_signature("main", expires, &sig, 999));
+        assert!(!store.verify_signature("main", expires, &sig, 1_000));
+        assert!(!store.verify_signature("main", expires, &sig, 5_000));
+    }
+
+    #[test]
+    fn rejects_tampered_signatures() {
+        let signer = store("key");
+        let (expires, sig) = sig(&signer.sign("main", 1_000).unwrap());
+        assert!(!signer.verify_signature("other", expires, &sig, 0));
+        assert!(!signer.verify_signature("main", expires + 1, &sig, 0));
+        let mut flipped = sig.clone().into_bytes();
+        flipped[0] = if flipped[0] == b'0' { b'1' } else { b'0' };
+        assert!(!signer.verify_signature("main", expires, std::str::from_utf8(&flipped).unwrap(), 0));
+        assert!(!signer.verify_signature("main", expires, &sig[..sig.len() - 1], 0));
+        assert!(!signer.verify_signature("main", expires, "", 0));
+        assert!(!store("other key").verify_signature("main", expires, &sig, 0));
+    }
+
+    #[test]
+    fn listener_tokens_check_secret_expiry_and_streams() {
+        let store = store("key");
+        store.data.lock().tokens.push(ListenerToken {
+            id: "abc".to_string(),
+            name: "lobby".to_string(),
+            secret_hash: hash_secret("s3cret"),
+            streams: Some(vec!["members".to_string()]),
+            created_at_ms: 0,
+            expires_at_ms: Some(1_000),
+        });
+        assert!(store.verify_token("lst_abc_s3cret", "members", 999));
+        assert!(!store.verify_token("lst_abc_s3cret", "members", 1_000));
+        assert!(!store.verify_token("lst_abc_s3cret", "main", 0));
+        assert!(!store.verify_token("lst_abc_wrong", "members", 0));
+        assert!(!store.verify_token("lst_xyz_s3cret", "members", 0));
+        assert!(!store.verify_token("oct_abc_s3cret", "members", 0));
+    }
+}
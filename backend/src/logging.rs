@@ -0,0 +1,215 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+    net::IpAddr,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing_subscriber::EnvFilter;
+
+/// Target of access log lines, so they can be filtered or routed on their own.
+pub const ACCESS_TARGET: &str = "access";
+
+/// Whether `init` chose JSON output. Text output keeps access lines to the
+/// plain Combined Log Format.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Installs the global subscriber. `LOG_LEVEL` takes `RUST_LOG`-style
+/// directives such as `info,octopus_backend::opus_player=debug`, and
+/// `LOG_FORMAT=json` switches to one JSON object per line.
+pub fn init() {
+    let filter = env::var("LOG_LEVEL")
+        .or_else(|_| env::var("RUST_LOG"))
+        .ok()
+        .and_then(|directives| match EnvFilter::try_new(&directives) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                eprintln!("Ignoring invalid LOG_LEVEL {:?}: {}", directives, e);
+                None
+            }
+        })
+        .unwrap_or_else(|| EnvFilter::new("info"));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(io::stdout().is_terminal());
+    match env::var("LOG_FORMAT").as_deref() {
+        Ok("json") => {
+            JSON.store(true, Ordering::Relaxed);
+            builder.json().flatten_event(true).with_current_span(true).init()
+        },
+        _ => builder.init(),
+    }
+}
+
+/// One request as it appears in the access log.
+pub struct AccessEntry {
+    pub ip: Option<IpAddr>,
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub status: u16,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Counts a response's body bytes and writes its access log line when the
+/// body is dropped, i.e. once it was sent in full or the client went away.
+/// Long-lived audio responses are thus logged with their real size and
+/// duration when the listener disconnects.
+pub struct AccessLog {
+    entry: AccessEntry,
+    started_at: SystemTime,
+    started: Instant,
+    bytes: u64,
+}
+
+impl AccessLog {
+    pub fn new(entry: AccessEntry) -> Self {
+        Self { entry, started_at: SystemTime::now(), started: Instant::now(), bytes: 0 }
+    }
+
+    pub fn set_status(&mut self, status: u16) {
+        self.entry.status = status;
+    }
+
+    pub fn add_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        let entry = &self.entry;
+        let duration_ms = self.started.elapsed().as_millis() as u64;
+        let ip = entry.ip.map(|ip| ip.to_string());
+        // Combined Log Format, plus the duration in milliseconds.
+        let line = format!(
+            "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {}",
+            ip.as_deref().unwrap_or("-"),
+            clf_time(self.started_at),
+            escape(&entry.method),
+            escape(&entry.target),
+            entry.version,
+            entry.status,
+            self.bytes,
+            escape(entry.referer.as_deref().unwrap_or("-")),
+            escape(entry.user_agent.as_deref().unwrap_or("-")),
+            duration_ms,
+        );
+        if !JSON.load(Ordering::Relaxed) {
+            tracing::info!(target: ACCESS_TARGET, "{}", line);
+            return;
+        }
+        tracing::info!(
+            target: ACCESS_TARGET,
+            remote_addr = ip.as_deref(),
+            method = %entry.method,
+            path = %entry.target,
+            status = entry.status,
+            bytes = self.bytes,
+            user_agent = entry.user_agent.as_deref(),
+            duration_ms,
+            "{}",
+            line,
+        );
+    }
+}
+
+/// The request target with secrets passed in the query, such as a WebSocket
+/// `token` or a signed URL's `sig`, masked.
+pub fn redacted_target(path: &str, query: Option<&str>) -> String {
+    let query = match query {
+        Some(q) if !q.is_empty() => q,
+        _ => return path.to_string(),
+    };
+    let params: Vec<String> = query.split('&')
+        .map(|kv| match kv.split_once('=') {
+            Some((key, _)) if key == "token" || key == "sig" => format!("{}=REDACTED", key),
+            _ => kv.to_string(),
+        })
+        .collect();
+    format!("{}?{}", path, params.join("&"))
+}
+
+/// Escapes a quoted field the way Apache does: `"` and `\` get a
+/// backslash, and control and non-ASCII bytes become `\xhh`, so a request
+/// can't forge fields or lines.
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for &b in field.as_bytes() {
+        match b {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(b as char);
+            },
+            0x20..=0x7e => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    escaped
+}
+
+/// `10/Oct/2000:13:55:36 +0000`, always in UTC.
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> String {
+        clf_time(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn clf_time_handles_month_ends_and_leap_years() {
+        assert_eq!(at(0), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(at(971_193_336), "10/Oct/2000:15:55:36 +0000");
+        // 2000 is a leap year despite being a century; 1900 and 2100 aren't.
+        assert_eq!(at(951_782_399), "28/Feb/2000:23:59:59 +0000");
+        assert_eq!(at(951_782_400), "29/Feb/2000:00:00:00 +0000");
+        assert_eq!(at(951_868_800), "01/Mar/2000:00:00:00 +0000");
+        assert_eq!(at(1_709_164_800), "29/Feb/2024:00:00:00 +0000");
+        assert_eq!(at(1_677_628_800), "01/Mar/2023:00:00:00 +0000");
+        assert_eq!(at(4_107_542_400), "01/Mar/2100:00:00:00 +0000");
+        assert_eq!(at(1_704_067_199), "31/Dec/2023:23:59:59 +0000");
+        assert_eq!(at(1_704_067_200), "01/Jan/2024:00:00:00 +0000");
+        assert_eq!(at(1_719_791_999), "30/Jun/2024:23:59:59 +0000");
+    }
+
+    #[test]
+    fn escapes_quotes_backslashes_and_control_bytes() {
+        assert_eq!(escape("Mozilla/5.0 (X11)"), "Mozilla/5.0 (X11)");
+        assert_eq!(escape(r#"a" 200 1 "-" "forged"#), r#"a\" 200 1 \"-\" \"forged"#);
+        assert_eq!(escape(r"back\slash"), r"back\\slash");
+        assert_eq!(escape("line\nbreak\tté"), r"line\x0abreak\x09t\xc3\xa9");
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{AppState, config::StreamConfig, stats::now_ms};
+
+// Counters behind the Prometheus `/metrics` endpoint, rendered in the text
+// exposition format.
+
+/// Upper bounds of the HTTP latency histogram, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// Kept by each player, so shared by a stream and its simulcasts.
+#[derive(Debug, Default)]
+pub struct PlayerMetrics {
+    pub packets_encoded: AtomicU64,
+    pub encode_nanos: AtomicU64,
+    /// Listeners dropped because their channel was full or closed.
+    pub dropped_listeners: AtomicU64,
+    /// Packets not sent to listeners skipping ahead to catch up.
+    pub skipped_packets: AtomicU64,
+    /// Times a slow listener was moved to a lower bitrate.
+    pub bitrate_downgrades: AtomicU64,
+    /// Unix time in milliseconds; 0 until the first packet.
+    pub last_packet_at_ms: AtomicU64,
+}
+
+impl PlayerMetrics {
+    pub fn record_packet(&self) {
+        self.last_packet_at_ms.store(now_ms(), Ordering::Relaxed);
+    }
+
+    pub fn record_encode(&self, elapsed: Duration) {
+        self.packets_encoded.fetch_add(1, Ordering::Relaxed);
+        self.encode_nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StreamMetrics {
+    pub bytes_sent: AtomicU64,
+    pub track_changes: AtomicU64,
+    playback_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl StreamMetrics {
+    pub fn add_bytes_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_playback_error(&self, kind: &'static str) {
+        *self.playback_errors.lock().unwrap().entry(kind).or_default() += 1;
+    }
+}
+
+#[derive(Default)]
+struct RequestStats {
+    count: u64,
+    sum_seconds: f64,
+    /// Per bucket in `LATENCY_BUCKETS`, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+/// Process-wide metrics that don't belong to a stream.
+#[derive(Default)]
+pub struct Metrics {
+    /// Open HTTP connections, not counting ones upgraded to WebSockets.
+    pub http_connections: AtomicUsize,
+    pub ws_connections: AtomicUsize,
+    pub ws_connections_total: AtomicU64,
+    /// Keyed by (method, route, status).
+    requests: Mutex<BTreeMap<(&'static str, &'static str, u16), RequestStats>>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str, route: &'static str, status: u16, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut requests = self.requests.lock().unwrap();
+        let stats = requests.entry((method_label(method), route, status)).or_default();
+        stats.count += 1;
+        stats.sum_seconds += seconds;
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            stats.buckets[i] += 1;
+        }
+    }
+
+    /// Counts an open HTTP connection until the returned guard is dropped.
+    pub fn http_connected(self: &Arc<Self>) -> ConnectionGuard {
+        self.http_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: self.clone(), gauge: |m| &m.http_connections }
+    }
+
+    /// Counts an open WebSocket until the returned guard is dropped.
+    pub fn ws_connected(self: &Arc<Self>) -> ConnectionGuard {
+        self.ws_connections.fetch_add(1, Ordering::Relaxed);
+        self.ws_connections_total.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard { metrics: self.clone(), gauge: |m| &m.ws_connections }
+    }
+
+    /// Connections of either kind still open, which shutdown waits on.
+    pub fn open_connections(&self) -> usize {
+        self.http_connections.load(Ordering::Relaxed) + self.ws_connections.load(Ordering::Relaxed)
+    }
+}
+
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+    gauge: fn(&Metrics) -> &AtomicUsize,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        (self.gauge)(&self.metrics).fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Collapses extension methods into one label, so made-up methods don't
+/// create a label each.
+pub fn method_label(method: &str) -> &'static str {
+    match method {
+        "GET" => "GET",
+        "HEAD" => "HEAD",
+        "POST" => "POST",
+        "PUT" => "PUT",
+        "PATCH" => "PATCH",
+        "DELETE" => "DELETE",
+        "OPTIONS" => "OPTIONS",
+        _ => "other",
+    }
+}
+
+/// Collapses a request path to the route it matched, so ids don't create a
+/// label per stream or queue index.
+pub fn route_label(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["streams"] => "/streams",
+        ["streams", _] => "/streams/{id}",
+        ["streams", _, "audio"] => "/streams/{id}/audio",
+        ["streams", _, "events"] => "/streams/{id}/events",
+        ["streams", _, "playlist-image"] => "/streams/{id}/playlist-image",
+        ["ws"] => "/ws",
+        ["ws", "streams", _] => "/ws/streams/{id}",
+        ["openapi.json"] => "/openapi.json",
+        ["metrics"] => "/metrics",
+        ["healthz"] => "/healthz",
+        ["readyz"] => "/readyz",
+        ["admin", "login"] => "/admin/login",
+        ["admin", "logout"] => "/admin/logout",
+        ["admin", "me"] => "/admin/me",
+        ["admin", "me", "totp"] => "/admin/me/totp",
+        ["admin", "me", "totp", "confirm"] => "/admin/me/totp/confirm",
+        ["admin", "sessions"] => "/admin/sessions",
+        ["admin", "sessions", _] => "/admin/sessions/{id}",
+        ["admin", "tokens"] => "/admin/tokens",
+        ["admin", "tokens", _] => "/admin/tokens/{id}",
+        ["admin", "users"] => "/admin/users",
+        ["admin", "users", _] => "/admin/users/{username}",
+        ["admin", "users", _, "password"] => "/admin/users/{username}/password",
+        ["admin", "users", _, "totp"] => "/admin/users/{username}/totp",
+        ["admin", "listeners"] => "/admin/listeners",
+        ["admin", "listeners", _] => "/admin/listeners/{username}",
+        ["admin", "listener-tokens"] => "/admin/listener-tokens",
+        ["admin", "listener-tokens", _] => "/admin/listener-tokens/{id}",
+        ["admin", "stats"] => "/admin/stats",
+        ["admin", "reload"] => "/admin/reload",
+        ["admin", "audit"] => "/admin/audit",
+        ["admin", "override"] => "/admin/override",
+        ["admin", "streams"] => "/admin/streams",
+        ["admin", "streams", _] => "/admin/streams/{id}",
+        ["admin", "streams", _, "skip"] => "/admin/streams/{id}/skip",
+        ["admin", "streams", _, "pause"] => "/admin/streams/{id}/pause",
+        ["admin", "streams", _, "resume"] => "/admin/streams/{id}/resume",
+        ["admin", "streams", _, "seek"] => "/admin/streams/{id}/seek",
+        ["admin", "streams", _, "signed-url"] => "/admin/streams/{id}/signed-url",
+        ["admin", "streams", _, "stats"] => "/admin/streams/{id}/stats",
+        ["admin", "streams", _, "queue"] => "/admin/streams/{id}/queue",
+        ["admin", "streams", _, "queue", _] => "/admin/streams/{id}/queue/{index}",
+        _ => "other",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes one metric family: its HELP and TYPE lines, then every sample.
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+/// Per-stream series cover only the streams `show` accepts.
+pub async fn render(app: &AppState, show: impl Fn(&StreamConfig) -> bool) -> String {
+    let mut listeners = Vec::new();
+    let mut peak_listeners = Vec::new();
+    let mut bytes_sent = Vec::new();
+    let mut track_changes = Vec::new();
+    let mut playback_errors = Vec::new();
+    let mut packets_encoded = Vec::new();
+    let mut encode_seconds = Vec::new();
+    let mut dropped_listeners = Vec::new();
+    let mut skipped_packets = Vec::new();
+    let mut bitrate_downgrades = Vec::new();
+
+    let registry = app.registry.read().await;
+    let mut ids: Vec<&String> = registry.keys().collect();
+    ids.sort();
+    for id in ids {
+        let entry = registry[id].read().await;
+        if !show(&entry.config) {
+            continue;
+        }
+        let stream = format!("stream=\"{}\"", escape_label(id));
+        let (current, peak) = entry.audience.counts();
+        listeners.push((stream.clone(), current as f64));
+        peak_listeners.push((stream.clone(), peak as f64));
+        bytes_sent.push((stream.clone(), entry.metrics.bytes_sent.load(Ordering::Relaxed) as f64));
+        track_changes.push((stream.clone(), entry.metrics.track_changes.load(Ordering::Relaxed) as f64));
+        for (kind, count) in entry.metrics.playback_errors.lock().unwrap().iter() {
+            playback_errors.push((format!("{},kind=\"{}\"", stream, kind), *count as f64));
+        }
+
+        // Simulcasts share their source's player; report it once.
+        if entry.config.simulcast_of.is_none() {
+            let player = &entry.player.metrics;
+            packets_encoded.push((stream.clone(), player.packets_encoded.load(Ordering::Relaxed) as f64));
+            encode_seconds.push((stream.clone(), player.encode_nanos.load(Ordering::Relaxed) as f64 / 1e9));
+            dropped_listeners.push((stream.clone(), player.dropped_listeners.load(Ordering::Relaxed) as f64));
+            skipped_packets.push((stream.clone(), player.skipped_packets.load(Ordering::Relaxed) as f64));
+            bitrate_downgrades.push((stream, player.bitrate_downgrades.load(Ordering::Relaxed) as f64));
+        }
+    }
+    drop(registry);
+
+    let mut out = String::new();
+    family(&mut out, "octopus_listeners", "gauge", "Connected audio listeners.", &listeners);
+    family(&mut out, "octopus_peak_listeners", "gauge", "Most audio listeners connected at once since startup.", &peak_listeners);
+    family(&mut out, "octopus_bytes_sent_total", "counter", "Ogg bytes sent to audio listeners.", &bytes_sent);
+    family(&mut out, "octopus_track_changes_total", "counter", "Tracks started.", &track_changes);
+    family(&mut out, "octopus_playback_errors_total", "counter", "Sources that failed to play, by kind.", &playback_errors);
+    family(&mut out, "octopus_packets_encoded_total", "counter", "Opus packets encoded, across all bitrates.", &packets_encoded);
+    family(&mut out, "octopus_encode_seconds_total", "counter", "Time spent encoding Opus packets.", &encode_seconds);
+    family(&mut out, "octopus_dropped_listeners_total", "counter", "Listeners dropped because they could not keep up or went away.", &dropped_listeners);
+    family(&mut out, "octopus_skipped_packets_total", "counter", "Packets skipped for slow listeners catching up.", &skipped_packets);
+    family(&mut out, "octopus_bitrate_downgrades_total", "counter", "Slow listeners moved to a lower bitrate.", &bitrate_downgrades);
+
+    let metrics = &app.metrics;
+    family(&mut out, "octopus_http_connections", "gauge", "Open HTTP connections, excluding WebSockets.",
+        &[(String::new(), metrics.http_connections.load(Ordering::Relaxed) as f64)]);
+    family(&mut out, "octopus_ws_connections", "gauge", "Open WebSocket connections.",
+        &[(String::new(), metrics.ws_connections.load(Ordering::Relaxed) as f64)]);
+    family(&mut out, "octopus_ws_connections_total", "counter", "WebSocket connections accepted.",
+        &[(String::new(), metrics.ws_connections_total.load(Ordering::Relaxed) as f64)]);
+    family(&mut out, "octopus_admin_sessions", "gauge", "Unexpired admin sessions.",
+        &[(String::new(), app.auth.session_count() as f64)]);
+
+    let requests = metrics.requests.lock().unwrap();
+    let mut request_counts = Vec::new();
+    // Latency is aggregated over status codes.
+    let mut latency: BTreeMap<(&str, &str), RequestStats> = BTreeMap::new();
+    for ((method, route, status), stats) in requests.iter() {
+        request_counts.push((
+            format!("method=\"{}\",route=\"{}\",status=\"{}\"", method, route, status),
+            stats.count as f64,
+        ));
+        let merged = latency.entry((method, route)).or_default();
+        merged.count += stats.count;
+        merged.sum_seconds += stats.sum_seconds;
+        for (total, n) in merged.buckets.iter_mut().zip(stats.buckets) {
+            *total += n;
+        }
+    }
+    family(&mut out, "octopus_http_requests_total", "counter", "HTTP requests, by route and status.", &request_counts);
+
+    let _ = writeln!(out, "# HELP octopus_http_request_duration_seconds Time until the response headers were ready.");
+    let _ = writeln!(out, "# TYPE octopus_http_request_duration_seconds histogram");
+    for ((method, route), stats) in &latency {
+        let labels = format!("method=\"{}\",route=\"{}\"", escape_label(method), route);
+        let mut cumulative = 0;
+        for (le, n) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+            cumulative += n;
+            let _ = writeln!(out, "octopus_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, le, cumulative);
+        }
+        let _ = writeln!(out, "octopus_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, stats.count);
+        let _ = writeln!(out, "octopus_http_request_duration_seconds_sum{{{}}} {}", labels, stats.sum_seconds);
+        let _ = writeln!(out, "octopus_http_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_methods_share_one_label() {
+        let metrics = Metrics::default();
+        for method in ["GET", "FOO1", "FOO2", "get"] {
+            metrics.record_request(method, "/", 200, Duration::ZERO);
+        }
+        let requests = metrics.requests.lock().unwrap();
+        let methods: Vec<&str> = requests.keys().map(|(method, _, _)| *method).collect();
+        assert_eq!(methods, ["GET", "other"]);
+        assert_eq!(requests[&("other", "/", 200)].count, 3);
+    }
+}
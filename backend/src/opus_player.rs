@@ -1,10 +1,12 @@
-use std::{fs::File, io::{BufReader, Seek}, path::Path, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+use std::{collections::{hash_map::Entry, HashMap}, f32::consts::TAU, fs::File, sync::{atomic::Ordering, Arc, Mutex}, io::{BufReader, Seek, SeekFrom}, path::Path, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 use ogg::{reading::PacketReader};
-use opus::{Application, Channels, Decoder as OpusDecoder, Encoder as OpusEncoder};
+use opus::{Application, Bitrate, Channels, Decoder as OpusDecoder, Encoder as OpusEncoder};
 
-use tokio::{sync::{mpsc, oneshot}, task, time::sleep};
+use tokio::{sync::{mpsc, oneshot}, task, time::{sleep, timeout}};
 
-use crate::oeggs::get_opus_comments;
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::{config::SlowListenerPolicy, metrics::PlayerMetrics, oeggs::get_opus_comments};
 
 pub const SAMPLE_RATE: u32 = 48_000;
 pub const CHANNELS: usize = 2;
@@ -12,6 +14,16 @@ pub const CHANNELS: usize = 2;
 pub const BUFFER_SIZE_MS: usize = 3000; // 5 seconds buffer
 const MAX_HEADSTART_BUFFER_SIZE: usize = ((BUFFER_SIZE_MS as f32 / 1000f32) * SAMPLE_RATE as f32 * CHANNELS as f32) as usize;
 
+// Frames whose peak sample stays at or below this (~-54 dBFS) count as silence.
+const SILENCE_PEAK: u16 = 64;
+// A relay that produces nothing for this long is treated as a failed source.
+const RELAY_STALL_TIMEOUT: Duration = Duration::from_secs(5);
+// Samples per channel in one 20ms generated frame.
+const GENERATED_FRAME_SIZE: usize = 960;
+const TONE_AMPLITUDE: f32 = 0.1 * i16::MAX as f32;
+// Opus needs ~80ms of decoded audio before a seek target to converge.
+const SEEK_PRE_ROLL_SAMPLES: u64 = SAMPLE_RATE as u64 * 80 / 1000;
+
 pub const OPUS_HEAD: &[u8] = &[
     // "OpusHead"
     0x4F, 0x70, 0x75, 0x73, 0x48, 0x65, 0x61, 0x64,
@@ -56,12 +68,69 @@ fn generate_serial() -> u32 {
         .subsec_nanos()
 }
 
+/// Where a player pulls audio from.
+#[derive(Debug)]
+pub enum PlaybackSource {
+    /// An Ogg Opus file on disk.
+    File(String),
+    /// Another stream's output, fed through a listener channel registered on
+    /// that stream's player.
+    Relay {
+        feed: mpsc::Receiver<OpusPlayerEvent>,
+        label: String,
+    },
+    /// A generated sine tone; a frequency of zero produces silence.
+    Tone { frequency_hz: f32 },
+}
+
+enum PlaybackInput {
+    File {
+        packet_reader: PacketReader<BufReader<File>>,
+        // Samples the decoder discards at the start, from the OpusHead header.
+        pre_skip: u64,
+    },
+    Relay(mpsc::Receiver<OpusPlayerEvent>),
+    Tone(ToneGenerator),
+}
+
+struct ToneGenerator {
+    frequency_hz: f32,
+    phase: f32,
+}
+
+impl ToneGenerator {
+    fn fill(&mut self, buf: &mut [i16]) -> usize {
+        let step = TAU * self.frequency_hz / SAMPLE_RATE as f32;
+        for frame in buf[..GENERATED_FRAME_SIZE * CHANNELS].chunks_exact_mut(CHANNELS) {
+            let sample = if self.frequency_hz > 0.0 {
+                (self.phase.sin() * TONE_AMPLITUDE) as i16
+            } else {
+                0
+            };
+            frame.fill(sample);
+            self.phase = (self.phase + step) % TAU;
+        }
+        GENERATED_FRAME_SIZE
+    }
+}
+
 pub struct PlaybackState {
-    packet_reader: PacketReader<BufReader<File>>,
+    input: PlaybackInput,
     opus_decoder: OpusDecoder,
     opus_encoder: OpusEncoder,
     decode_buf: Vec<i16>,
     file_id: u64,
+    detect_silence: bool,
+    played_samples: u64,
+    limit_samples: Option<u64>,
+}
+
+/// What happened after processing a single packet.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PacketOutcome {
+    More,
+    Finished,
+    Silent,
 }
 
 #[derive(Debug)]
@@ -74,47 +143,80 @@ pub enum OpusPlayerEvent {
 
 #[derive(Debug, Clone)]
 pub struct TimeData {
-    pub start_time_ms: u64,
+    // Negative when playback started partway into a track before the stream
+    // timeline had advanced that far.
+    pub start_time_ms: i64,
     pub current_time_ms: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct ActiveFileMetadata {
     pub id: u64,
-    pub start_granule_position: u64,
+    pub start_granule_position: i64,
     pub title: String,
     pub author: String,
     pub image: Option<String>,
     pub duration_ms: u64,
 }
 
+struct Listener {
+    sender: mpsc::Sender<OpusPlayerEvent>,
+    // None uses the player's default encoder settings.
+    bitrate_kbps: Option<u32>,
+    slow: SlowListenerPolicy,
+    // Set once the listener fell behind; nothing is sent until its buffer
+    // has drained to half.
+    skipping: bool,
+}
+
+/// Bitrates a `lower_bitrate` listener steps down through, highest first.
+const SLOW_LISTENER_BITRATES: [u32; 4] = [96, 64, 48, 32];
+
 pub struct OpusPlayer {
     start_instant: Option<Instant>,
     headstart_buffer: Vec<i16>, // pcm data
     headstart_granule_position: u64, // granule position at the start of headstart_buffer
-    listeners: Vec<mpsc::Sender<OpusPlayerEvent>>,
+    listeners: Vec<Listener>,
+    // One encoder per bitrate rendition, shared by every listener on it. The
+    // Mutex only makes the player Sync for its `&self` async methods; the
+    // actor is the sole user and goes through `get_mut`.
+    rendition_encoders: HashMap<u32, Mutex<OpusEncoder>>,
     granule_position: u64,
     active_file: Option<ActiveFileMetadata>,
     current_playlist_path: Option<String>,
     paused: bool,
     pause_started_at: Option<Instant>,
+    silence_threshold: Option<Duration>,
+    silent_samples: u64,
+    last_file_id: u64,
+    metrics: Arc<PlayerMetrics>,
 }
 
 impl OpusPlayer {
-    pub fn new() -> Self {
+    pub fn new(metrics: Arc<PlayerMetrics>) -> Self {
         Self {
             start_instant: None,
             headstart_buffer: Vec::new(),
             headstart_granule_position: 0,
             listeners: vec![],
+            rendition_encoders: HashMap::new(),
             granule_position: 0,
             active_file: None,
             current_playlist_path: None,
             paused: false,
             pause_started_at: None,
+            silence_threshold: None,
+            silent_samples: 0,
+            last_file_id: 0,
+            metrics,
         }
     }
 
+    pub fn set_silence_threshold(&mut self, threshold: Option<Duration>) {
+        self.silence_threshold = threshold;
+        self.silent_samples = 0;
+    }
+
     pub async fn get_metadata(&self) -> Option<ActiveFileMetadata> {
         return self.active_file.clone();
     }
@@ -160,11 +262,11 @@ impl OpusPlayer {
             }
         };
 
-        let start_time_ms = active_file.start_granule_position as f64 / 48_000 as f64 * 1000.0;
+        let start_time_ms = active_file.start_granule_position as f64 / SAMPLE_RATE as f64 * 1000.0;
         let current_time_ms = self.granule_position as f64 / 48_000 as f64 * 1000.0;
 
         return TimeData {
-            start_time_ms: start_time_ms as u64,
+            start_time_ms: start_time_ms as i64,
             current_time_ms: (current_time_ms as u64).checked_sub(BUFFER_SIZE_MS as u64).unwrap_or(0),
         };
     }
@@ -190,26 +292,96 @@ impl OpusPlayer {
 
     pub async fn start_playback(
         &mut self,
-        path: &str,
+        source: PlaybackSource,
+        limit: Option<Duration>,
     ) -> Result<PlaybackState, String> {
+        let detect_silence = !matches!(source, PlaybackSource::Tone { .. });
+        let limit_samples = limit.map(|l| l.as_millis() as u64 * SAMPLE_RATE as u64 / 1000);
+        let limit_ms = limit.map(|l| l.as_millis() as u64).unwrap_or(0);
+
+        let (input, title, author, image, duration_ms) = match source {
+            PlaybackSource::File(path) => {
+                let (packet_reader, pre_skip, title, author, duration_ms) = self.open_file(&path).await?;
+                let input = PlaybackInput::File { packet_reader, pre_skip };
+                (input, title, author, Some("/playlist-image".to_string()), duration_ms)
+            },
+            PlaybackSource::Relay { feed, label } => {
+                info!("Relaying {}", label);
+                (PlaybackInput::Relay(feed), label, "Relay".to_string(), None, limit_ms)
+            },
+            PlaybackSource::Tone { frequency_hz } => {
+                let title = if frequency_hz > 0.0 {
+                    format!("Tone ({} Hz)", frequency_hz)
+                } else {
+                    "Silence".to_string()
+                };
+                info!("Generating {}", title);
+                let generator = ToneGenerator { frequency_hz, phase: 0.0 };
+                (PlaybackInput::Tone(generator), title, "Octopus".to_string(), None, limit_ms)
+            },
+        };
+
+        if self.start_instant.is_none() {
+            self.start_instant = Some(Instant::now());
+        }
+
+        // Generated sources are silent on purpose, so don't let them carry a
+        // half-filled silence counter into the next real source either.
+        if !detect_silence {
+            self.silent_samples = 0;
+        }
+
+        // Ids come from a counter rather than the active file so a track
+        // restored after an override never shares an id with the override.
+        self.last_file_id += 1;
+        let file_id = self.last_file_id;
+
+        self.active_file = Some(ActiveFileMetadata {
+            id: file_id,
+            start_granule_position: self.granule_position as i64,
+            title,
+            author,
+            image,
+            duration_ms,
+        });
+
+        let opus_decoder = OpusDecoder::new(SAMPLE_RATE, Channels::Stereo)
+            .map_err(|x| format!("Decoder {}", x))?;
+        let decode_buf = vec![0i16; 1920 * CHANNELS];
+
+        let opus_encoder = OpusEncoder::new(SAMPLE_RATE, Channels::Stereo, Application::Audio)
+            .map_err(|x| x.to_string())?;
+
+        Ok(PlaybackState {
+            input,
+            opus_decoder,
+            opus_encoder,
+            decode_buf,
+            file_id,
+            detect_silence,
+            played_samples: 0,
+            limit_samples,
+        })
+    }
+
+    async fn open_file(
+        &mut self,
+        path: &str,
+    ) -> Result<(PacketReader<BufReader<File>>, u64, String, String, u64), String> {
         let cloned_path = path.to_string();
 
-        println!("Playing file {}", path);
+        info!("Playing file {}", path);
 
         // Extract and store playlist path (directory containing the file)
         if let Some(parent) = Path::new(path).parent() {
             self.current_playlist_path = Some(parent.to_string_lossy().to_string());
         }
 
-        if let None = self.start_instant {
-            self.start_instant = Some(Instant::now());
-        }
-
         let mut file = task::spawn_blocking(move || {
-            File::open(cloned_path.to_string()).map_err(|x| x.to_string())
+            File::open(cloned_path).map_err(|x| x.to_string())
         }).await.expect("Should spawn_blocking")?;
 
-        let duration_ms = Self::get_file_duration(&file).expect("Should read file duration");
+        let duration_ms = Self::get_file_duration(&file)?;
         file.seek(std::io::SeekFrom::Start(0)).map_err(|x| x.to_string())?;
 
         let ogg_comments_result = get_opus_comments(&mut file);
@@ -223,7 +395,7 @@ impl OpusPlayer {
                 )
             },
             Err(e) => {
-                println!("Failed to read Ogg comments: {}", e);
+                warn!("Failed to read Ogg comments: {}", e);
 
                 (
                     "Unknown Title".to_string(),
@@ -231,52 +403,33 @@ impl OpusPlayer {
                 )
             }
         };
-        
-        let file_id = match &self.active_file {
-            Some(file) => file.id + 1,
-            None => 1,
-        };
-
-        self.active_file = Some(ActiveFileMetadata {
-            id: file_id,
-            start_granule_position: self.granule_position,
-            title,
-            author,
-            image: Some("/playlist-image".to_string()),
-            duration_ms,
-        });
 
         // Calculate file duration
-        println!("\tFile duration: {:.2} seconds ({} ms)", duration_ms as f64 / 1000.0, duration_ms);
+        debug!("File duration: {:.2} seconds ({} ms)", duration_ms as f64 / 1000.0, duration_ms);
 
         let buf_reader = BufReader::new(file);
-        let packet_reader = PacketReader::new(buf_reader);
-
-        let opus_decoder = OpusDecoder::new(SAMPLE_RATE, Channels::Stereo)
-            .map_err(|x| format!("Decoder {}", x.to_string()))?;
-        let decode_buf = vec![0i16; 1920 * CHANNELS];
+        let mut packet_reader = PacketReader::new(buf_reader);
 
-        let opus_encoder = OpusEncoder::new(SAMPLE_RATE, Channels::Stereo, Application::Audio)
-            .map_err(|x| x.to_string())?;
+        let pre_skip = match packet_reader.read_packet() {
+            Ok(Some(packet)) if packet.data.starts_with(b"OpusHead") && packet.data.len() >= 12 => {
+                u16::from_le_bytes([packet.data[10], packet.data[11]]) as u64
+            },
+            _ => 0,
+        };
+        packet_reader.seek_bytes(SeekFrom::Start(0)).map_err(|x| x.to_string())?;
 
-        Ok(PlaybackState {
-            packet_reader,
-            opus_decoder,
-            opus_encoder,
-            decode_buf,
-            file_id,
-        })
+        Ok((packet_reader, pre_skip, title, author, duration_ms))
     }
 
     pub async fn process_next_packet(
         &mut self,
         state: &mut PlaybackState,
-    ) -> Result<bool, String> {
+    ) -> Result<PacketOutcome, String> {
         // While paused, idle without producing packets or advancing time.
         // Listeners stay connected; new ones get the headstart buffer.
         if self.paused {
             sleep(Duration::from_millis(100)).await;
-            return Ok(true);
+            return Ok(PacketOutcome::More);
         }
 
         let active_file = match &self.active_file {
@@ -284,54 +437,139 @@ impl OpusPlayer {
             None => return Err("No active file for playback".to_string()),
         };
 
-        // Try to read the next packet
-        let packet = match state.packet_reader.read_packet() {
-            Ok(Some(packet)) => packet,
-            Ok(None) => return Ok(false), // EOF - no more packets
-            Err(e) => return Err(e.to_string()),
-        };
-
         // Check if playback was interrupted by a new file
         if active_file.id != state.file_id {
             return Err("Playback interrupted: File changed!".to_string());
         }
 
-        // Skip header packets
-        if packet.data.starts_with(b"OpusHead") || packet.data.starts_with(b"OpusTags") {
-            return Ok(true); // Continue to next packet
-        }
+        let frame_size = match &mut state.input {
+            PlaybackInput::File { packet_reader, .. } => {
+                // Try to read the next packet
+                let packet = match packet_reader.read_packet() {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => return Ok(PacketOutcome::Finished), // EOF - no more packets
+                    Err(e) => return Err(e.to_string()),
+                };
 
-        // Decode the packet
-        let frame_size = state.opus_decoder
-            .decode(&packet.data, &mut state.decode_buf, false)
-            .map_err(|x| x.to_string())?;
+                // Skip header packets
+                if packet.data.starts_with(b"OpusHead") || packet.data.starts_with(b"OpusTags") {
+                    return Ok(PacketOutcome::More); // Continue to next packet
+                }
+
+                state.opus_decoder
+                    .decode(&packet.data, &mut state.decode_buf, false)
+                    .map_err(|x| x.to_string())?
+            },
+            PlaybackInput::Relay(feed) => {
+                let raw_opus_data = match timeout(RELAY_STALL_TIMEOUT, feed.recv()).await {
+                    Ok(Some(OpusPlayerEvent::AudioData { raw_opus_data, .. })) => raw_opus_data,
+                    Ok(None) => return Err("Relay source went away".to_string()),
+                    Err(_) => return Err("Relay source stalled".to_string()),
+                };
 
-        let frame_duration_ms = frame_size as f64 / 48_000 as f64 * 1000.0;
+                state.opus_decoder
+                    .decode(&raw_opus_data, &mut state.decode_buf, false)
+                    .map_err(|x| x.to_string())?
+            },
+            PlaybackInput::Tone(generator) => generator.fill(&mut state.decode_buf),
+        };
+
+        let frame_duration_ms = frame_size as f64 / SAMPLE_RATE as f64 * 1000.0;
         let pcm = &state.decode_buf[..frame_size * CHANNELS];
 
+        let silence_detected = state.detect_silence && self.track_silence(pcm, frame_size);
+
         // Update granule position
         self.granule_position += frame_size as u64;
         let absgp = self.granule_position;
-        let now_playing_ms = absgp as f64 / 48_000 as f64 * 1000.0;
+        let now_playing_ms = absgp as f64 / SAMPLE_RATE as f64 * 1000.0;
 
         // Re-encode the audio
         let mut encoded = vec![0u8; 4096];
+        let encode_started = Instant::now();
         let encoded_len = state.opus_encoder.encode(pcm, &mut encoded)
             .map_err(|x| x.to_string())?;
+        self.metrics.record_encode(encode_started.elapsed());
+        self.metrics.record_packet();
+
+        // Encode once per rendition that currently has listeners.
+        let mut renditions: Vec<(u32, Vec<u8>)> = Vec::new();
+        for bitrate in self.listeners.iter().filter_map(|l| l.bitrate_kbps) {
+            if renditions.iter().any(|(b, _)| *b == bitrate) {
+                continue;
+            }
+            if let Some(encoder) = self.rendition_encoders.get_mut(&bitrate) {
+                let encoder = encoder.get_mut().map_err(|x| x.to_string())?;
+                let mut buf = vec![0u8; 4096];
+                let encode_started = Instant::now();
+                let len = encoder.encode(pcm, &mut buf).map_err(|x| x.to_string())?;
+                self.metrics.record_encode(encode_started.elapsed());
+                buf.truncate(len);
+                renditions.push((bitrate, buf));
+            }
+        }
 
         // Broadcast to all listeners
         let mut listener_indices_to_drop = Vec::new();
 
-        for (i, listener) in self.listeners.iter().enumerate() {
-            let send_result = listener
+        for (i, listener) in self.listeners.iter_mut().enumerate() {
+            if listener.skipping {
+                if listener.sender.is_closed() {
+                    self.metrics.dropped_listeners.fetch_add(1, Ordering::Relaxed);
+                    listener_indices_to_drop.push(i);
+                    continue;
+                }
+                if listener.sender.capacity() < listener.sender.max_capacity() / 2 {
+                    self.metrics.skipped_packets.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                listener.skipping = false;
+            }
+
+            let data = listener.bitrate_kbps
+                .and_then(|b| renditions.iter().find(|(rb, _)| *rb == b))
+                .map(|(_, data)| data.as_slice())
+                .unwrap_or(&encoded[..encoded_len]);
+
+            let send_result = listener.sender
                 .try_send(OpusPlayerEvent::AudioData {
-                    raw_opus_data: encoded[..encoded_len].to_vec(),
+                    raw_opus_data: data.to_vec(),
                     granule_position: absgp,
                 });
 
-            if let Err(_) = send_result {
-                println!("Send to listener {} failed. Dropping listener...", i);
-                listener_indices_to_drop.push(i);
+            match send_result {
+                Ok(()) => {},
+                Err(mpsc::error::TrySendError::Full(_)) if listener.slow != SlowListenerPolicy::Drop => {
+                    if listener.slow == SlowListenerPolicy::LowerBitrate {
+                        let lower = SLOW_LISTENER_BITRATES.into_iter()
+                            .find(|&b| listener.bitrate_kbps.is_none_or(|current| b < current));
+                        if let Some(bitrate) = lower {
+                            let encoder = match self.rendition_encoders.entry(bitrate) {
+                                Entry::Occupied(_) => Ok(()),
+                                Entry::Vacant(slot) => new_encoder(Some(bitrate)).map(|e| {
+                                    slot.insert(Mutex::new(e));
+                                }),
+                            };
+                            match encoder {
+                                Ok(()) => {
+                                    debug!("Listener {} is falling behind, lowering to {} kbps", i, bitrate);
+                                    listener.bitrate_kbps = Some(bitrate);
+                                    self.metrics.bitrate_downgrades.fetch_add(1, Ordering::Relaxed);
+                                },
+                                Err(e) => warn!("Failed to create {} kbps encoder: {}", bitrate, e),
+                            }
+                        }
+                    }
+                    // Either way the listener skips ahead until it has caught up.
+                    debug!("Listener {} is falling behind, skipping ahead", i);
+                    listener.skipping = true;
+                    self.metrics.skipped_packets.fetch_add(1, Ordering::Relaxed);
+                },
+                Err(_) => {
+                    debug!("Send to listener {} failed. Dropping listener...", i);
+                    self.metrics.dropped_listeners.fetch_add(1, Ordering::Relaxed);
+                    listener_indices_to_drop.push(i);
+                },
             }
         }
 
@@ -372,7 +610,142 @@ impl OpusPlayer {
             }
         }
 
-        Ok(true) // More packets remain
+        if silence_detected {
+            return Ok(PacketOutcome::Silent);
+        }
+
+        state.played_samples += frame_size as u64;
+        if state.limit_samples.is_some_and(|limit| state.played_samples >= limit) {
+            return Ok(PacketOutcome::Finished);
+        }
+
+        Ok(PacketOutcome::More) // More packets remain
+    }
+
+    /// Moves file playback to `position_ms` into the track and returns the
+    /// position actually landed on, which is at most one frame past the target.
+    /// Positions past the end are refused without touching playback; if the
+    /// seek fails anyway, playback goes back to where it was.
+    pub fn seek(&mut self, state: &mut PlaybackState, position_ms: u64) -> Result<u64, String> {
+        if !matches!(state.input, PlaybackInput::File { .. }) {
+            return Err("Only file playback can seek".to_string());
+        }
+        let Some(active_file) = &self.active_file else {
+            return Err("Nothing is playing".to_string());
+        };
+        if position_ms >= active_file.duration_ms {
+            return Err("Seek position is past the end of the track".to_string());
+        }
+        let played = (self.granule_position as i64 - active_file.start_granule_position).max(0) as u64;
+        let current_ms = played * 1000 / SAMPLE_RATE as u64;
+
+        let result = self.seek_file(state, position_ms);
+        if result.is_err() {
+            if let Err(e) = self.seek_file(state, current_ms) {
+                warn!("Could not return to {} ms after a failed seek: {}", current_ms, e);
+                if let PlaybackInput::File { packet_reader, .. } = &mut state.input {
+                    packet_reader.seek_bytes(SeekFrom::Start(0)).map_err(|x| x.to_string())?;
+                    state.opus_decoder.reset_state().map_err(|x| x.to_string())?;
+                    if let Some(active_file) = self.active_file.as_mut() {
+                        active_file.start_granule_position = self.granule_position as i64;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Bisects the file by page granule position, starting early enough that
+    /// the decoder gets at least `SEEK_PRE_ROLL_SAMPLES` of pre-roll, then
+    /// decodes and discards audio up to the target.
+    fn seek_file(&mut self, state: &mut PlaybackState, position_ms: u64) -> Result<u64, String> {
+        let (packet_reader, pre_skip) = match &mut state.input {
+            PlaybackInput::File { packet_reader, pre_skip } => (packet_reader, *pre_skip),
+            _ => return Err("Only file playback can seek".to_string()),
+        };
+
+        let target = pre_skip + position_ms * SAMPLE_RATE as u64 / 1000;
+        let mut goal = target.saturating_sub(SEEK_PRE_ROLL_SAMPLES + SAMPLE_RATE as u64);
+
+        let landed = 'search: loop {
+            state.opus_decoder.reset_state().map_err(|x| x.to_string())?;
+
+            // Granule position at the end of the last decoded packet, once known.
+            let mut position = if goal <= pre_skip {
+                packet_reader.seek_bytes(SeekFrom::Start(0)).map_err(|x| x.to_string())?;
+                Some(0)
+            } else {
+                if !packet_reader.seek_absgp(None, goal).map_err(|x| x.to_string())? {
+                    return Err("Seek position is past the end of the track".to_string());
+                }
+                None
+            };
+
+            loop {
+                let packet = match packet_reader.read_packet().map_err(|x| x.to_string())? {
+                    Some(packet) => packet,
+                    None => return Err("Seek position is past the end of the track".to_string()),
+                };
+                if packet.data.starts_with(b"OpusHead") || packet.data.starts_with(b"OpusTags") {
+                    continue;
+                }
+
+                let frame_size = state.opus_decoder
+                    .decode(&packet.data, &mut state.decode_buf, false)
+                    .map_err(|x| x.to_string())? as u64;
+
+                position = match position {
+                    Some(p) => Some(p + frame_size),
+                    None if packet.last_in_page() => {
+                        // The first exact position we learn must leave room for
+                        // pre-roll; otherwise start the search further back.
+                        if packet.absgp_page() + SEEK_PRE_ROLL_SAMPLES > target {
+                            goal = goal.saturating_sub(2 * SAMPLE_RATE as u64);
+                            continue 'search;
+                        }
+                        Some(packet.absgp_page())
+                    },
+                    None => None,
+                };
+
+                if let Some(p) = position {
+                    if p >= target {
+                        break 'search p;
+                    }
+                }
+            }
+        };
+
+        let landed_samples = landed.saturating_sub(pre_skip);
+        if let Some(active_file) = self.active_file.as_mut() {
+            active_file.start_granule_position = self.granule_position as i64 - landed_samples as i64;
+        }
+        self.silent_samples = 0;
+
+        Ok(landed_samples * 1000 / SAMPLE_RATE as u64)
+    }
+
+    /// Accumulates consecutive silent samples and reports whether the
+    /// configured silence threshold has just been crossed.
+    fn track_silence(&mut self, pcm: &[i16], frame_size: usize) -> bool {
+        let threshold = match self.silence_threshold {
+            Some(t) => t,
+            None => return false,
+        };
+
+        let peak = pcm.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+        if peak > SILENCE_PEAK {
+            self.silent_samples = 0;
+            return false;
+        }
+
+        self.silent_samples += frame_size as u64;
+        let threshold_samples = threshold.as_millis() as u64 * SAMPLE_RATE as u64 / 1000;
+        if self.silent_samples >= threshold_samples {
+            self.silent_samples = 0;
+            return true;
+        }
+        false
     }
 
     pub async fn play_file(
@@ -380,26 +753,25 @@ impl OpusPlayer {
         path: &str,
     ) -> Result<(), String> {
         // Initialize playback
-        let mut state = self.start_playback(path).await?;
+        let mut state = self.start_playback(PlaybackSource::File(path.to_string()), None).await?;
 
         // Process packets until done
         loop {
             match self.process_next_packet(&mut state).await {
-                Ok(true) => continue,  // More packets to process
-                Ok(false) => return Ok(()), // EOF - done successfully
+                Ok(PacketOutcome::More) => continue,  // More packets to process
+                Ok(PacketOutcome::Finished) => return Ok(()), // EOF - done successfully
+                Ok(PacketOutcome::Silent) => return Err("Silence detected".to_string()),
                 Err(e) => return Err(e), // Error during playback
             }
         }
     }
 
-    pub async fn get_headstart_data(&self) -> Vec<OpusPlayerEvent> {
+    pub async fn get_headstart_data(&self, bitrate_kbps: Option<u32>) -> Vec<OpusPlayerEvent> {
         let frame_size_ms = 20f32;
         let frame_size = (SAMPLE_RATE as f32 / (1000f32 / frame_size_ms) * 2f32) as usize;
         let packets = (BUFFER_SIZE_MS as f32 / frame_size_ms).ceil() as usize;
-        let mut events: Vec<OpusPlayerEvent> = Vec::with_capacity(packets as usize);
-        let mut opus_encoder = OpusEncoder::new(SAMPLE_RATE, Channels::Stereo, Application::Audio)
-            .map_err(|x| x.to_string())
-            .expect("Should create opus encoder");
+        let mut events: Vec<OpusPlayerEvent> = Vec::with_capacity(packets);
+        let mut opus_encoder = new_encoder(bitrate_kbps).expect("Should create opus encoder");
 
         if self.headstart_buffer.is_empty() {
             return Vec::new();
@@ -425,26 +797,77 @@ impl OpusPlayer {
         return events;
     }
 
-    pub async fn add_listener(&mut self, listener: mpsc::Sender<OpusPlayerEvent>) {
-        self.listeners.push(listener);
+    pub async fn add_listener(
+        &mut self,
+        listener: mpsc::Sender<OpusPlayerEvent>,
+        bitrate_kbps: Option<u32>,
+        slow: SlowListenerPolicy,
+    ) {
+        if let Some(bitrate) = bitrate_kbps {
+            if let Entry::Vacant(slot) = self.rendition_encoders.entry(bitrate) {
+                match new_encoder(Some(bitrate)) {
+                    Ok(encoder) => {
+                        slot.insert(Mutex::new(encoder));
+                    },
+                    Err(e) => {
+                        warn!("Failed to create {} kbps encoder, using default: {}", bitrate, e);
+                        self.listeners.push(Listener { sender: listener, bitrate_kbps: None, slow, skipping: false });
+                        return;
+                    }
+                }
+            }
+        }
+        self.listeners.push(Listener { sender: listener, bitrate_kbps, slow, skipping: false });
+    }
+}
+
+fn new_encoder(bitrate_kbps: Option<u32>) -> Result<OpusEncoder, String> {
+    let mut encoder = OpusEncoder::new(SAMPLE_RATE, Channels::Stereo, Application::Audio)
+        .map_err(|x| x.to_string())?;
+    if let Some(kbps) = bitrate_kbps {
+        encoder.set_bitrate(Bitrate::Bits(kbps as i32 * 1000)).map_err(|x| x.to_string())?;
     }
+    Ok(encoder)
 }
 
 enum OpusPlayerCommand {
-    PlayFile {
-        path: String,
+    Play {
+        source: PlaybackSource,
+        limit: Option<Duration>,
+        start_at_ms: Option<u64>,
         started: oneshot::Sender<()>,
         result: oneshot::Sender<PlaybackResult>,
     },
     GetMetadata(oneshot::Sender<Option<ActiveFileMetadata>>),
-    GetHeadstartData(oneshot::Sender<Vec<OpusPlayerEvent>>),
+    GetHeadstartData(Option<u32>, oneshot::Sender<Vec<OpusPlayerEvent>>),
     GetTimeData(oneshot::Sender<TimeData>),
-    RegisterListener(mpsc::Sender<OpusPlayerEvent>),
+    RegisterListener(mpsc::Sender<OpusPlayerEvent>, Option<u32>, SlowListenerPolicy),
     GetPlaylistPath(oneshot::Sender<Option<String>>),
     Skip,
     Pause,
     Resume,
     GetPaused(oneshot::Sender<bool>),
+    SetSilenceThreshold(Option<Duration>),
+    StartOverride {
+        source: PlaybackSource,
+        started: oneshot::Sender<()>,
+        result: oneshot::Sender<PlaybackResult>,
+    },
+    EndOverride,
+    GetOverride(oneshot::Sender<bool>),
+    Seek(u64, oneshot::Sender<Result<u64, String>>),
+    /// Ends the actor, disconnecting every listener.
+    Stop,
+}
+
+type ActivePlayback = (PlaybackState, oneshot::Sender<PlaybackResult>);
+
+/// The regular playback set aside while an override is on air.
+struct SuspendedPlayback {
+    playback: Option<ActivePlayback>,
+    metadata: Option<ActiveFileMetadata>,
+    granule_position: u64,
+    was_paused: bool,
 }
 
 pub struct PlayFileHandles {
@@ -457,6 +880,7 @@ pub enum PlaybackResult {
     Finished,
     Interrupted,
     Skipped,
+    Silence,
     Error(String),
 }
 
@@ -466,36 +890,88 @@ struct OpusPlayerActor {
 }
 
 impl OpusPlayerActor {
-    pub fn new(receiver: tokio::sync::mpsc::Receiver<OpusPlayerCommand>) -> Self {
+    pub fn new(receiver: tokio::sync::mpsc::Receiver<OpusPlayerCommand>, metrics: Arc<PlayerMetrics>) -> Self {
         Self {
-            player: OpusPlayer::new(),
+            player: OpusPlayer::new(metrics),
             receiver,
         }
     }
 
+    /// Puts suspended playback back on air once an override ends, shifting its
+    /// timeline by however long the override ran so progress stays correct.
+    fn restore(player: &mut OpusPlayer, suspended: &mut Option<SuspendedPlayback>) -> Option<ActivePlayback> {
+        let SuspendedPlayback { playback, metadata, granule_position, was_paused } = suspended.take()?;
+
+        let override_samples = player.granule_position - granule_position;
+        player.active_file = metadata.map(|mut m| {
+            m.start_granule_position += override_samples as i64;
+            m
+        });
+        if was_paused {
+            player.pause();
+        }
+
+        playback
+    }
+
     pub async fn run(mut self) {
-        let mut playback_state: Option<(PlaybackState, oneshot::Sender<PlaybackResult>)> = None;
+        let mut playback_state: Option<ActivePlayback> = None;
+        let mut suspended: Option<SuspendedPlayback> = None;
 
         loop {
             tokio::select! {
                 // Process incoming commands
                 Some(command) = self.receiver.recv() => {
                     match command {
-                        OpusPlayerCommand::PlayFile { path, started, result } => {
+                        OpusPlayerCommand::Play { source, limit, start_at_ms, started, result } if suspended.is_some() => {
+                            // An override is on air: the new playback replaces the
+                            // suspended one and waits for the override to end.
+                            let override_metadata = self.player.active_file.clone();
+                            let suspended = suspended.as_mut().expect("checked by guard");
+                            if let Some((_, old_sender)) = suspended.playback.take() {
+                                let _ = old_sender.send(PlaybackResult::Interrupted);
+                            }
+                            match self.player.start_playback(source, limit).await {
+                                Ok(mut state) => {
+                                    if let Some(ms) = start_at_ms {
+                                        if let Err(e) = self.player.seek(&mut state, ms) {
+                                            warn!("Could not resume at {} ms: {}", ms, e);
+                                        }
+                                    }
+                                    let _ = started.send(());
+                                    suspended.playback = Some((state, result));
+                                    suspended.metadata = self.player.active_file.clone();
+                                    suspended.granule_position = self.player.granule_position;
+                                },
+                                Err(e) => {
+                                    warn!("Error starting playback: {}", e);
+                                    drop(started);
+                                    let _ = result.send(PlaybackResult::Error(e));
+                                }
+                            }
+                            self.player.active_file = override_metadata;
+                        },
+                        OpusPlayerCommand::Play { source, limit, start_at_ms, started, result } => {
                             // If already playing, notify the old sender that playback was interrupted
                             if let Some((_, old_sender)) = playback_state.take() {
                                 let _ = old_sender.send(PlaybackResult::Interrupted);
                             }
 
                             // Start new playback
-                            match self.player.start_playback(&path).await {
-                                Ok(state) => {
+                            match self.player.start_playback(source, limit).await {
+                                Ok(mut state) => {
+                                    // A failed resume just plays the track from the start.
+                                    if let Some(ms) = start_at_ms {
+                                        if let Err(e) = self.player.seek(&mut state, ms) {
+                                            warn!("Could not resume at {} ms: {}", ms, e);
+                                        }
+                                    }
                                     // Signal that active_file now reflects the new track.
                                     let _ = started.send(());
                                     playback_state = Some((state, result));
                                 },
                                 Err(e) => {
-                                    println!("Error starting playback: {}", e);
+                                    warn!("Error starting playback: {}", e);
                                     // Drop `started` (closed) so the caller knows start failed
                                     // without needing a separate signal.
                                     drop(started);
@@ -507,38 +983,42 @@ impl OpusPlayerActor {
                             let metadata = self.player.get_metadata().await;
 
                             if let Err(e) = sender.send(metadata) {
-                                println!("Error sending metadata: {:?}", e);
+                                warn!("Error sending metadata: {:?}", e);
                             }
                         },
-                        OpusPlayerCommand::RegisterListener(listener) => {
-                            self.player.add_listener(listener).await;
+                        OpusPlayerCommand::RegisterListener(listener, bitrate_kbps, slow) => {
+                            self.player.add_listener(listener, bitrate_kbps, slow).await;
                         },
-                        OpusPlayerCommand::GetHeadstartData(sender) => {
-                            let data = self.player.get_headstart_data().await;
+                        OpusPlayerCommand::GetHeadstartData(bitrate_kbps, sender) => {
+                            let data = self.player.get_headstart_data(bitrate_kbps).await;
 
                             if let Err(e) = sender.send(data) {
-                                println!("Error sending headstart data: {:?}", e);
+                                warn!("Error sending headstart data: {:?}", e);
                             }
                         },
                         OpusPlayerCommand::GetTimeData(sender) => {
                             let time_data = self.player.get_stream_time_data().await;
 
                             if let Err(e) = sender.send(time_data) {
-                                println!("Error sending time data: {:?}", e);
+                                warn!("Error sending time data: {:?}", e);
                             }
                         },
                         OpusPlayerCommand::GetPlaylistPath(sender) => {
                             let playlist_path = self.player.get_playlist_path().await;
 
                             if let Err(e) = sender.send(playlist_path) {
-                                println!("Error sending playlist path: {:?}", e);
+                                warn!("Error sending playlist path: {:?}", e);
                             }
                         },
                         OpusPlayerCommand::Skip => {
                             // Abort the current file; the playlist loop will advance.
+                            // Skipping an override ends it instead.
                             if let Some((_, sender)) = playback_state.take() {
                                 let _ = sender.send(PlaybackResult::Skipped);
                             }
+                            if suspended.is_some() {
+                                playback_state = Self::restore(&mut self.player, &mut suspended);
+                            }
                         },
                         OpusPlayerCommand::Pause => {
                             self.player.pause();
@@ -549,6 +1029,77 @@ impl OpusPlayerActor {
                         OpusPlayerCommand::GetPaused(sender) => {
                             let _ = sender.send(self.player.is_paused());
                         },
+                        OpusPlayerCommand::SetSilenceThreshold(threshold) => {
+                            self.player.set_silence_threshold(threshold);
+                        },
+                        OpusPlayerCommand::StartOverride { source, started, result } => {
+                            match suspended {
+                                // Replacing a running override keeps the original
+                                // playback suspended.
+                                Some(_) => {
+                                    if let Some((_, old_sender)) = playback_state.take() {
+                                        let _ = old_sender.send(PlaybackResult::Interrupted);
+                                    }
+                                },
+                                None => {
+                                    suspended = Some(SuspendedPlayback {
+                                        playback: playback_state.take(),
+                                        metadata: self.player.active_file.clone(),
+                                        granule_position: self.player.granule_position,
+                                        was_paused: self.player.is_paused(),
+                                    });
+                                    // An emergency announcement must be heard even on a
+                                    // paused stream.
+                                    self.player.resume();
+                                },
+                            }
+
+                            match self.player.start_playback(source, None).await {
+                                Ok(mut state) => {
+                                    // Announcements may contain pauses; never fail over on them.
+                                    state.detect_silence = false;
+                                    let _ = started.send(());
+                                    playback_state = Some((state, result));
+                                },
+                                Err(e) => {
+                                    error!("Error starting override: {}", e);
+                                    drop(started);
+                                    let _ = result.send(PlaybackResult::Error(e));
+                                    playback_state = Self::restore(&mut self.player, &mut suspended);
+                                }
+                            }
+                        },
+                        OpusPlayerCommand::EndOverride => {
+                            if suspended.is_some() {
+                                if let Some((_, sender)) = playback_state.take() {
+                                    let _ = sender.send(PlaybackResult::Interrupted);
+                                }
+                                playback_state = Self::restore(&mut self.player, &mut suspended);
+                            }
+                        },
+                        OpusPlayerCommand::GetOverride(sender) => {
+                            let _ = sender.send(suspended.is_some());
+                        },
+                        OpusPlayerCommand::Seek(position_ms, sender) => {
+                            let result = match playback_state.as_mut() {
+                                // The override is what's on air; the suspended track
+                                // can't be heard, so seeking it would be confusing.
+                                _ if suspended.is_some() => Err("Cannot seek during an override".to_string()),
+                                None => Err("Nothing is playing".to_string()),
+                                Some((state, _)) => self.player.seek(state, position_ms),
+                            };
+                            let _ = sender.send(result);
+                        },
+                        OpusPlayerCommand::Stop => {
+                            if let Some((_, sender)) = playback_state.take() {
+                                let _ = sender.send(PlaybackResult::Interrupted);
+                            }
+                            if let Some((_, sender)) = suspended.take().and_then(|s| s.playback) {
+                                let _ = sender.send(PlaybackResult::Interrupted);
+                            }
+                            info!("Player stopped");
+                            return;
+                        },
                     }
                 }
 
@@ -560,18 +1111,24 @@ impl OpusPlayerActor {
                     }
                 }, if playback_state.is_some() => {
                     match result {
-                        Some(Ok(true)) => {
+                        Some(Ok(PacketOutcome::More)) => {
                             // Continue playing - more packets remain
                         },
-                        Some(Ok(false)) => {
+                        Some(Ok(PacketOutcome::Finished)) => {
                             // Playback finished successfully
                             if let Some((_, sender)) = playback_state.take() {
                                 let _ = sender.send(PlaybackResult::Finished);
                             }
                         },
+                        Some(Ok(PacketOutcome::Silent)) => {
+                            // Hand control back so the caller can fail over.
+                            if let Some((_, sender)) = playback_state.take() {
+                                let _ = sender.send(PlaybackResult::Silence);
+                            }
+                        },
                         Some(Err(e)) => {
                             // Error during playback
-                            println!("Playback error: {}", e);
+                            warn!("Playback error: {}", e);
                             if let Some((_, sender)) = playback_state.take() {
                                 let _ = sender.send(PlaybackResult::Error(e.to_string()));
                             }
@@ -581,6 +1138,12 @@ impl OpusPlayerActor {
 
                         }
                     }
+
+                    // An override that just ended hands the player back to
+                    // whatever it interrupted.
+                    if playback_state.is_none() && suspended.is_some() {
+                        playback_state = Self::restore(&mut self.player, &mut suspended);
+                    }
                 }
             }
         }
@@ -590,27 +1153,51 @@ impl OpusPlayerActor {
 #[derive(Debug, Clone)]
 pub struct OpusPlayerHandle {
     sender: mpsc::Sender<OpusPlayerCommand>,
+    pub metrics: Arc<PlayerMetrics>,
 }
 
 impl OpusPlayerHandle {
     pub fn new() -> Self {
         let (sender, receiver) = mpsc::channel(50);
-        let actor = OpusPlayerActor::new(receiver);
+        let metrics = Arc::new(PlayerMetrics::default());
+        let actor = OpusPlayerActor::new(receiver, metrics.clone());
         
         // Spawn the actor in its own thread
         tokio::spawn(async move {
             actor.run().await;
-        });
+        }.in_current_span());
         
-        Self { sender }
+        Self { sender, metrics }
     }
 
     pub async fn play_file(&self, path: String) -> Result<PlayFileHandles, String> {
+        self.play_source(PlaybackSource::File(path), None).await
+    }
+
+    /// Starts playing `source`, interrupting whatever was playing before.
+    /// When `limit` is set playback finishes after that much audio.
+    pub async fn play_source(
+        &self,
+        source: PlaybackSource,
+        limit: Option<Duration>,
+    ) -> Result<PlayFileHandles, String> {
+        self.play_source_at(source, limit, None).await
+    }
+
+    /// Like `play_source`, but file sources start `start_at_ms` into the track.
+    pub async fn play_source_at(
+        &self,
+        source: PlaybackSource,
+        limit: Option<Duration>,
+        start_at_ms: Option<u64>,
+    ) -> Result<PlayFileHandles, String> {
         let (started_tx, started_rx) = oneshot::channel();
         let (result_tx, result_rx) = oneshot::channel();
 
-        let command = OpusPlayerCommand::PlayFile {
-            path,
+        let command = OpusPlayerCommand::Play {
+            source,
+            limit,
+            start_at_ms,
             started: started_tx,
             result: result_tx,
         };
@@ -635,18 +1222,26 @@ impl OpusPlayerHandle {
         Ok(metadata)
     }
 
-    pub async fn register_listener(&self, listener: mpsc::Sender<OpusPlayerEvent>) -> Result<(), String> {
-        let command = OpusPlayerCommand::RegisterListener(listener);
+    /// Subscribes `listener` to the encoded output. With `bitrate_kbps` set the
+    /// listener gets its own rendition; the decode and timeline stay shared.
+    /// `slow` decides what happens once the listener's channel fills up.
+    pub async fn register_listener(
+        &self,
+        listener: mpsc::Sender<OpusPlayerEvent>,
+        bitrate_kbps: Option<u32>,
+        slow: SlowListenerPolicy,
+    ) -> Result<(), String> {
+        let command = OpusPlayerCommand::RegisterListener(listener, bitrate_kbps, slow);
 
         self.sender.send(command).await.map_err(|x| x.to_string())?;
 
         Ok(())
     }
 
-    pub async fn get_headstart_data(&self) -> Result<Vec<OpusPlayerEvent>, String> {
+    pub async fn get_headstart_data(&self, bitrate_kbps: Option<u32>) -> Result<Vec<OpusPlayerEvent>, String> {
         let (sender, receiver) = oneshot::channel();
 
-        let command = OpusPlayerCommand::GetHeadstartData(sender);
+        let command = OpusPlayerCommand::GetHeadstartData(bitrate_kbps, sender);
 
         self.sender.send(command).await.map_err(|x| x.to_string())?;
 
@@ -691,10 +1286,143 @@ impl OpusPlayerHandle {
         self.sender.send(OpusPlayerCommand::Resume).await.map_err(|x| x.to_string())
     }
 
+    pub async fn set_silence_threshold(&self, threshold: Option<Duration>) -> Result<(), String> {
+        self.sender.send(OpusPlayerCommand::SetSilenceThreshold(threshold)).await.map_err(|x| x.to_string())
+    }
+
+    /// Plays `source` over whatever is on air. The interrupted playback resumes
+    /// where it left off once the override finishes or is ended.
+    pub async fn start_override(&self, source: PlaybackSource) -> Result<PlayFileHandles, String> {
+        let (started_tx, started_rx) = oneshot::channel();
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let command = OpusPlayerCommand::StartOverride {
+            source,
+            started: started_tx,
+            result: result_tx,
+        };
+
+        self.sender.send(command).await.map_err(|x| x.to_string())?;
+
+        Ok(PlayFileHandles {
+            started: started_rx,
+            result: result_rx,
+        })
+    }
+
+    pub async fn end_override(&self) -> Result<(), String> {
+        self.sender.send(OpusPlayerCommand::EndOverride).await.map_err(|x| x.to_string())
+    }
+
+    /// Seeks the current track and returns the position landed on in ms.
+    pub async fn seek(&self, position_ms: u64) -> Result<u64, String> {
+        let (sender, receiver) = oneshot::channel();
+
+        let command = OpusPlayerCommand::Seek(position_ms, sender);
+
+        self.sender.send(command).await.map_err(|x| x.to_string())?;
+        receiver.await.map_err(|x| x.to_string())?
+    }
+
+    pub async fn is_overridden(&self) -> Result<bool, String> {
+        let (sender, receiver) = oneshot::channel();
+        self.sender.send(OpusPlayerCommand::GetOverride(sender)).await.map_err(|x| x.to_string())?;
+        receiver.await.map_err(|x| x.to_string())
+    }
+
     pub async fn is_paused(&self) -> Result<bool, String> {
         let (sender, receiver) = oneshot::channel();
         self.sender.send(OpusPlayerCommand::GetPaused(sender)).await.map_err(|x| x.to_string())?;
         receiver.await.map_err(|x| x.to_string())
     }
+
+    /// Stops the player for good; later commands fail.
+    pub async fn stop(&self) -> Result<(), String> {
+        self.sender.send(OpusPlayerCommand::Stop).await.map_err(|x| x.to_string())
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ogg::{PacketWriteEndInfo, PacketWriter};
+
+    const PRE_SKIP: u64 = 312;
+    const FRAME: usize = 960;
+
+    /// Writes `secs` of tone as an Ogg Opus file with a page every second.
+    fn write_track(path: &Path, secs: usize) {
+        let mut head = OPUS_HEAD.to_vec();
+        head[10..12].copy_from_slice(&(PRE_SKIP as u16).to_le_bytes());
+        let mut writer = PacketWriter::new(File::create(path).unwrap());
+        writer.write_packet(head, 1, PacketWriteEndInfo::EndPage, 0).unwrap();
+        writer.write_packet(OPUS_COMMENTS.to_vec(), 1, PacketWriteEndInfo::EndPage, 0).unwrap();
+
+        let mut encoder = OpusEncoder::new(SAMPLE_RATE, Channels::Stereo, Application::Audio).unwrap();
+        let mut tone = ToneGenerator { frequency_hz: 440.0, phase: 0.0 };
+        let mut pcm = vec![0i16; FRAME * CHANNELS];
+        let frames = secs * SAMPLE_RATE as usize / FRAME;
+        for n in 1..=frames {
+            tone.fill(&mut pcm);
+            let packet = encoder.encode_vec(&pcm, 4000).unwrap();
+            let end = match n {
+                _ if n == frames => PacketWriteEndInfo::EndStream,
+                _ if n % 50 == 0 => PacketWriteEndInfo::EndPage,
+                _ => PacketWriteEndInfo::NormalPacket,
+            };
+            writer.write_packet(packet, 1, end, PRE_SKIP + (n * FRAME) as u64).unwrap();
+        }
+    }
+
+    async fn playing(secs: usize) -> (OpusPlayer, PlaybackState, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("octopus-seek-{}-{}.opus", std::process::id(), generate_serial()));
+        write_track(&path, secs);
+        let mut player = OpusPlayer::new(Arc::default());
+        let state = player.start_playback(PlaybackSource::File(path.to_string_lossy().into_owned()), None).await.unwrap();
+        (player, state, path)
+    }
+
+    #[tokio::test]
+    async fn seeks_to_at_most_a_frame_past_the_target() {
+        let (mut player, mut state, path) = playing(10).await;
+        for target_ms in [0, 1_000, 2_500, 7_345, 9_900] {
+            let landed_ms = player.seek(&mut state, target_ms).unwrap();
+            assert!(landed_ms >= target_ms && landed_ms <= target_ms + 20, "{} -> {}", target_ms, landed_ms);
+            let active = player.active_file.as_ref().unwrap();
+            let expected = player.granule_position as i64 - (landed_ms * SAMPLE_RATE as u64 / 1000) as i64;
+            assert!((active.start_granule_position - expected).abs() <= 48, "{} -> {}", target_ms, landed_ms);
+        }
+        // Seeking backwards works as well as forwards.
+        assert!(player.seek(&mut state, 500).unwrap() >= 500);
+        assert_eq!(player.process_next_packet(&mut state).await.unwrap(), PacketOutcome::More);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn seeking_past_the_end_leaves_playback_where_it_was() {
+        let (mut player, mut state, path) = playing(3).await;
+        let landed_ms = player.seek(&mut state, 2_000).unwrap();
+        let start = player.active_file.as_ref().unwrap().start_granule_position;
+        assert!(player.seek(&mut state, 60_000).is_err());
+        // Within the duration, which counts the pre-skip, but past the last
+        // sample: the seek fails and goes back.
+        assert!(player.seek(&mut state, 3_005).is_err());
+        assert_eq!(player.active_file.as_ref().unwrap().start_granule_position, start);
+        // The next packet still follows the position seeked to.
+        let PlaybackInput::File { packet_reader, .. } = &mut state.input else {
+            unreachable!();
+        };
+        let next = packet_reader.read_packet().unwrap().unwrap();
+        let next_end = next.absgp_page() - PRE_SKIP;
+        let landed = landed_ms * SAMPLE_RATE as u64 / 1000;
+        assert!(next_end > landed && next_end <= landed + SAMPLE_RATE as u64, "{} after {}", next_end, landed);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_files_can_seek() {
+        let mut player = OpusPlayer::new(Arc::default());
+        let mut state = player.start_playback(PlaybackSource::Tone { frequency_hz: 440.0 }, None).await.unwrap();
+        assert!(player.seek(&mut state, 1_000).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+use std::{path::Path, sync::atomic::Ordering, time::{Duration, Instant}};
+
+use tokio::{fs, sync::mpsc, time::sleep};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::{
+    StreamRegistry,
+    api::{AdminEvent, HistoryEntry},
+    config::{FallbackSource, SlowListenerPolicy},
+    opus_player::{OpusPlayerHandle, PlaybackResult, PlaybackSource},
+    state::StreamPosition,
+    stats::now_ms,
+    ws_server::{broadcast_metadata, broadcast_schedule, simulcast_group},
+};
+
+// Pause between rounds when every source in the chain has failed, so a broken
+// chain doesn't spin.
+const EXHAUSTED_CHAIN_BACKOFF: Duration = Duration::from_secs(5);
+
+// Tracks kept per stream for `get_history`.
+const HISTORY_LEN: usize = 50;
+
+/// Which source in a stream's fallback chain is currently on air.
+#[derive(Debug, Clone, Default)]
+pub struct FailoverStatus {
+    /// 0 is the stream's own playlist; higher levels index into `fallback`.
+    pub level: usize,
+    pub source: String,
+    pub last_alert: Option<String>,
+}
+
+enum SourceOutcome {
+    /// The retry deadline passed; start again from the top of the chain.
+    Yield,
+    /// The source is unusable right now; move on to the next one.
+    Failed(String),
+    /// Another playlist took over the player; stop this task.
+    Interrupted,
+}
+
+struct Playout {
+    player: OpusPlayerHandle,
+    stream_id: String,
+    registry: StreamRegistry,
+    resume_mid_track: bool,
+}
+
+/// Starts `path` on the stream's player. `resume` continues a rotation saved
+/// by a previous run if it was for the same playlist.
+pub async fn play_playlist(
+    player: OpusPlayerHandle,
+    path: String,
+    stream_id: String,
+    registry: StreamRegistry,
+    resume: Option<StreamPosition>,
+) -> Result<(), String> {
+    let entry = registry.read().await.get(&stream_id).cloned();
+    let (fallback, silence_threshold, retry, resume_mid_track) = match &entry {
+        Some(entry) => {
+            let e = entry.read().await;
+            (
+                e.config.fallback.clone(),
+                e.config.silence_threshold_secs.map(Duration::from_secs),
+                Duration::from_secs(e.config.failover_retry_secs()),
+                e.config.resume_mid_track,
+            )
+        },
+        None => (Vec::new(), None, Duration::from_secs(60), false),
+    };
+
+    // Without a fallback chain there is nothing to fail over to, so keep the
+    // old behaviour of refusing to start on an unusable playlist.
+    if fallback.is_empty() {
+        get_playlist_files(&path).await?;
+    }
+
+    player.set_silence_threshold(silence_threshold).await?;
+
+    let playout = Playout {
+        player,
+        stream_id: stream_id.clone(),
+        registry,
+        resume_mid_track,
+    };
+    let resume = resume.filter(|r| r.playlist == path);
+
+    // Everything the playout logs is tagged with its stream.
+    let span = info_span!("stream", stream_id = %stream_id);
+    span.in_scope(|| info!("Spawning player for playlist: {}", path));
+    let task = tokio::spawn(async move {
+        playout.run(path, fallback, retry, resume).await;
+    }.instrument(span));
+
+    // Replacing the playlist (e.g. from the control FIFO) retires the previous
+    // task even if it is currently backing off between rounds.
+    if let Some(entry) = entry {
+        if let Some(previous) = entry.write().await.task.replace(task.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    Ok(())
+}
+
+impl Playout {
+    async fn run(
+        self,
+        path: String,
+        fallback: Vec<FallbackSource>,
+        retry: Duration,
+        mut resume: Option<StreamPosition>,
+    ) {
+        let mut cursors = vec![0usize; fallback.len() + 1];
+
+        loop {
+            let mut played = false;
+
+            for level in 0..cursors.len() {
+                // The primary playlist keeps playing until it fails; fallbacks
+                // give the chain a chance to recover after `retry`.
+                let deadline = if level == 0 { None } else { Some(Instant::now() + retry) };
+                let label = match level {
+                    0 => format!("playlist {}", path),
+                    n => fallback[n - 1].label(),
+                };
+
+                let outcome = match level {
+                    0 => {
+                        self.run_playlist(&path, &mut cursors[0], level, &label, deadline, resume.take()).await
+                    },
+                    n => match &fallback[n - 1] {
+                        FallbackSource::Playlist { path } => {
+                            self.run_playlist(path, &mut cursors[n], level, &label, deadline, None).await
+                        },
+                        FallbackSource::Stream { id } => self.run_relay(id, level, &label, retry).await,
+                        FallbackSource::Tone { frequency_hz } => {
+                            self.run_generated(*frequency_hz, level, &label, retry).await
+                        },
+                        FallbackSource::Silence => self.run_generated(0.0, level, &label, retry).await,
+                    },
+                };
+
+                match outcome {
+                    SourceOutcome::Yield => {
+                        played = true;
+                        break;
+                    },
+                    SourceOutcome::Failed(reason) => self.alert(&label, &reason).await,
+                    SourceOutcome::Interrupted => return,
+                }
+            }
+
+            if !played {
+                error!(
+                    "Every source in the fallback chain failed, retrying in {}s",
+                    EXHAUSTED_CHAIN_BACKOFF.as_secs(),
+                );
+                sleep(EXHAUSTED_CHAIN_BACKOFF).await;
+            }
+        }
+    }
+
+    async fn run_playlist(
+        &self,
+        path: &str,
+        cursor: &mut usize,
+        level: usize,
+        label: &str,
+        deadline: Option<Instant>,
+        resume: Option<StreamPosition>,
+    ) -> SourceOutcome {
+        // Re-read the folder every round so files added after a failure are
+        // picked up when the chain retries this source.
+        let files = match get_playlist_files(path).await {
+            Ok(files) => files,
+            Err(e) => return SourceOutcome::Failed(e),
+        };
+
+        // Prefer the saved file name so tracks added or removed while the
+        // server was down don't shift the rotation.
+        let mut start_at_ms = None;
+        if let Some(resume) = resume {
+            *cursor = files.iter().position(|f| *f == resume.file).unwrap_or(resume.index);
+            if self.resume_mid_track && files.get(*cursor) == Some(&resume.file) && resume.position_ms > 0 {
+                start_at_ms = Some(resume.position_ms);
+            }
+            info!("Resuming playlist at {}", files[*cursor % files.len()]);
+        }
+
+        let mut consecutive_errors = 0;
+        loop {
+            // Queued files play ahead of the primary playlist without moving
+            // its cursor.
+            let queued = if level == 0 { self.next_queued().await } else { None };
+            let (file, position) = match queued {
+                Some(file) => (file, None),
+                None => {
+                    let index = *cursor % files.len();
+                    let file = files[index].clone();
+                    *cursor += 1;
+
+                    // Only the primary playlist's rotation is persisted.
+                    let position = (level == 0).then(|| StreamPosition {
+                        playlist: path.to_string(),
+                        index,
+                        file: file.clone(),
+                        position_ms: 0,
+                        queue: Vec::new(),
+                    });
+                    (file, position)
+                },
+            };
+            let file = &file;
+
+            let source = PlaybackSource::File(file.clone());
+            let start_at_ms = if position.is_some() { start_at_ms.take() } else { None };
+            let result = self.play(source, None, start_at_ms, level, label, position).await;
+
+            match result {
+                PlaybackResult::Finished => {
+                    debug!("Finished playback normally for file: {}", file);
+                    consecutive_errors = 0;
+                },
+                PlaybackResult::Skipped => {
+                    info!("Playback was skipped for file: {}", file);
+                    consecutive_errors = 0;
+                },
+                PlaybackResult::Interrupted => {
+                    info!("Playback was interrupted for file: {}", file);
+                    return SourceOutcome::Interrupted;
+                },
+                PlaybackResult::Silence => {
+                    return SourceOutcome::Failed(format!("silence detected in {}", file));
+                },
+                PlaybackResult::Error(e) => {
+                    warn!("Error during playback of file {}: {}", file, e);
+                    self.admin_event(AdminEvent::PlaybackError {
+                        stream: self.stream_id.clone(),
+                        file: file.clone(),
+                        message: e,
+                    }).await;
+                    consecutive_errors += 1;
+                    if consecutive_errors >= files.len() {
+                        return SourceOutcome::Failed("every file in the playlist failed".to_string());
+                    }
+                },
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return SourceOutcome::Yield;
+            }
+        }
+    }
+
+    async fn run_relay(&self, source_id: &str, level: usize, label: &str, retry: Duration) -> SourceOutcome {
+        let source_player = match self.registry.read().await.get(source_id).cloned() {
+            Some(entry) => entry.read().await.player.clone(),
+            None => return SourceOutcome::Failed(format!("stream '{}' not found", source_id)),
+        };
+
+        let (feed_tx, feed) = mpsc::channel(500);
+        if let Err(e) = source_player.register_listener(feed_tx, None, SlowListenerPolicy::Drop).await {
+            return SourceOutcome::Failed(e);
+        }
+
+        let source = PlaybackSource::Relay { feed, label: format!("Relay of {}", source_id) };
+        match self.play(source, Some(retry), None, level, label, None).await {
+            PlaybackResult::Finished | PlaybackResult::Skipped => SourceOutcome::Yield,
+            PlaybackResult::Interrupted => SourceOutcome::Interrupted,
+            PlaybackResult::Silence => SourceOutcome::Failed("silence detected".to_string()),
+            PlaybackResult::Error(e) => SourceOutcome::Failed(e),
+        }
+    }
+
+    async fn run_generated(&self, frequency_hz: f32, level: usize, label: &str, retry: Duration) -> SourceOutcome {
+        match self.play(PlaybackSource::Tone { frequency_hz }, Some(retry), None, level, label, None).await {
+            PlaybackResult::Interrupted => SourceOutcome::Interrupted,
+            PlaybackResult::Error(e) => SourceOutcome::Failed(e),
+            _ => SourceOutcome::Yield,
+        }
+    }
+
+    async fn play(
+        &self,
+        source: PlaybackSource,
+        limit: Option<Duration>,
+        start_at_ms: Option<u64>,
+        level: usize,
+        label: &str,
+        position: Option<StreamPosition>,
+    ) -> PlaybackResult {
+        let handles = match self.player.play_source_at(source, limit, start_at_ms).await {
+            Ok(h) => h,
+            Err(e) => {
+                self.record_error("player_unavailable").await;
+                return PlaybackResult::Error(format!("Error issuing play for {}: {}", label, e));
+            },
+        };
+
+        // Wait for the actor to confirm the new source is now the active one,
+        // then broadcast metadata so connected listeners see the new track
+        // (covers normal playlist advance, skip, and any other trigger).
+        let started = handles.started.await.is_ok();
+        if started {
+            self.set_active(level, label, position).await;
+            broadcast_metadata(&self.registry, &self.stream_id).await;
+        }
+
+        let result = match handles.result.await {
+            Ok(r) => r,
+            Err(e) => PlaybackResult::Error(format!("Lost play result channel for {}: {}", label, e)),
+        };
+        match &result {
+            PlaybackResult::Error(_) if started => self.record_error("playback").await,
+            PlaybackResult::Error(_) => self.record_error("start").await,
+            PlaybackResult::Silence => self.record_error("silence").await,
+            _ => {},
+        }
+        result
+    }
+
+    async fn record_error(&self, kind: &'static str) {
+        if let Some(entry) = self.registry.read().await.get(&self.stream_id) {
+            entry.read().await.metrics.record_playback_error(kind);
+        }
+    }
+
+    async fn set_active(&self, level: usize, label: &str, position: Option<StreamPosition>) {
+        let entry = match self.registry.read().await.get(&self.stream_id).cloned() {
+            Some(e) => e,
+            None => return,
+        };
+        let metadata = self.player.get_metadata().await.ok().flatten();
+
+        let mut entry = entry.write().await;
+        if position.is_some() {
+            entry.position = position;
+        }
+        let track = metadata.as_ref().map(|m| (m.title.clone(), m.author.clone()));
+        if let Some(metadata) = metadata {
+            if entry.history.len() == HISTORY_LEN {
+                entry.history.pop_front();
+            }
+            entry.history.push_back(HistoryEntry {
+                title: metadata.title,
+                author: metadata.author,
+                started_at_ms: now_ms(),
+                source: label.to_string(),
+            });
+        }
+
+        let changed = entry.failover.level != level || entry.failover.source != label;
+        if changed {
+            if level > 0 {
+                warn!(fallback_level = level, "Now on fallback source: {}", label);
+            } else if entry.failover.level > 0 {
+                info!("Recovered to the primary playlist");
+            }
+            entry.failover.level = level;
+            entry.failover.source = label.to_string();
+        }
+        drop(entry);
+
+        // Simulcasts have their own listeners, so each keeps its own figures.
+        if let Some((title, author)) = track {
+            for member in simulcast_group(&self.registry, &self.stream_id).await {
+                let member = member.read().await;
+                member.audience.track_started(title.clone(), author.clone());
+                member.metrics.track_changes.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if changed {
+            broadcast_schedule(&self.registry, &self.stream_id, label, level).await;
+            self.admin_event(AdminEvent::SourceConnected {
+                stream: self.stream_id.clone(),
+                source: label.to_string(),
+                fallback_level: level,
+            }).await;
+        }
+    }
+
+    async fn next_queued(&self) -> Option<String> {
+        let entry = self.registry.read().await.get(&self.stream_id).cloned()?;
+        let next = entry.write().await.queue.pop_front();
+        next
+    }
+
+    async fn admin_event(&self, event: AdminEvent) {
+        if let Some(entry) = self.registry.read().await.get(&self.stream_id).cloned() {
+            let _ = entry.read().await.admin_tx.send(event);
+        }
+    }
+
+    async fn alert(&self, label: &str, reason: &str) {
+        let message = format!("{} failed: {}", label, reason);
+
+        let entry = match self.registry.read().await.get(&self.stream_id).cloned() {
+            Some(e) => e,
+            None => return,
+        };
+        let mut entry = entry.write().await;
+        // Retries of a source that is still broken would otherwise repeat the
+        // same alert every round.
+        if entry.failover.last_alert.as_deref() != Some(message.as_str()) {
+            error!(alert = true, "{}", message);
+            let _ = entry.admin_tx.send(AdminEvent::Alert { stream: self.stream_id.clone(), message: message.clone() });
+            entry.failover.last_alert = Some(message);
+        }
+    }
+}
+
+pub async fn get_playlist_files(path: &str) -> Result<Vec<String>, String> {
+    let mut dir = fs::read_dir(path).await.map_err(|x| x.to_string())?;
+    let mut file_names = Vec::new();
+
+    debug!("Loading playlist folder: {}", path);
+    while let Some(entry) = dir.next_entry().await.map_err(|x| x.to_string())? {
+        let metadata = entry.metadata().await.map_err(|x| x.to_string())?;
+        if metadata.is_file() && entry.path().extension().and_then(|s| s.to_str()) == Some("opus") {
+            if let Some(name) = entry.file_name().to_str() {
+                let full_path = Path::new(&path).join(name);
+                file_names.push(full_path.to_string_lossy().to_string());
+            }
+        }
+    }
+
+    if file_names.is_empty() {
+        return Err("No .opus files found in the specified directory".to_string());
+    }
+
+    file_names.sort();
+
+    Ok(file_names)
+}
@@ -0,0 +1,236 @@
+use std::{env, net::IpAddr};
+
+use hyper::HeaderMap;
+use tracing::info;
+
+/// The address a request came from, after `X-Forwarded-For` from trusted
+/// proxies. Stored in the request's extensions.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// An address, or a network in CIDR notation.
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn parse(s: &str) -> Result<Self, String> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("invalid address '{}'", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse::<u8>().ok().filter(|p| *p <= max).ok_or_else(|| format!("invalid prefix in '{}'", s))?,
+            None => max,
+        };
+        // An IPv4-mapped network, e.g. ::ffff:10.0.0.0/104, is stored as the
+        // IPv4 network it covers, since peers are compared canonicalized.
+        match (addr, addr.to_canonical()) {
+            (IpAddr::V6(_), v4 @ IpAddr::V4(_)) if prefix >= 96 => Ok(Self { addr: v4, prefix: prefix - 96 }),
+            (IpAddr::V6(_), IpAddr::V4(_)) => Err(format!("prefix in '{}' is wider than the IPv4-mapped range", s)),
+            _ => Ok(Self { addr, prefix }),
+        }
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Reverse proxies whose `X-Forwarded-For` header is believed, from
+/// `TRUSTED_PROXIES`. Empty by default, so the header is ignored.
+#[derive(Debug, Default)]
+pub struct TrustedProxies(Vec<Network>);
+
+impl TrustedProxies {
+    /// A comma-separated list of addresses and CIDR networks, e.g.
+    /// `127.0.0.1, 10.0.0.0/8`.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(Network::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+
+    pub fn from_env() -> Self {
+        let Ok(list) = env::var("TRUSTED_PROXIES") else {
+            return Self::default();
+        };
+        let proxies = Self::parse(&list).unwrap_or_else(|e| panic!("Invalid TRUSTED_PROXIES: {}", e));
+        if !proxies.0.is_empty() {
+            info!("Trusting X-Forwarded-For from {}", list.trim());
+        }
+        proxies
+    }
+
+    /// Whether `ip` is one of the trusted proxies.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+
+    /// The peer's address, unless it is a trusted proxy: then the nearest
+    /// `X-Forwarded-For` hop that isn't one. Hops further left could have
+    /// been made up by the client, so they are only reached through
+    /// trusted proxies.
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.contains(peer) {
+            return peer;
+        }
+        let hops: Vec<&str> = headers.get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect();
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            match hop.parse::<IpAddr>() {
+                Ok(ip) => {
+                    client = ip.to_canonical();
+                    if !self.contains(client) {
+                        break;
+                    }
+                },
+                // Garbage from a proxy we trust; stop at what we have.
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn forwarded(hops: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for hop in hops {
+            headers.append("x-forwarded-for", HeaderValue::from_str(hop).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_addresses_and_networks() {
+        assert!(Network::parse("10.0.0.1").is_ok());
+        assert!(Network::parse("10.0.0.0/8").is_ok());
+        assert!(Network::parse("0.0.0.0/0").is_ok());
+        assert!(Network::parse("10.0.0.1/32").is_ok());
+        assert!(Network::parse("fd00::/8").is_ok());
+        assert!(Network::parse("::/0").is_ok());
+        assert!(Network::parse("::1/128").is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_networks() {
+        assert!(Network::parse("").is_err());
+        assert!(Network::parse("10.0.0").is_err());
+        assert!(Network::parse("10.0.0.0/33").is_err());
+        assert!(Network::parse("10.0.0.0/").is_err());
+        assert!(Network::parse("10.0.0.0/-1").is_err());
+        assert!(Network::parse("::1/129").is_err());
+        assert!(Network::parse("example.com").is_err());
+        assert!(TrustedProxies::parse("127.0.0.1, nope").is_err());
+    }
+
+    #[test]
+    fn contains_v4() {
+        let net = Network::parse("10.1.0.0/16").unwrap();
+        assert!(net.contains(ip("10.1.0.0")));
+        assert!(net.contains(ip("10.1.255.255")));
+        assert!(!net.contains(ip("10.2.0.0")));
+        assert!(!net.contains(ip("fd00::1")));
+
+        let host = Network::parse("10.0.0.1/32").unwrap();
+        assert!(host.contains(ip("10.0.0.1")));
+        assert!(!host.contains(ip("10.0.0.2")));
+
+        let all = Network::parse("0.0.0.0/0").unwrap();
+        assert!(all.contains(ip("1.2.3.4")));
+        assert!(all.contains(ip("255.255.255.255")));
+    }
+
+    #[test]
+    fn contains_v6() {
+        let net = Network::parse("fd00::/8").unwrap();
+        assert!(net.contains(ip("fd12::1")));
+        assert!(!net.contains(ip("fe80::1")));
+        assert!(!net.contains(ip("10.0.0.1")));
+
+        let host = Network::parse("::1/128").unwrap();
+        assert!(host.contains(ip("::1")));
+        assert!(!host.contains(ip("::2")));
+
+        let all = Network::parse("::/0").unwrap();
+        assert!(all.contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn mapped_addresses_match_their_ipv4_form() {
+        let net = Network::parse("::ffff:10.0.0.0/104").unwrap();
+        assert!(net.contains(ip("10.20.30.40")));
+        assert!(net.contains(ip("::ffff:10.20.30.40")));
+        assert!(!net.contains(ip("11.0.0.1")));
+
+        let host = Network::parse("::ffff:127.0.0.1").unwrap();
+        assert!(host.contains(ip("127.0.0.1")));
+        assert!(!host.contains(ip("127.0.0.2")));
+
+        let v4 = Network::parse("127.0.0.0/8").unwrap();
+        assert!(v4.contains(ip("::ffff:127.0.0.1")));
+
+        // Wider than ::ffff:0:0/96 would take in non-mapped addresses.
+        assert!(Network::parse("::ffff:10.0.0.0/95").is_err());
+        assert!(Network::parse("::ffff:0.0.0.0/96").unwrap().contains(ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn client_ip_ignores_header_from_untrusted_peers() {
+        let proxies = TrustedProxies::parse("10.0.0.1").unwrap();
+        let headers = forwarded(&["1.2.3.4"]);
+        assert_eq!(proxies.client_ip(ip("5.6.7.8"), &headers), ip("5.6.7.8"));
+        assert_eq!(TrustedProxies::default().client_ip(ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn client_ip_takes_nearest_untrusted_hop() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&["1.2.3.4"])), ip("1.2.3.4"));
+        // The client made up 6.6.6.6; the trusted proxy appended what it saw.
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&["6.6.6.6, 1.2.3.4"])), ip("1.2.3.4"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&["6.6.6.6", "1.2.3.4, 10.0.0.2"])), ip("1.2.3.4"));
+        assert_eq!(proxies.client_ip(ip("::ffff:10.0.0.1"), &forwarded(&["::ffff:1.2.3.4"])), ip("1.2.3.4"));
+    }
+
+    #[test]
+    fn client_ip_stops_at_garbage() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&["1.2.3.4, junk, 10.0.0.2"])), ip("10.0.0.2"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&[])), ip("10.0.0.1"));
+        // Every hop trusted: the furthest one is all we know.
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded(&["10.0.0.3, 10.0.0.2"])), ip("10.0.0.3"));
+    }
+}
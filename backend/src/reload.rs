@@ -0,0 +1,295 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    fs,
+    signal::unix::{signal, SignalKind},
+    sync::{broadcast, RwLock},
+    time::interval,
+};
+use tracing::{error, info, info_span, warn};
+
+use crate::{
+    AppState, StreamEntry, StreamRegistry,
+    audit::Actor,
+    api::{AdminEvent, AuditAction, ConfigDiff, ReloadReport, ReloadTrigger, StreamChange, StreamEvent},
+    config::{StreamConfig, StreamsConfig},
+    opus_player::OpusPlayerHandle,
+    playout::play_playlist,
+    state::StreamPosition,
+    stats::now_ms,
+};
+
+/// Settings the playout reads when it starts; changing one restarts it.
+const PLAYOUT_FIELDS: [&str; 5] = ["playlist", "fallback", "silence_threshold_secs", "failover_retry_secs", "resume_mid_track"];
+
+/// Registers a stream with its own player and starts its playlist.
+pub async fn start_source(
+    registry: &StreamRegistry,
+    admin_tx: &broadcast::Sender<AdminEvent>,
+    config: &StreamConfig,
+    resume: Option<StreamPosition>,
+) -> Result<(), String> {
+    // The player's own log lines carry the stream they belong to.
+    let player = info_span!("stream", stream_id = %config.id).in_scope(OpusPlayerHandle::new);
+    let mut entry = StreamEntry::new(config.clone(), player.clone(), admin_tx.clone());
+    if let Some(resume) = resume.as_ref().filter(|r| r.playlist == config.playlist) {
+        entry.queue = resume.queue.iter().cloned().collect();
+    }
+    registry.write().await.insert(config.id.clone(), Arc::new(RwLock::new(entry)));
+
+    play_playlist(player, config.playlist.clone(), config.id.clone(), registry.clone(), resume).await
+        .map_err(|e| format!("failed to start playlist '{}': {}", config.playlist, e))
+}
+
+/// Registers a stream that shares its source stream's player.
+pub async fn start_simulcast(
+    registry: &StreamRegistry,
+    admin_tx: &broadcast::Sender<AdminEvent>,
+    config: &StreamConfig,
+) -> Result<(), String> {
+    let source_id = config.source_id();
+    let player = match registry.read().await.get(source_id) {
+        Some(source) => source.read().await.player.clone(),
+        None => return Err(format!("simulcast source '{}' is not running", source_id)),
+    };
+    let entry = StreamEntry::new(config.clone(), player, admin_tx.clone());
+    registry.write().await.insert(config.id.clone(), Arc::new(RwLock::new(entry)));
+    info!(stream_id = %config.id, "Simulcast of '{}'", source_id);
+    Ok(())
+}
+
+/// Unregisters a stream and disconnects its listeners. A source stream's
+/// player is stopped too.
+async fn stop_stream(registry: &StreamRegistry, stream_id: &str) {
+    let Some(entry) = registry.write().await.remove(stream_id) else {
+        return;
+    };
+    let entry = entry.read().await;
+    if let Some(task) = &entry.task {
+        task.abort();
+    }
+    entry.stopped.trigger();
+    if entry.config.simulcast_of.is_none() {
+        let _ = entry.player.stop().await;
+    }
+    info!(stream_id = %stream_id, "Stream stopped");
+}
+
+/// Config keys that differ between two versions of a stream.
+fn changed_fields(old: &StreamConfig, new: &StreamConfig) -> Vec<String> {
+    let table = |cfg: &StreamConfig| match toml::Value::try_from(cfg) {
+        Ok(toml::Value::Table(table)) => table.into_iter().collect::<BTreeMap<_, _>>(),
+        _ => BTreeMap::new(),
+    };
+    let (old, new) = (table(old), table(new));
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+fn diff(running: &HashMap<String, StreamConfig>, default_stream: &Option<String>, next: &StreamsConfig) -> ConfigDiff {
+    let mut diff = ConfigDiff {
+        default_stream_changed: *default_stream != next.default_stream,
+        ..Default::default()
+    };
+    for cfg in &next.streams {
+        match running.get(&cfg.id) {
+            None => diff.added.push(cfg.id.clone()),
+            Some(old) => {
+                let fields = changed_fields(old, cfg);
+                if !fields.is_empty() {
+                    diff.changed.push(StreamChange { stream: cfg.id.clone(), fields });
+                }
+            },
+        }
+    }
+    diff.removed = running.keys()
+        .filter(|id| !next.streams.iter().any(|s| &s.id == *id))
+        .cloned()
+        .collect();
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort_by(|a, b| a.stream.cmp(&b.stream));
+    diff
+}
+
+/// Where the stream's playlist is right now, so a restarted playout carries
+/// on from the current track.
+async fn current_position(entry: &Arc<RwLock<StreamEntry>>) -> Option<StreamPosition> {
+    let (mut position, player) = {
+        let e = entry.read().await;
+        (e.position.clone()?, e.player.clone())
+    };
+    if let Ok(time) = player.get_time_data().await {
+        position.position_ms = (time.current_time_ms as i64 - time.start_time_ms).max(0) as u64;
+    }
+    Some(position)
+}
+
+/// Applies a changed stream to its running entry.
+async fn apply_change(app: &AppState, cfg: &StreamConfig, fields: &[String], warnings: &mut Vec<String>) {
+    let Some(entry) = app.registry.read().await.get(&cfg.id).cloned() else {
+        return;
+    };
+    let player = {
+        let mut e = entry.write().await;
+        e.config = cfg.clone();
+        if fields.iter().any(|f| f == "name") {
+            e.events_tx.send(StreamEvent::Renamed { stream: cfg.id.clone(), name: cfg.name.clone() });
+        }
+        e.player.clone()
+    };
+
+    if cfg.simulcast_of.is_some() || !fields.iter().any(|f| PLAYOUT_FIELDS.contains(&f.as_str())) {
+        return;
+    }
+    // Picks up where the rotation is; `play_playlist` ignores the position
+    // if the folder changed.
+    let resume = current_position(&entry).await;
+    if let Err(e) = play_playlist(player, cfg.playlist.clone(), cfg.id.clone(), app.registry.clone(), resume).await {
+        warnings.push(format!("stream '{}' kept its previous playlist: {}", cfg.id, e));
+    }
+}
+
+async fn apply(app: &AppState, next: &StreamsConfig, diff: &ConfigDiff) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let find = |id: &str| next.streams.iter().find(|s| s.id == id);
+
+    // Switching between source and simulcast swaps the player, so those
+    // streams are stopped and started again.
+    let replaced: Vec<&StreamChange> = diff.changed.iter()
+        .filter(|c| c.fields.iter().any(|f| f == "simulcast_of"))
+        .collect();
+    for id in diff.removed.iter().chain(replaced.iter().map(|c| &c.stream)) {
+        stop_stream(&app.registry, id).await;
+    }
+
+    let starting: Vec<&StreamConfig> = diff.added.iter().map(String::as_str)
+        .chain(replaced.iter().map(|c| c.stream.as_str()))
+        .filter_map(find)
+        .collect();
+    for cfg in starting.iter().filter(|s| s.simulcast_of.is_none()) {
+        if let Err(e) = start_source(&app.registry, &app.admin_events, cfg, None).await {
+            warnings.push(format!("stream '{}': {}", cfg.id, e));
+        }
+    }
+
+    for change in diff.changed.iter().filter(|c| !replaced.iter().any(|r| r.stream == c.stream)) {
+        if let Some(cfg) = find(&change.stream) {
+            apply_change(app, cfg, &change.fields, &mut warnings).await;
+        }
+    }
+
+    for cfg in starting.iter().filter(|s| s.simulcast_of.is_some()) {
+        if let Err(e) = start_simulcast(&app.registry, &app.admin_events, cfg).await {
+            warnings.push(format!("stream '{}': {}", cfg.id, e));
+        }
+    }
+
+    *app.default_stream.write().await = next.default_stream.clone();
+    warnings
+}
+
+/// Re-reads the config file and brings the running streams in line with it.
+/// A file that fails to load or validate is rejected as a whole, leaving
+/// every stream as it was.
+pub async fn reload(app: &AppState, actor: &Actor, trigger: ReloadTrigger) -> ReloadReport {
+    // Held throughout, so concurrent reloads apply one after the other.
+    let mut last = app.last_reload.lock().await;
+    let at_ms = now_ms();
+
+    let report = match StreamsConfig::load(&app.config_path) {
+        Err(e) => {
+            error!(?trigger, "Rejected config reload from {:?}: {}", app.config_path, e);
+            ReloadReport { at_ms, trigger, ok: false, error: Some(e), diff: ConfigDiff::default(), warnings: Vec::new() }
+        },
+        Ok(next) => {
+            let registry = app.registry.read().await;
+            let mut running = HashMap::with_capacity(registry.len());
+            for (id, entry) in registry.iter() {
+                running.insert(id.clone(), entry.read().await.config.clone());
+            }
+            drop(registry);
+
+            let diff = diff(&running, &*app.default_stream.read().await, &next);
+            // The file watcher also sees our own writes, e.g. after a rename.
+            if diff.is_empty() && trigger == ReloadTrigger::File {
+                return ReloadReport { at_ms, trigger, ok: true, error: None, diff, warnings: Vec::new() };
+            }
+
+            let warnings = apply(app, &next, &diff).await;
+            if diff.is_empty() {
+                info!(?trigger, "Config reloaded, nothing changed");
+            } else {
+                let changed: Vec<String> = diff.changed.iter()
+                    .map(|c| format!("{} ({})", c.stream, c.fields.join(", ")))
+                    .collect();
+                info!(
+                    ?trigger,
+                    "Config reloaded: added [{}], removed [{}], changed [{}]{}",
+                    diff.added.join(", "),
+                    diff.removed.join(", "),
+                    changed.join("; "),
+                    if diff.default_stream_changed { ", new default stream" } else { "" },
+                );
+            }
+            for warning in &warnings {
+                warn!("Config reload: {}", warning);
+            }
+            ReloadReport { at_ms, trigger, ok: true, error: None, diff, warnings }
+        },
+    };
+
+    let source = format!("{:?}", trigger).to_lowercase();
+    let detail = match &report.error {
+        Some(error) => format!("{} reload rejected: {}", source, error),
+        None => format!("{} reload", source),
+    };
+    app.audit.record(actor.entry(AuditAction::ConfigReload).after(&report.diff).detail(detail));
+
+    let _ = app.admin_events.send(AdminEvent::ConfigReloaded { report: report.clone() });
+    *last = Some(report.clone());
+    report
+}
+
+/// Reloads the config on every SIGHUP.
+pub async fn run_signal_reloader(app: Arc<AppState>) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Should install SIGHUP handler");
+    while hangup.recv().await.is_some() {
+        info!("Received SIGHUP, reloading config");
+        reload(&app, &Actor::system(), ReloadTrigger::Signal).await;
+    }
+}
+
+/// Polls the config file's modification time every `CONFIG_WATCH_SECS` and
+/// reloads when it changes. Off unless the variable is set.
+pub async fn run_file_watcher(app: Arc<AppState>) {
+    let every = match env::var("CONFIG_WATCH_SECS").ok().map(|secs| secs.parse::<u64>()) {
+        None | Some(Ok(0)) => return,
+        Some(Ok(secs)) => Duration::from_secs(secs),
+        Some(Err(_)) => {
+            warn!("Ignoring invalid CONFIG_WATCH_SECS");
+            return;
+        },
+    };
+    info!("Watching {:?} for changes every {:?}", app.config_path, every);
+
+    let modified = || async { fs::metadata(&app.config_path).await.and_then(|m| m.modified()).ok() };
+    let mut seen = modified().await;
+    let mut ticker = interval(every);
+    loop {
+        ticker.tick().await;
+        let current = modified().await;
+        if current.is_some() && current != seen {
+            seen = current;
+            reload(&app, &Actor::system(), ReloadTrigger::File).await;
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{cmp::Reverse, collections::BTreeMap, net::IpAddr, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::SessionInfo,
+    auth::random_hex,
+    stats::now_ms,
+    store::TomlStore,
+    tokens::hash_secret,
+};
+
+/// A session ends after this long without a request...
+pub const SESSION_IDLE: Duration = Duration::from_secs(60 * 60 * 24);
+/// ...and this long after login, however busy.
+pub const SESSION_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+/// Requests closer together than this don't renew the session again, so
+/// busy sessions don't rewrite the file every time.
+const RENEWAL_INTERVAL_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Public handle for listing and revoking; not the cookie value.
+    pub id: String,
+    pub username: String,
+    pub created_at_ms: u64,
+    pub last_seen_ms: u64,
+    pub expires_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpAddr>,
+}
+
+impl Session {
+    fn renew(&mut self, now_ms: u64) {
+        self.last_seen_ms = now_ms;
+        self.expires_at_ms = (now_ms + SESSION_IDLE.as_millis() as u64)
+            .min(self.created_at_ms + SESSION_MAX_AGE.as_millis() as u64);
+    }
+
+    pub fn info(&self, current: bool) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            username: self.username.clone(),
+            created_at_ms: self.created_at_ms,
+            last_seen_ms: self.last_seen_ms,
+            expires_at_ms: self.expires_at_ms,
+            user_agent: self.user_agent.clone(),
+            ip: self.ip.map(|ip| ip.to_string()),
+            current,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SessionsFile {
+    /// Keyed by the SHA-256 of the cookie value, so the file alone can't be
+    /// used to sign in.
+    #[serde(default)]
+    sessions: BTreeMap<String, Session>,
+}
+
+/// Signed-in admin sessions, kept in a TOML file so restarts don't sign
+/// everyone out.
+pub struct SessionStore(TomlStore<SessionsFile>);
+
+impl SessionStore {
+    /// A missing file loads as no sessions. Expired ones are dropped.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let store = TomlStore::<SessionsFile>::load(path, "sessions")?;
+        let now = now_ms();
+        store.lock().sessions.retain(|_, s| s.expires_at_ms > now);
+        Ok(Self(store))
+    }
+
+    /// Starts a session and returns its cookie value.
+    pub fn create(&self, username: &str, user_agent: Option<String>, ip: Option<IpAddr>, now_ms: u64) -> Result<String, String> {
+        let token = random_hex(32);
+        let mut session = Session {
+            id: random_hex(8),
+            username: username.to_string(),
+            created_at_ms: now_ms,
+            last_seen_ms: now_ms,
+            expires_at_ms: now_ms,
+            user_agent,
+            ip,
+        };
+        session.renew(now_ms);
+        self.update(|sessions| {
+            sessions.insert(hash_secret(&token), session);
+        })?;
+        Ok(token)
+    }
+
+    /// The session, with its expiry pushed back. Expired sessions are
+    /// removed. Either change is saved in the background, since it only
+    /// records activity and shouldn't hold up the request.
+    pub fn validate(&self, token: &str, now_ms: u64) -> Option<Session> {
+        let key = hash_secret(token);
+        let mut file = self.0.lock();
+        let session = file.sessions.get_mut(&key)?;
+        if session.expires_at_ms <= now_ms {
+            file.sessions.remove(&key);
+            self.0.save_in_background(&file);
+            return None;
+        }
+        if now_ms.saturating_sub(session.last_seen_ms) < RENEWAL_INTERVAL_MS {
+            return Some(session.clone());
+        }
+        session.renew(now_ms);
+        let session = session.clone();
+        self.0.save_in_background(&file);
+        Some(session)
+    }
+
+    /// Sessions for which `include` is true, most recently used first.
+    /// `current` is the caller's own cookie value, if any.
+    pub fn list(&self, current: Option<&str>, now_ms: u64, include: impl Fn(&Session) -> bool) -> Vec<SessionInfo> {
+        let current = current.map(hash_secret);
+        let file = self.0.lock();
+        let mut items: Vec<SessionInfo> = file.sessions.iter()
+            .filter(|(_, s)| s.expires_at_ms > now_ms && include(s))
+            .map(|(key, s)| s.info(current.as_ref() == Some(key)))
+            .collect();
+        items.sort_by_key(|s| Reverse(s.last_seen_ms));
+        items
+    }
+
+    pub fn get(&self, id: &str) -> Option<Session> {
+        self.0.lock().sessions.values().find(|s| s.id == id).cloned()
+    }
+
+    pub fn count(&self, now_ms: u64) -> usize {
+        self.0.lock().sessions.values().filter(|s| s.expires_at_ms > now_ms).count()
+    }
+
+    /// Ends the session with this cookie value.
+    pub fn revoke_token(&self, token: &str) -> Result<(), String> {
+        let key = hash_secret(token);
+        self.update(|sessions| {
+            sessions.remove(&key);
+        })
+    }
+
+    /// Ends the session with this public id.
+    pub fn revoke_id(&self, id: &str) -> Result<(), String> {
+        self.update(|sessions| sessions.retain(|_, s| s.id != id))
+    }
+
+    pub fn revoke_user(&self, username: &str) -> Result<(), String> {
+        self.update(|sessions| sessions.retain(|_, s| s.username != username))
+    }
+
+    /// Also drops sessions that have expired since.
+    fn update(&self, change: impl FnOnce(&mut BTreeMap<String, Session>)) -> Result<(), String> {
+        self.0.update(|file| {
+            change(&mut file.sessions);
+            let now = now_ms();
+            file.sessions.retain(|_, s| s.expires_at_ms > now);
+            Ok(())
+        })
+    }
+}
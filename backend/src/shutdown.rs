@@ -0,0 +1,57 @@
+use std::{env, sync::Arc, time::Duration};
+
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+use tracing::warn;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set once the process was asked to stop. Cheap to clone; clones share the
+/// same flag.
+#[derive(Clone)]
+pub struct Shutdown(Arc<watch::Sender<bool>>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Resolves once `trigger` was called, immediately if it already was.
+    pub async fn wait(&self) {
+        let mut rx = self.0.subscribe();
+        // The sender lives in `self`, so the channel can't close under us.
+        let _ = rx.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Waits for SIGTERM or SIGINT.
+pub async fn signal_received() -> &'static str {
+    let mut term = signal(SignalKind::terminate()).expect("Should install SIGTERM handler");
+    let mut int = signal(SignalKind::interrupt()).expect("Should install SIGINT handler");
+    tokio::select! {
+        _ = term.recv() => "SIGTERM",
+        _ = int.recv() => "SIGINT",
+    }
+}
+
+/// How long connections get to drain, from `SHUTDOWN_TIMEOUT_SECS`.
+pub fn timeout_from_env() -> Duration {
+    match env::var("SHUTDOWN_TIMEOUT_SECS") {
+        Ok(secs) => match secs.parse() {
+            Ok(secs) => Duration::from_secs(secs),
+            Err(_) => {
+                warn!("Ignoring invalid SHUTDOWN_TIMEOUT_SECS {:?}", secs);
+                DEFAULT_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_TIMEOUT,
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use tokio::{net::TcpStream, io::{AsyncWriteExt, self}, sync::{oneshot, mpsc}};
+use tracing::error;
 
 #[derive(Debug)]
 pub struct SocketManager {
@@ -32,7 +33,7 @@ impl SocketManager {
                 Err(e) => {
                     // Failed to write to socket
                     sockets_to_clean.push(*id);
-                    eprintln!("[ERROR]: Something happened while writing to socket! {:?}", e);
+                    error!("Something happened while writing to socket! {:?}", e);
                 },
                 _ => {},
             };
@@ -69,7 +70,7 @@ impl SocketManager {
                 Err(e) => {
                     // Failed to write to socket
                     self.sockets.remove(&id);
-                    eprintln!("[ERROR]: Something happened while writing to socket! {:?}", e);
+                    error!("Something happened while writing to socket! {:?}", e);
                 },
                 _ => {},
             };
@@ -107,7 +108,7 @@ impl SocketManager {
 async fn run_socket_manager(mut sm: SocketManager) {
     while let Some(msg) = sm.receiver.recv().await {
         if let Err(e) = sm.handle_message(msg).await {
-            eprintln!("[ERROR(socket_manager)]: Failed to process message {}", e);
+            error!("Failed to process message {}", e);
         }
     }
 }
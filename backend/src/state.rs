@@ -0,0 +1,111 @@
+use std::{collections::BTreeMap, fs, path::{Path, PathBuf}, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::{StreamRegistry, shutdown::Shutdown};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where a stream's primary playlist was when last saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamPosition {
+    pub playlist: String,
+    /// Index into the sorted playlist folder.
+    pub index: usize,
+    /// File at `index`, used to find the track again if the folder changed.
+    pub file: String,
+    #[serde(default)]
+    pub position_ms: u64,
+    /// Files queued to play next, restored along with the position.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queue: Vec<String>,
+}
+
+/// Rotation state written to the state file, keyed by stream id.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayoutState {
+    #[serde(default, rename = "stream")]
+    pub streams: BTreeMap<String, StreamPosition>,
+}
+
+impl PlayoutState {
+    /// A missing state file is not an error: every stream starts from the top.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("read state: {}", e)),
+        };
+        toml::from_str(&raw).map_err(|e| format!("parse state: {}", e))
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), String> {
+        let serialized = toml::to_string_pretty(self).map_err(|e| format!("serialize state: {}", e))?;
+        let tmp: PathBuf = path.with_extension("toml.tmp");
+        tokio::fs::write(&tmp, serialized).await.map_err(|e| format!("write tmp state: {}", e))?;
+        tokio::fs::rename(&tmp, path).await.map_err(|e| format!("rename tmp state: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Periodically snapshots every stream's playlist position to `path`, and
+/// once more when shutdown starts.
+pub async fn run_state_saver(registry: StreamRegistry, path: PathBuf, mut state: PlayoutState, shutdown: Shutdown) {
+    let mut ticker = interval(SAVE_INTERVAL);
+    loop {
+        let stopping = tokio::select! {
+            _ = ticker.tick() => false,
+            _ = shutdown.wait() => true,
+        };
+
+        let next = snapshot(&registry, &state).await;
+        if next != state {
+            match next.save(&path).await {
+                Ok(()) => state = next,
+                Err(e) => error!("Failed to save playout state to {:?}: {}", path, e),
+            }
+        }
+
+        if stopping {
+            info!("Saved playout state to {:?}", path);
+            return;
+        }
+    }
+}
+
+/// Current positions, keeping `previous` for streams that can't report one
+/// right now.
+async fn snapshot(registry: &StreamRegistry, previous: &PlayoutState) -> PlayoutState {
+    let entries: Vec<_> = registry.read().await.iter()
+        .map(|(id, entry)| (id.clone(), entry.clone()))
+        .collect();
+
+    let mut next = previous.clone();
+    next.streams.retain(|id, _| entries.iter().any(|(e, _)| e == id));
+    for (id, entry) in entries {
+        let (mut position, player, queue) = {
+            let e = entry.read().await;
+            // Fallback sources and simulcasts have no rotation of their own.
+            match (&e.position, e.failover.level) {
+                (Some(position), 0) => (position.clone(), e.player.clone(), e.queue.iter().cloned().collect()),
+                _ => continue,
+            }
+        };
+        // While an override is on air the track underneath is suspended,
+        // so keep the last position saved before it started.
+        if player.is_overridden().await.unwrap_or(true) {
+            if let Some(saved) = next.streams.get_mut(&id) {
+                saved.queue = queue;
+            }
+            continue;
+        }
+        position.queue = queue;
+        if let Ok(time) = player.get_time_data().await {
+            position.position_ms = (time.current_time_ms as i64 - time.start_time_ms).max(0) as u64;
+        }
+        next.streams.insert(id, position);
+    }
+    next
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::BuildHasher,
+    net::IpAddr,
+    sync::{Arc, Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::api::{ListenerSession, StreamStats, TrackAudience};
+
+/// Finished listener sessions kept per stream.
+const SESSION_HISTORY_LEN: usize = 500;
+/// Finished tracks kept per stream, matching the playout history.
+const TRACK_HISTORY_LEN: usize = 50;
+
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Hashes listener addresses with a key chosen at startup, so sessions from
+/// the same address can be told apart without keeping the address itself.
+fn hash_ip(ip: IpAddr) -> String {
+    static KEY: OnceLock<std::collections::hash_map::RandomState> = OnceLock::new();
+    format!("{:016x}", KEY.get_or_init(Default::default).hash_one(ip))
+}
+
+struct ActiveSession {
+    connected_at_ms: u64,
+    user_agent: Option<String>,
+    ip_hash: Option<String>,
+}
+
+struct CurrentTrack {
+    title: String,
+    author: String,
+    started_at_ms: u64,
+    /// Listeners multiplied by milliseconds, up to `last_change_ms`.
+    listener_ms: u64,
+    last_change_ms: u64,
+    peak_listeners: usize,
+}
+
+#[derive(Default)]
+struct AudienceState {
+    peak: usize,
+    next_session: u64,
+    active: HashMap<u64, ActiveSession>,
+    /// Finished sessions, oldest first.
+    sessions: VecDeque<ListenerSession>,
+    finished_listening_ms: u64,
+    track: Option<CurrentTrack>,
+    /// Finished tracks, oldest first.
+    tracks: VecDeque<TrackAudience>,
+}
+
+impl AudienceState {
+    /// Credits the current track with the listeners it had since the last
+    /// change.
+    fn accrue(&mut self, now: u64) {
+        let listeners = self.active.len();
+        if let Some(track) = &mut self.track {
+            track.listener_ms += listeners as u64 * now.saturating_sub(track.last_change_ms);
+            track.last_change_ms = now;
+            track.peak_listeners = track.peak_listeners.max(listeners);
+        }
+    }
+}
+
+fn track_audience(track: &CurrentTrack, now: u64) -> TrackAudience {
+    let duration_ms = now.saturating_sub(track.started_at_ms);
+    TrackAudience {
+        title: track.title.clone(),
+        author: track.author.clone(),
+        started_at_ms: track.started_at_ms,
+        duration_ms,
+        average_listeners: if duration_ms == 0 { 0.0 } else { track.listener_ms as f64 / duration_ms as f64 },
+        peak_listeners: track.peak_listeners,
+    }
+}
+
+/// Listener counts and session history for one stream. Cheap to clone;
+/// clones share the same state.
+#[derive(Clone, Default)]
+pub struct Audience(Arc<Mutex<AudienceState>>);
+
+impl Audience {
+    /// Records a new listener and returns its session id and the new count,
+    /// unless there are `max` already.
+    pub fn join(&self, max: Option<usize>, user_agent: Option<String>, ip: Option<IpAddr>) -> Option<(u64, usize)> {
+        let now = now_ms();
+        let mut state = self.0.lock().unwrap();
+        if max.is_some_and(|max| state.active.len() >= max) {
+            return None;
+        }
+        state.accrue(now);
+        let id = state.next_session;
+        state.next_session += 1;
+        state.active.insert(id, ActiveSession {
+            connected_at_ms: now,
+            user_agent,
+            ip_hash: ip.map(hash_ip),
+        });
+        let count = state.active.len();
+        state.peak = state.peak.max(count);
+        if let Some(track) = &mut state.track {
+            track.peak_listeners = track.peak_listeners.max(count);
+        }
+        Some((id, count))
+    }
+
+    /// Ends a session started by `join` and returns the new count.
+    pub fn leave(&self, session: u64) -> usize {
+        let now = now_ms();
+        let mut state = self.0.lock().unwrap();
+        state.accrue(now);
+        if let Some(active) = state.active.remove(&session) {
+            let duration_ms = now.saturating_sub(active.connected_at_ms);
+            state.finished_listening_ms += duration_ms;
+            if state.sessions.len() == SESSION_HISTORY_LEN {
+                state.sessions.pop_front();
+            }
+            state.sessions.push_back(ListenerSession {
+                connected_at_ms: active.connected_at_ms,
+                duration_ms,
+                user_agent: active.user_agent,
+                ip_hash: active.ip_hash,
+                active: false,
+            });
+        }
+        state.active.len()
+    }
+
+    /// Current and peak listener counts.
+    pub fn counts(&self) -> (usize, usize) {
+        let state = self.0.lock().unwrap();
+        (state.active.len(), state.peak)
+    }
+
+    /// Closes the audience figures of the previous track and starts new ones.
+    pub fn track_started(&self, title: String, author: String) {
+        let now = now_ms();
+        let mut state = self.0.lock().unwrap();
+        state.accrue(now);
+        if let Some(track) = state.track.take() {
+            if state.tracks.len() == TRACK_HISTORY_LEN {
+                state.tracks.pop_front();
+            }
+            let finished = track_audience(&track, now);
+            state.tracks.push_back(finished);
+        }
+        let listeners = state.active.len();
+        state.track = Some(CurrentTrack {
+            title,
+            author,
+            started_at_ms: now,
+            listener_ms: 0,
+            last_change_ms: now,
+            peak_listeners: listeners,
+        });
+    }
+
+    pub fn snapshot(&self, stream_id: &str) -> StreamStats {
+        let now = now_ms();
+        let mut state = self.0.lock().unwrap();
+        state.accrue(now);
+
+        // Most recent first, with connected listeners ahead of finished ones.
+        let mut sessions: Vec<ListenerSession> = state.active.values()
+            .map(|s| ListenerSession {
+                connected_at_ms: s.connected_at_ms,
+                duration_ms: now.saturating_sub(s.connected_at_ms),
+                user_agent: s.user_agent.clone(),
+                ip_hash: s.ip_hash.clone(),
+                active: true,
+            })
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.connected_at_ms));
+        sessions.extend(state.sessions.iter().rev().cloned());
+
+        let active_ms: u64 = sessions.iter().filter(|s| s.active).map(|s| s.duration_ms).sum();
+        let total_ms = state.finished_listening_ms + active_ms;
+
+        let mut tracks: Vec<TrackAudience> = state.track.iter().map(|t| track_audience(t, now)).collect();
+        tracks.extend(state.tracks.iter().rev().cloned());
+
+        StreamStats {
+            stream: stream_id.to_string(),
+            listeners: state.active.len(),
+            peak_listeners: state.peak,
+            total_listening_hours: total_ms as f64 / 3_600_000.0,
+            sessions,
+            tracks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_stops_at_the_cap_until_someone_leaves() {
+        let audience = Audience::default();
+        let (first, _) = audience.join(Some(2), None, None).unwrap();
+        assert_eq!(audience.join(Some(2), None, None).map(|(_, count)| count), Some(2));
+        assert!(audience.join(Some(2), None, None).is_none());
+        audience.leave(first);
+        assert!(audience.join(Some(2), None, None).is_some());
+        assert!(audience.join(None, None, None).is_some());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, MutexGuard},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use tracing::warn;
+
+/// Admin state kept in a TOML file, readable by its owner only since the
+/// files hold password and secret hashes. Changes are made to a copy that
+/// only replaces the data once it has been written.
+pub struct TomlStore<T> {
+    path: PathBuf,
+    /// Names the file in errors, e.g. "users".
+    what: &'static str,
+    data: Mutex<T>,
+    /// Counts saves, which are numbered while `data` is locked...
+    version: AtomicU64,
+    /// ...and the latest one written, so a background save never overwrites
+    /// a newer one.
+    written: Arc<Mutex<u64>>,
+}
+
+impl<T: Clone + Default + Serialize + DeserializeOwned> TomlStore<T> {
+    /// A missing file loads as empty.
+    pub fn load(path: &Path, what: &'static str) -> Result<Self, String> {
+        let data = match fs::read_to_string(path) {
+            Ok(raw) => toml::from_str(&raw).map_err(|e| format!("parse {}: {}", what, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+            Err(e) => return Err(format!("read {}: {}", what, e)),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            what,
+            data: Mutex::new(data),
+            version: AtomicU64::new(0),
+            written: Arc::default(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The data, for reads and for changes that `save_in_background` then
+    /// writes.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.data.lock().expect("store lock")
+    }
+
+    /// Applies `change` to a copy of the data and saves it; nothing changes
+    /// if either step fails.
+    pub fn update<R, E: From<String>>(&self, change: impl FnOnce(&mut T) -> Result<R, E>) -> Result<R, E> {
+        let mut data = self.lock();
+        let mut next = data.clone();
+        let result = change(&mut next)?;
+        let contents = self.serialize(&next)?;
+        write_newer(&self.path, &contents, self.next_version(), &self.written)?;
+        *data = next;
+        Ok(result)
+    }
+
+    /// Writes `data`, as changed through `lock` and still locked, from a
+    /// blocking task, for changes that only record activity and shouldn't
+    /// hold up a request. Failures are logged.
+    pub fn save_in_background(&self, data: &T) {
+        let contents = match self.serialize(data) {
+            Ok(contents) => contents,
+            Err(e) => return warn!("Failed to save {}: {}", self.what, e),
+        };
+        let (path, version, written, what) = (self.path.clone(), self.next_version(), self.written.clone(), self.what);
+        let write = move || {
+            if let Err(e) = write_newer(&path, &contents, version, &written) {
+                warn!("Failed to save {}: {}", what, e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(write)),
+            Err(_) => write(),
+        }
+    }
+
+    fn serialize(&self, data: &T) -> Result<String, String> {
+        toml::to_string_pretty(data).map_err(|e| format!("serialize {}: {}", self.what, e))
+    }
+
+    fn next_version(&self) -> u64 {
+        self.version.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Writes `contents` unless a later version already has been.
+fn write_newer(path: &Path, contents: &str, version: u64, written: &Mutex<u64>) -> Result<(), String> {
+    let mut written = written.lock().expect("store write lock");
+    if *written > version {
+        return Ok(());
+    }
+    write_private(path, contents)?;
+    *written = version;
+    Ok(())
+}
+
+/// Replaces the file atomically, readable by its owner only.
+pub fn write_private(path: &Path, contents: &str) -> Result<(), String> {
+    let tmp: PathBuf = path.with_extension("toml.tmp");
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&tmp)
+        .and_then(|mut f| f.write_all(contents.as_bytes()))
+        .map_err(|e| format!("write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("rename {:?}: {}", tmp, e))?;
+    Ok(())
+}
+
+/// Entries kept in a map in memory but listed as an array of tables in the
+/// file, e.g. `[[user]]`.
+pub trait Keyed {
+    fn key(&self) -> &str;
+}
+
+/// `#[serde(with = "keyed")]` for a `BTreeMap<String, V>` of `Keyed`
+/// entries.
+pub mod keyed {
+    use super::*;
+
+    pub fn serialize<S: Serializer, V: Serialize>(map: &BTreeMap<String, V>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.values())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de> + Keyed>(deserializer: D) -> Result<BTreeMap<String, V>, D::Error> {
+        let entries = Vec::<V>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|v| (v.key().to_string(), v)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        name: String,
+        value: u32,
+    }
+
+    impl Keyed for Entry {
+        fn key(&self) -> &str {
+            &self.name
+        }
+    }
+
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    struct File {
+        #[serde(default, rename = "entry", with = "keyed")]
+        entries: BTreeMap<String, Entry>,
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("octopus-store-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn keyed_maps_round_trip_as_tables() {
+        let path = temp_path("keyed");
+        let store = TomlStore::<File>::load(&path, "entries").unwrap();
+        store.update(|file| {
+            file.entries.insert("b".to_string(), Entry { name: "b".to_string(), value: 2 });
+            Ok::<_, String>(())
+        }).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("[[entry]]"));
+        let reloaded = TomlStore::<File>::load(&path, "entries").unwrap();
+        assert_eq!(reloaded.lock().entries.get("b").map(|e| e.value), Some(2));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn older_saves_never_overwrite_newer_ones() {
+        let path = temp_path("versions");
+        let written = Mutex::new(0);
+        write_newer(&path, "new = 1", 2, &written).unwrap();
+        write_newer(&path, "old = 1", 1, &written).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new = 1");
+        fs::remove_file(&path).unwrap();
+    }
+}
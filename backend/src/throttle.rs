@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::limits::positive_env;
+
+/// Failures allowed before each further one has to wait.
+const FREE_ATTEMPTS: u32 = 3;
+const DEFAULT_MAX_FAILURES: u32 = 10;
+const DEFAULT_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+/// At most this many addresses and usernames are tracked; stale ones are
+/// forgotten first, then the least recently failed.
+const MAX_TRACKED: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Ip(IpAddr),
+    User(String),
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    blocked_until: Option<Instant>,
+}
+
+/// What a failed login led to.
+pub struct Failure {
+    /// Failures in a row for the username.
+    pub count: u32,
+    /// Set when this failure locked the username or address out.
+    pub locked_for: Option<Duration>,
+}
+
+/// Slows down password guessing, per client address and per username.
+/// After a few failures each further attempt waits twice as long as the
+/// one before; `LOGIN_MAX_FAILURES` in a row lock out for
+/// `LOGIN_LOCKOUT_SECS`. Failures are forgotten after a lockout period
+/// without any. A successful login only clears its username's failures, so
+/// logging in to another account doesn't reset an address's count.
+pub struct LoginThrottle {
+    max_failures: u32,
+    lockout: Duration,
+    failures: Mutex<HashMap<Key, Failures>>,
+}
+
+impl LoginThrottle {
+    pub fn from_env() -> Self {
+        Self::new(
+            positive_env("LOGIN_MAX_FAILURES").map_or(DEFAULT_MAX_FAILURES, |n| n.min(u32::MAX as u64) as u32),
+            positive_env("LOGIN_LOCKOUT_SECS").map_or(DEFAULT_LOCKOUT, Duration::from_secs),
+        )
+    }
+
+    pub fn new(max_failures: u32, lockout: Duration) -> Self {
+        Self { max_failures, lockout, failures: Mutex::new(HashMap::new()) }
+    }
+
+    /// A separate throttle with the same limits and no failures yet.
+    pub fn fresh(&self) -> Self {
+        Self::new(self.max_failures, self.lockout)
+    }
+
+    fn keys(ip: Option<IpAddr>, username: &str) -> impl Iterator<Item = Key> {
+        ip.map(Key::Ip).into_iter().chain([Key::User(username.to_string())])
+    }
+
+    /// How long until the address and username may try again, if either
+    /// has to wait.
+    pub fn check(&self, ip: Option<IpAddr>, username: &str) -> Option<Duration> {
+        let now = Instant::now();
+        let failures = self.failures.lock().expect("throttle lock");
+        Self::keys(ip, username)
+            .filter_map(|key| failures.get(&key)?.blocked_until)
+            .filter_map(|until| until.checked_duration_since(now))
+            .max()
+    }
+
+    pub fn record_failure(&self, ip: Option<IpAddr>, username: &str) -> Failure {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().expect("throttle lock");
+        let mut result = Failure { count: 0, locked_for: None };
+        for key in Self::keys(ip, username) {
+            if !failures.contains_key(&key) {
+                self.make_room(&mut failures, now);
+            }
+            let is_user = matches!(key, Key::User(_));
+            let entry = failures.entry(key).or_insert(Failures { count: 0, last: now, blocked_until: None });
+            if self.is_stale(entry, now) {
+                entry.count = 0;
+            }
+            entry.count += 1;
+            entry.last = now;
+            let wait = if entry.count >= self.max_failures {
+                result.locked_for = Some(self.lockout);
+                Some(self.lockout)
+            } else if entry.count > FREE_ATTEMPTS {
+                let backoff = Duration::from_secs(1 << (entry.count - FREE_ATTEMPTS - 1).min(16));
+                Some(backoff.min(self.lockout))
+            } else {
+                None
+            };
+            entry.blocked_until = wait.map(|wait| now + wait);
+            if is_user {
+                result.count = entry.count;
+            }
+        }
+        result
+    }
+
+    /// Clears the username's failures. The address's are left to expire, or
+    /// a login to an account the client controls would reset them.
+    pub fn record_success(&self, username: &str) {
+        let mut failures = self.failures.lock().expect("throttle lock");
+        failures.remove(&Key::User(username.to_string()));
+    }
+
+    /// Makes space for one more entry, forgetting stale ones and then, if
+    /// none were, the one that failed longest ago.
+    fn make_room(&self, failures: &mut HashMap<Key, Failures>, now: Instant) {
+        if failures.len() < MAX_TRACKED {
+            return;
+        }
+        failures.retain(|_, f| !self.is_stale(f, now));
+        while failures.len() >= MAX_TRACKED {
+            let Some(oldest) = failures.iter().min_by_key(|(_, f)| f.last).map(|(key, _)| key.clone()) else {
+                break;
+            };
+            failures.remove(&oldest);
+        }
+    }
+
+    fn is_stale(&self, failures: &Failures, now: Instant) -> bool {
+        let blocked = failures.blocked_until.is_some_and(|until| until > now);
+        !blocked && now.duration_since(failures.last) >= self.lockout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn backs_off_after_free_attempts_then_locks_out() {
+        let throttle = LoginThrottle::new(6, Duration::from_secs(600));
+        for n in 1..=FREE_ATTEMPTS {
+            let failure = throttle.record_failure(ip("10.0.0.1"), "alice");
+            assert_eq!(failure.count, n);
+            assert!(failure.locked_for.is_none());
+            assert!(throttle.check(ip("10.0.0.1"), "alice").is_none());
+        }
+
+        throttle.record_failure(ip("10.0.0.1"), "alice");
+        let first = throttle.check(ip("10.0.0.1"), "alice").unwrap();
+        assert!(first <= Duration::from_secs(1));
+        throttle.record_failure(ip("10.0.0.1"), "alice");
+        let second = throttle.check(ip("10.0.0.1"), "alice").unwrap();
+        assert!(second > first && second <= Duration::from_secs(2));
+
+        let failure = throttle.record_failure(ip("10.0.0.1"), "alice");
+        assert_eq!(failure.count, 6);
+        assert_eq!(failure.locked_for, Some(Duration::from_secs(600)));
+        assert!(throttle.check(ip("10.0.0.1"), "alice").unwrap() > Duration::from_secs(590));
+    }
+
+    #[test]
+    fn throttles_by_address_and_by_username() {
+        let throttle = LoginThrottle::new(10, Duration::from_secs(600));
+        for user in ["a", "b", "c", "d"] {
+            throttle.record_failure(ip("10.0.0.1"), user);
+        }
+        assert!(throttle.check(ip("10.0.0.1"), "e").is_some());
+        assert!(throttle.check(ip("10.0.0.2"), "e").is_none());
+
+        for addr in ["10.0.1.1", "10.0.1.2", "10.0.1.3", "10.0.1.4"] {
+            throttle.record_failure(ip(addr), "victim");
+        }
+        assert!(throttle.check(ip("10.0.2.1"), "victim").is_some());
+        assert!(throttle.check(None, "victim").is_some());
+    }
+
+    #[test]
+    fn success_clears_the_username_but_not_the_address() {
+        let throttle = LoginThrottle::new(10, Duration::from_secs(600));
+        for _ in 0..=FREE_ATTEMPTS {
+            throttle.record_failure(ip("10.0.0.1"), "alice");
+        }
+        throttle.record_success("alice");
+        assert!(throttle.check(ip("10.0.0.2"), "alice").is_none());
+        assert!(throttle.check(ip("10.0.0.1"), "mallory").is_some());
+
+        let failure = throttle.record_failure(ip("10.0.0.1"), "alice");
+        assert_eq!(failure.count, 1);
+    }
+
+    #[test]
+    fn interleaved_successes_do_not_reset_the_address() {
+        let throttle = LoginThrottle::new(5, Duration::from_secs(600));
+        let mut locked = None;
+        for _ in 0..5 {
+            locked = throttle.record_failure(ip("10.0.0.1"), "victim").locked_for.or(locked);
+            throttle.record_success("mallory");
+        }
+        assert!(locked.is_some());
+        assert!(throttle.check(ip("10.0.0.1"), "mallory").is_some());
+    }
+
+    #[test]
+    fn tracks_at_most_max_tracked_entries() {
+        let throttle = LoginThrottle::new(10, Duration::from_secs(600));
+        for n in 0..MAX_TRACKED + 10 {
+            throttle.record_failure(None, &n.to_string());
+        }
+        let failures = throttle.failures.lock().unwrap();
+        assert_eq!(failures.len(), MAX_TRACKED);
+        assert!(!failures.contains_key(&Key::User("0".to_string())));
+        assert!(failures.contains_key(&Key::User((MAX_TRACKED + 9).to_string())));
+    }
+}
@@ -0,0 +1,221 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    api::{ApiTokenInfo, Role},
+    auth::hex,
+    store::{keyed, Keyed, TomlStore},
+    users::User,
+};
+
+/// Marks a bearer credential as an API token rather than a session.
+pub const TOKEN_PREFIX: &str = "oct_";
+
+/// Uses closer together than this only update `last_used_ms` in memory.
+const LAST_USED_SAVE_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub username: String,
+    /// SHA-256 of the secret part. The secret is random, so a fast hash is
+    /// enough.
+    pub secret_hash: String,
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    pub created_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used_ms: Option<u64>,
+}
+
+impl ApiToken {
+    pub fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_at_ms.is_some_and(|at| now_ms >= at)
+    }
+
+    /// The user as seen through the token: their role capped at the token's,
+    /// and their streams narrowed to the token's.
+    pub fn scoped(&self, mut user: User) -> User {
+        let streams = match (&self.streams, user.has_all_streams()) {
+            (None, true) => None,
+            (None, false) => user.streams.clone(),
+            (Some(limit), _) => Some(limit.iter().filter(|id| user.can_access(id)).cloned().collect()),
+        };
+        user.role = user.role.min(self.role);
+        user.streams = streams;
+        user
+    }
+
+    pub fn info(&self) -> ApiTokenInfo {
+        ApiTokenInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            username: self.username.clone(),
+            role: self.role,
+            streams: self.streams.clone(),
+            created_at_ms: self.created_at_ms,
+            expires_at_ms: self.expires_at_ms,
+            last_used_ms: self.last_used_ms,
+        }
+    }
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    hex(&Sha256::digest(secret.as_bytes()))
+}
+
+/// Splits `oct_<id>_<secret>` into its id and secret.
+pub fn parse(token: &str) -> Option<(&str, &str)> {
+    token.strip_prefix(TOKEN_PREFIX)?.split_once('_')
+}
+
+impl Keyed for ApiToken {
+    fn key(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TokensFile {
+    #[serde(default, rename = "token", with = "keyed")]
+    tokens: BTreeMap<String, ApiToken>,
+}
+
+/// API tokens, kept in a TOML file next to the users file.
+pub struct TokenStore(TomlStore<TokensFile>);
+
+impl TokenStore {
+    /// A missing file loads as no tokens.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        TomlStore::load(path, "tokens").map(Self)
+    }
+
+    /// The token `secret` belongs to, if `id` exists and hasn't expired.
+    pub fn verify(&self, id: &str, secret: &str, now_ms: u64) -> Option<ApiToken> {
+        let token = self.get(id)?;
+        let matches: bool = hash_secret(secret).as_bytes().ct_eq(token.secret_hash.as_bytes()).into();
+        (matches && !token.is_expired(now_ms)).then_some(token)
+    }
+
+    /// Records a use. It is only written to disk once a minute per token, so
+    /// busy scripts don't rewrite the file on every request.
+    pub fn touch(&self, id: &str, now_ms: u64) {
+        let mut file = self.0.lock();
+        let Some(token) = file.tokens.get_mut(id) else {
+            return;
+        };
+        if token.last_used_ms.is_some_and(|last| now_ms.saturating_sub(last) < LAST_USED_SAVE_MS) {
+            return;
+        }
+        token.last_used_ms = Some(now_ms);
+        self.0.save_in_background(&file);
+    }
+
+    /// Tokens for which `include` is true, oldest first.
+    pub fn list(&self, include: impl Fn(&ApiToken) -> bool) -> Vec<ApiTokenInfo> {
+        let file = self.0.lock();
+        let mut items: Vec<ApiTokenInfo> = file.tokens.values().filter(|t| include(t)).map(ApiToken::info).collect();
+        items.sort_by_key(|t| t.created_at_ms);
+        items
+    }
+
+    pub fn get(&self, id: &str) -> Option<ApiToken> {
+        self.0.lock().tokens.get(id).cloned()
+    }
+
+    pub fn insert(&self, token: ApiToken) -> Result<(), String> {
+        self.update(|tokens| {
+            tokens.insert(token.id.clone(), token);
+        })
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        self.update(|tokens| {
+            tokens.remove(id);
+        })
+    }
+
+    /// Drops every token of a deleted user.
+    pub fn remove_user(&self, username: &str) -> Result<(), String> {
+        self.update(|tokens| tokens.retain(|_, t| t.username != username))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut BTreeMap<String, ApiToken>)) -> Result<(), String> {
+        self.0.update(|file| {
+            change(&mut file.tokens);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::users::{Permission, Scope};
+
+    fn user(role: Role, streams: Option<&[&str]>) -> User {
+        User {
+            username: "someone".to_string(),
+            password_hash: String::new(),
+            role,
+            streams: streams.map(|s| s.iter().map(|id| id.to_string()).collect()),
+            totp: None,
+        }
+    }
+
+    fn token(role: Role, streams: Option<&[&str]>) -> ApiToken {
+        ApiToken {
+            id: "abc".to_string(),
+            name: "script".to_string(),
+            username: "someone".to_string(),
+            secret_hash: String::new(),
+            role,
+            streams: streams.map(|s| s.iter().map(|id| id.to_string()).collect()),
+            created_at_ms: 0,
+            expires_at_ms: None,
+            last_used_ms: None,
+        }
+    }
+
+    #[test]
+    fn tokens_cap_the_role_but_never_raise_it() {
+        let scoped = token(Role::Viewer, None).scoped(user(Role::Operator, None));
+        assert_eq!(scoped.role, Role::Viewer);
+        assert!(!scoped.can(Permission::Control, Scope::Stream("main")));
+
+        let scoped = token(Role::Owner, None).scoped(user(Role::Dj, None));
+        assert_eq!(scoped.role, Role::Dj);
+        assert!(!scoped.can(Permission::Manage, Scope::AllStreams));
+    }
+
+    #[test]
+    fn tokens_narrow_streams_but_never_widen_them() {
+        // Narrowed to the token's streams.
+        let scoped = token(Role::Dj, Some(&["main"])).scoped(user(Role::Dj, None));
+        assert!(scoped.can_access("main"));
+        assert!(!scoped.can_access("other"));
+        assert!(!scoped.has_all_streams());
+
+        // Streams the user lacks stay out of reach.
+        let scoped = token(Role::Dj, Some(&["main", "other"])).scoped(user(Role::Dj, Some(&["main"])));
+        assert_eq!(scoped.streams, Some(vec!["main".to_string()]));
+
+        // A token for every stream keeps the user's grants.
+        let scoped = token(Role::Dj, None).scoped(user(Role::Dj, Some(&["main"])));
+        assert_eq!(scoped.streams, Some(vec!["main".to_string()]));
+        assert!(!scoped.can(Permission::View, Scope::AllStreams));
+
+        // An owner's lower-role token is bound by its streams, since only
+        // owners ignore grants.
+        let scoped = token(Role::Operator, Some(&["main"])).scoped(user(Role::Owner, None));
+        assert!(!scoped.can_access("other"));
+        assert!(!scoped.can(Permission::Manage, Scope::AllStreams));
+    }
+}
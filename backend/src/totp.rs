@@ -0,0 +1,195 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{auth::random_hex, tokens::hash_secret};
+
+/// Shown as the account's label in authenticator apps.
+pub const ISSUER: &str = "Octopus";
+
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+/// Codes from one step either side are accepted, for clock drift.
+const SKEW_STEPS: u64 = 1;
+const RECOVERY_CODES: usize = 10;
+
+/// What a second factor was checked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Used {
+    Code,
+    /// A recovery code, now spent; `remaining` are left.
+    RecoveryCode { remaining: usize },
+}
+
+/// A user's TOTP (RFC 6238) enrollment. Off until a code from the
+/// authenticator app has confirmed the secret.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Totp {
+    /// Base32, as shown to authenticator apps.
+    pub secret: String,
+    #[serde(default)]
+    pub confirmed: bool,
+    /// SHA-256 of each unused recovery code.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recovery_codes: Vec<String>,
+    /// The last time step a code was accepted for; codes can't be reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_step: Option<u64>,
+}
+
+impl Totp {
+    /// A new, unconfirmed enrollment with a random 160-bit secret.
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 20];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            secret: Secret::Raw(secret.to_vec()).to_encoded().to_string(),
+            confirmed: false,
+            recovery_codes: Vec::new(),
+            last_step: None,
+        }
+    }
+
+    /// The `otpauth://` URI authenticator apps take, usually as a QR code.
+    pub fn provisioning_uri(&self, username: &str) -> String {
+        format!(
+            "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECS}",
+            issuer = ISSUER,
+            user = percent_encode(username),
+            secret = self.secret,
+        )
+    }
+
+    /// Checks a code from the app, allowing for clock drift. Accepted codes
+    /// move `last_step` on so they can't be replayed.
+    pub fn verify_code(&mut self, code: &str, now_secs: u64) -> bool {
+        let code = code.trim();
+        let Ok(bytes) = Secret::Encoded(self.secret.clone()).to_bytes() else {
+            return false;
+        };
+        let Ok(totp) = TOTP::new(Algorithm::SHA1, DIGITS, SKEW_STEPS as u8, STEP_SECS, bytes) else {
+            return false;
+        };
+        let current = now_secs / STEP_SECS;
+        let steps = current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS;
+        let matched = steps
+            .filter(|step| self.last_step.is_none_or(|last| *step > last))
+            .find(|step| totp.generate(step * STEP_SECS).as_bytes().ct_eq(code.as_bytes()).into());
+        if let Some(step) = matched {
+            self.last_step = Some(step);
+        }
+        matched.is_some()
+    }
+
+    /// Checks a code from the app, or else spends a recovery code.
+    pub fn verify(&mut self, code: &str, now_secs: u64) -> Option<Used> {
+        if self.verify_code(code, now_secs) {
+            return Some(Used::Code);
+        }
+        let hash = hash_secret(&normalize_recovery_code(code));
+        let index = self.recovery_codes.iter().position(|stored| stored.as_bytes().ct_eq(hash.as_bytes()).into())?;
+        self.recovery_codes.remove(index);
+        Some(Used::RecoveryCode { remaining: self.recovery_codes.len() })
+    }
+
+    /// Replaces the recovery codes with fresh ones and returns them; only
+    /// their hashes are kept.
+    pub fn new_recovery_codes(&mut self) -> Vec<String> {
+        let codes: Vec<String> = (0..RECOVERY_CODES)
+            .map(|_| {
+                let hex = random_hex(5);
+                format!("{}-{}", &hex[..5], &hex[5..])
+            })
+            .collect();
+        self.recovery_codes = codes.iter().map(|c| hash_secret(&normalize_recovery_code(c))).collect();
+        codes
+    }
+}
+
+/// Recovery codes are accepted with or without the dash, in any case.
+fn normalize_recovery_code(code: &str) -> String {
+    code.trim().chars().filter(|c| *c != '-').flat_map(char::to_lowercase).collect()
+}
+
+/// Escapes everything but unreserved characters, for the URI's label.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn code(totp: &Totp, at_secs: u64) -> String {
+        let bytes = Secret::Encoded(totp.secret.clone()).to_bytes().unwrap();
+        TOTP::new(Algorithm::SHA1, DIGITS, SKEW_STEPS as u8, STEP_SECS, bytes).unwrap().generate(at_secs)
+    }
+
+    #[test]
+    fn accepts_current_and_neighbouring_codes() {
+        let mut totp = Totp::generate();
+        assert!(totp.verify_code(&code(&totp, NOW - STEP_SECS), NOW));
+        assert!(totp.verify_code(&code(&totp, NOW), NOW));
+        assert!(totp.verify_code(&format!(" {} ", code(&totp, NOW + STEP_SECS)), NOW));
+    }
+
+    #[test]
+    fn rejects_wrong_and_distant_codes() {
+        let mut totp = Totp::generate();
+        assert!(!totp.verify_code(&code(&totp, NOW - 2 * STEP_SECS), NOW));
+        assert!(!totp.verify_code(&code(&totp, NOW + 2 * STEP_SECS), NOW));
+        assert!(!totp.verify_code("", NOW));
+        assert!(!totp.verify_code("abcdef", NOW));
+        assert!(totp.last_step.is_none());
+    }
+
+    #[test]
+    fn codes_cannot_be_replayed() {
+        let mut totp = Totp::generate();
+        let current = code(&totp, NOW);
+        assert!(totp.verify_code(&current, NOW));
+        assert!(!totp.verify_code(&current, NOW));
+        assert!(!totp.verify_code(&current, NOW + STEP_SECS));
+        // Nor can an older one, once a newer one was used.
+        assert!(!totp.verify_code(&code(&totp, NOW - STEP_SECS), NOW));
+        assert!(totp.verify_code(&code(&totp, NOW + STEP_SECS), NOW + STEP_SECS));
+    }
+
+    #[test]
+    fn recovery_codes_work_once() {
+        let mut totp = Totp::generate();
+        let codes = totp.new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert!(totp.recovery_codes.iter().all(|stored| !codes.contains(stored)));
+
+        assert_eq!(totp.verify(&codes[0], NOW), Some(Used::RecoveryCode { remaining: RECOVERY_CODES - 1 }));
+        assert_eq!(totp.verify(&codes[0], NOW), None);
+        let loose = codes[1].replace('-', "").to_uppercase();
+        assert_eq!(totp.verify(&loose, NOW), Some(Used::RecoveryCode { remaining: RECOVERY_CODES - 2 }));
+        assert_eq!(totp.verify("00000-00000", NOW), None);
+    }
+
+    #[test]
+    fn new_recovery_codes_replace_the_old_ones() {
+        let mut totp = Totp::generate();
+        let old = totp.new_recovery_codes();
+        totp.new_recovery_codes();
+        assert_eq!(totp.verify(&old[0], NOW), None);
+    }
+
+    #[test]
+    fn provisioning_uri_escapes_the_username() {
+        let totp = Totp::generate();
+        let uri = totp.provisioning_uri("dj bob");
+        assert!(uri.starts_with("otpauth://totp/Octopus:dj%20bob?secret="), "{}", uri);
+        assert!(uri.contains(&totp.secret));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{collections::BTreeMap, path::Path, sync::OnceLock};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::{Role, UserInfo},
+    store::{keyed, Keyed, TomlStore},
+    totp::Totp,
+};
+
+/// The owner created from `ADMIN_PASSWORD`, and the login default.
+pub const DEFAULT_USERNAME: &str = "admin";
+
+/// Shortest password accepted through the admin API.
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// Kinds of admin action, checked against a user's role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Stream details, stats, queues.
+    View,
+    /// Skip, pause, resume, seek, queue edits.
+    Control,
+    /// Renames, overrides, config reloads.
+    Manage,
+    /// Creating, changing and deleting users.
+    ManageUsers,
+}
+
+impl Permission {
+    fn min_role(self) -> Role {
+        match self {
+            Permission::View => Role::Viewer,
+            Permission::Control => Role::Dj,
+            Permission::Manage => Role::Operator,
+            Permission::ManageUsers => Role::Owner,
+        }
+    }
+}
+
+/// What an action applies to.
+#[derive(Debug, Clone, Copy)]
+pub enum Scope<'a> {
+    Stream(&'a str),
+    /// Lists, filtered down to the user's streams.
+    AnyStream,
+    /// Server-wide actions, which need every stream.
+    AllStreams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    pub password_hash: String,
+    pub role: Role,
+    /// Every stream when unset. Ignored for owners.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streams: Option<Vec<String>>,
+    /// Two-factor login, once enrolled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<Totp>,
+}
+
+impl User {
+    pub fn can_access(&self, stream_id: &str) -> bool {
+        self.role == Role::Owner || self.streams.as_ref().is_none_or(|s| s.iter().any(|id| id == stream_id))
+    }
+
+    /// Whether the grants cover every stream, including ones added later.
+    pub fn has_all_streams(&self) -> bool {
+        self.role == Role::Owner || self.streams.is_none()
+    }
+
+    /// Whether logins need a TOTP or recovery code as well.
+    pub fn has_totp(&self) -> bool {
+        self.totp.as_ref().is_some_and(|t| t.confirmed)
+    }
+
+    pub fn can(&self, permission: Permission, scope: Scope) -> bool {
+        self.role >= permission.min_role() && match scope {
+            Scope::Stream(id) => self.can_access(id),
+            Scope::AnyStream => true,
+            Scope::AllStreams => self.has_all_streams(),
+        }
+    }
+
+    pub fn info(&self) -> UserInfo {
+        UserInfo {
+            username: self.username.clone(),
+            role: self.role,
+            streams: if self.role == Role::Owner { None } else { self.streams.clone() },
+            totp: self.has_totp(),
+        }
+    }
+}
+
+impl Keyed for User {
+    fn key(&self) -> &str {
+        &self.username
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct UsersFile {
+    #[serde(default, rename = "user", with = "keyed")]
+    users: BTreeMap<String, User>,
+}
+
+/// Hashes with a fresh salt. Slow on purpose; call from a blocking task.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("hash password: {}", e))
+}
+
+/// Checks `password` against a stored hash; false for unparseable hashes.
+/// Slow on purpose; call from a blocking task.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+/// Something to verify against when the username doesn't exist, so failed
+/// logins take as long whether or not the user does.
+pub fn dummy_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("not a password").unwrap_or_default())
+}
+
+pub fn validate_username(username: &str) -> Result<(), String> {
+    let valid = !username.is_empty()
+        && username.len() <= 64
+        && username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    match valid {
+        true => Ok(()),
+        false => Err("username must be 1-64 letters, digits, '-', '_' or '.'".to_string()),
+    }
+}
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    match password.chars().count() >= MIN_PASSWORD_LEN {
+        true => Ok(()),
+        false => Err(format!("password must be at least {} characters", MIN_PASSWORD_LEN)),
+    }
+}
+
+/// Admin users, kept in a TOML file next to the stream config.
+pub struct UserStore(TomlStore<UsersFile>);
+
+impl UserStore {
+    /// A missing file loads as no users.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        TomlStore::load(path, "users").map(Self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().users.is_empty()
+    }
+
+    pub fn get(&self, username: &str) -> Option<User> {
+        self.0.lock().users.get(username).cloned()
+    }
+
+    pub fn list(&self) -> Vec<UserInfo> {
+        self.0.lock().users.values().map(User::info).collect()
+    }
+
+    /// Adds a user, failing if the name is taken.
+    pub fn insert(&self, user: User) -> Result<(), String> {
+        self.update(|users| {
+            if users.contains_key(&user.username) {
+                return Err(format!("user '{}' already exists", user.username));
+            }
+            users.insert(user.username.clone(), user);
+            Ok(())
+        })
+    }
+
+    /// Applies `change` to a copy of the users and saves it; nothing changes
+    /// if either step fails.
+    pub fn update<T, E: From<String>>(&self, change: impl FnOnce(&mut BTreeMap<String, User>) -> Result<T, E>) -> Result<T, E> {
+        self.0.update(|file| change(&mut file.users))
+    }
+}
+
+pub fn has_owner(users: &BTreeMap<String, User>) -> bool {
+    users.values().any(|u| u.role == Role::Owner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(role: Role, streams: Option<&[&str]>) -> User {
+        User {
+            username: "someone".to_string(),
+            password_hash: String::new(),
+            role,
+            streams: streams.map(|s| s.iter().map(|id| id.to_string()).collect()),
+            totp: None,
+        }
+    }
+
+    #[test]
+    fn each_permission_needs_its_role_or_higher() {
+        let allowed = |role| {
+            [Permission::View, Permission::Control, Permission::Manage, Permission::ManageUsers]
+                .map(|p| user(role, None).can(p, Scope::AllStreams))
+        };
+        assert_eq!(allowed(Role::Viewer), [true, false, false, false]);
+        assert_eq!(allowed(Role::Dj), [true, true, false, false]);
+        assert_eq!(allowed(Role::Operator), [true, true, true, false]);
+        assert_eq!(allowed(Role::Owner), [true, true, true, true]);
+    }
+
+    #[test]
+    fn grants_limit_streams_and_server_wide_actions() {
+        let dj = user(Role::Dj, Some(&["main"]));
+        assert!(dj.can(Permission::Control, Scope::Stream("main")));
+        assert!(!dj.can(Permission::Control, Scope::Stream("other")));
+        // Lists are filtered later, so any grant will do.
+        assert!(dj.can(Permission::View, Scope::AnyStream));
+        assert!(!dj.can(Permission::View, Scope::AllStreams));
+        assert!(!user(Role::Dj, Some(&[])).can(Permission::View, Scope::Stream("main")));
+
+        let everywhere = user(Role::Operator, None);
+        assert!(everywhere.can(Permission::Manage, Scope::AllStreams));
+        assert!(everywhere.can(Permission::Manage, Scope::Stream("added-later")));
+    }
+
+    #[test]
+    fn owners_ignore_grants() {
+        let owner = user(Role::Owner, Some(&["main"]));
+        assert!(owner.can_access("other"));
+        assert!(owner.has_all_streams());
+        assert!(owner.can(Permission::ManageUsers, Scope::AllStreams));
+        assert_eq!(owner.info().streams, None);
+    }
+}
@@ -1,13 +1,38 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::{HashMap, HashSet}, net::{IpAddr, SocketAddr}, sync::Arc};
 
-use futures_util::{SinkExt, StreamExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::tungstenite::{
-    handshake::server::{ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse},
-    Message, Utf8Bytes,
+use futures_util::{SinkExt, StreamExt, stream::SplitStream};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast::{self, error::RecvError}, mpsc, RwLock},
+    task::JoinHandle,
+};
+use tracing::{debug, info};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request as HandshakeRequest, Response as HandshakeResponse},
+        http::StatusCode,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message, Utf8Bytes,
+    },
+    WebSocketStream,
+};
+
+use crate::{
+    AppState, StreamEntry, StreamRegistry,
+    api::{AdminEvent, ClientMessage, ErrorCode, ServerMessage, StreamEvent, TrackMetadata, Visibility},
+    audit::Actor,
+    listeners::ListenerCredentials,
+    stats::Audience,
+    control,
+    limits::ConnectionSlot,
+    opus_player::{BUFFER_SIZE_MS, OpusPlayerHandle, TimeData},
+    shutdown::Shutdown,
+    users::{Permission, Scope, User},
 };
 
-use crate::{AppState, opus_player::{BUFFER_SIZE_MS, OpusPlayerHandle, TimeData}};
+pub const PROTOCOL_VERSION: u32 = 1;
 
 pub struct WSServerContext {
     pub app: Arc<AppState>,
@@ -23,10 +48,37 @@ pub async fn init_ws_server(
 
     let ctx_arc = Arc::from(ctx);
 
-    println!("WS server is up on port {}", port);
+    info!("WS server is up on port {}", port);
 
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(accept_connection(ctx_arc.clone(), stream));
+    let shutdown = ctx_arc.app.shutdown.clone();
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(_) => break,
+            },
+            _ = shutdown.wait() => break,
+        };
+        let peer = peer.ip().to_canonical();
+        let app = &ctx_arc.app;
+        // Connections through a trusted proxy are counted per client once
+        // the handshake names it.
+        let slot = if app.trusted_proxies.contains(peer) {
+            None
+        } else {
+            match app.limits.connection(peer) {
+                Some(slot) => Some(slot),
+                None => {
+                    debug!("Refusing WebSocket connection from {}: too many open", peer);
+                    continue;
+                },
+            }
+        };
+        let ctx = ctx_arc.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            accept_connection(ctx, stream, peer).await
+        });
     }
 
     Ok(())
@@ -35,155 +87,536 @@ pub async fn init_ws_server(
 async fn accept_connection(
     ctx: Arc<WSServerContext>,
     stream: TcpStream,
+    peer: IpAddr,
 ) -> Result<(), String> {
     use std::sync::Mutex;
-    let captured_path: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-    let captured_for_cb = captured_path.clone();
+    // Request path and credentials from the handshake, and the client's
+    // connection slot if it came through a trusted proxy.
+    type Captured = (String, ListenerCredentials, Option<ConnectionSlot>);
+    let captured: Arc<Mutex<Option<Captured>>> = Arc::default();
+    let captured_for_cb = captured.clone();
+    let app = ctx.app.clone();
 
-    let ws_stream = tokio_tungstenite::accept_hdr_async(stream, move |req: &HandshakeRequest, resp: HandshakeResponse| {
-        *captured_for_cb.lock().unwrap() = Some(req.uri().path().to_string());
-        Ok::<_, ErrorResponse>(resp)
-    })
-    .await
-    .map_err(|e| format!("ws handshake: {}", e))?;
+    let header_timeout = app.limits.header_timeout;
+    #[allow(clippy::result_large_err)]
+    let handshake = tokio_tungstenite::accept_hdr_async(stream, move |req: &HandshakeRequest, resp: HandshakeResponse| {
+        let client = app.trusted_proxies.client_ip(peer, req.headers());
+        let slot = if app.trusted_proxies.contains(peer) {
+            let Some(slot) = app.limits.connection(client) else {
+                debug!("Refusing WebSocket connection from {}: too many open", client);
+                let mut response = ErrorResponse::new(Some("too many open connections".to_string()));
+                *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                return Err(response);
+            };
+            Some(slot)
+        } else {
+            None
+        };
+        // No `ClientIp` extension on this port, so the address is set here.
+        let mut credentials = ListenerCredentials::from_request(req, app.cors.allows_request(req));
+        credentials.ip = Some(client);
+        *captured_for_cb.lock().unwrap() = Some((req.uri().path().to_string(), credentials, slot));
+        Ok(resp)
+    });
+    let ws_stream = tokio::time::timeout(header_timeout, handshake)
+        .await
+        .map_err(|_| "ws handshake timed out".to_string())?
+        .map_err(|e| format!("ws handshake: {}", e))?;
+
+    let (path, credentials, _slot) = captured.lock().unwrap().take().ok_or("ws handshake without a request")?;
+    handle_socket(ctx.app.clone(), ws_stream, &path, credentials).await;
+    Ok(())
+}
 
-    let path = captured_path.lock().unwrap().clone().unwrap_or_default();
+/// Whether `path` names a WebSocket endpoint, so the HTTP server knows which
+/// upgrade requests to hand over.
+pub fn is_socket_path(path: &str) -> bool {
+    path == "/ws" || path.starts_with("/ws/streams/") || path.starts_with("/streams/")
+}
 
-    // Expect path of the form /streams/{id}
-    let stream_id = match path.strip_prefix("/streams/") {
+/// Runs an accepted WebSocket, whichever listener it arrived on.
+pub async fn handle_socket<S>(app: Arc<AppState>, ws_stream: WebSocketStream<S>, path: &str, credentials: ListenerCredentials)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let _connection = app.metrics.ws_connected();
+    if path == "/ws" {
+        run_protocol(app, ws_stream, credentials).await;
+        return;
+    }
+
+    // Older clients connect to /streams/{id} and only speak "metadata".
+    let stream_id = match path.strip_prefix("/ws").unwrap_or(path).strip_prefix("/streams/") {
         Some(rest) if !rest.is_empty() => rest.to_string(),
         _ => {
-            // Send a close frame and bail.
-            let (mut write, _) = ws_stream.split();
-            let _ = write
-                .send(Message::Close(Some(tokio_tungstenite::tungstenite::protocol::CloseFrame {
-                    code: tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::Policy,
-                    reason: "Invalid stream path".into(),
-                })))
-                .await;
-            return Ok(());
+            close_with_policy(ws_stream, "Invalid stream path").await;
+            return;
         }
     };
 
-    let entry_arc = match ctx.app.registry.read().await.get(&stream_id).cloned() {
+    let entry_arc = match app.registry.read().await.get(&stream_id).cloned() {
         Some(e) => e,
         None => {
-            let (mut write, _) = ws_stream.split();
-            let _ = write
-                .send(Message::Close(Some(tokio_tungstenite::tungstenite::protocol::CloseFrame {
-                    code: tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode::Policy,
-                    reason: "Unknown stream".into(),
-                })))
-                .await;
-            return Ok(());
+            close_with_policy(ws_stream, "Unknown stream").await;
+            return;
         }
     };
+    if !may_listen(&app, &entry_arc, &stream_id, &credentials).await {
+        close_with_policy(ws_stream, "Private stream").await;
+        return;
+    }
 
-    let (player, mut metadata_rx, stream_name) = {
-        let e = entry_arc.read().await;
-        (e.player.clone(), e.metadata_tx.subscribe(), e.config.name.clone())
-    };
+    run_legacy(entry_arc, ws_stream, app.shutdown.clone()).await;
+}
 
-    let (mut write, mut read) = ws_stream.split();
+async fn close_with_policy<S>(ws_stream: WebSocketStream<S>, reason: &'static str)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut write, _) = ws_stream.split();
+    let _ = write
+        .send(Message::Close(Some(CloseFrame {
+            code: CloseCode::Policy,
+            reason: reason.into(),
+        })))
+        .await;
+}
 
-    // Channel to multiplex outgoing messages.
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+/// Splits the socket and spawns a writer for everything sent on the returned
+/// channel. On shutdown the writer closes the socket with a "restarting"
+/// reason, which ends the read half once the client answers.
+fn spawn_writer<S>(ws_stream: WebSocketStream<S>, shutdown: Shutdown) -> (mpsc::UnboundedSender<String>, SplitStream<WebSocketStream<S>>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut write, read) = ws_stream.split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
 
-    let broadcast_tx = tx.clone();
     tokio::spawn(async move {
-        while let Ok(metadata_json) = metadata_rx.recv().await {
-            if broadcast_tx.send(metadata_json).is_err() {
+        loop {
+            let msg = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                _ = shutdown.wait() => {
+                    let _ = write
+                        .send(Message::Close(Some(CloseFrame {
+                            code: CloseCode::Away,
+                            reason: "restarting".into(),
+                        })))
+                        .await;
+                    break;
+                }
+            };
+            if write.send(Message::Text(Utf8Bytes::from(msg))).await.is_err() {
                 break;
             }
         }
     });
 
-    // Outgoing writer
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if write.send(Message::Text(Utf8Bytes::from(msg))).await.is_err() {
-                break;
-            }
+    (tx, read)
+}
+
+fn send_json<T: Serialize>(tx: &mpsc::UnboundedSender<String>, message: &T) {
+    if let Ok(json) = serde_json::to_string(message) {
+        let _ = tx.send(json);
+    }
+}
+
+fn send_error(tx: &mpsc::UnboundedSender<String>, code: ErrorCode, message: impl Into<String>) {
+    send_json(tx, &ServerMessage::Error { code, message: message.into() });
+}
+
+/// The original protocol: bare `TrackMetadata` pushes for a single stream,
+/// and the text `"metadata"` to ask for the current track.
+async fn run_legacy<S>(entry_arc: Arc<RwLock<StreamEntry>>, ws_stream: WebSocketStream<S>, shutdown: Shutdown)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut events_rx = entry_arc.read().await.events_tx.subscribe();
+
+    let (tx, mut read) = spawn_writer(ws_stream, shutdown);
+
+    let broadcast_tx = tx.clone();
+    let entry_for_events = entry_arc.clone();
+    let forwarder = tokio::spawn(async move {
+        loop {
+            let event = match events_rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            let metadata = match event.event {
+                StreamEvent::TrackChanged { metadata, .. } | StreamEvent::Paused { metadata, .. } => metadata,
+                StreamEvent::Renamed { .. } => match entry_metadata(&entry_for_events).await {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                },
+                StreamEvent::ListenerCount { .. } | StreamEvent::ScheduleChanged { .. } => continue,
+            };
+            send_json(&broadcast_tx, &metadata);
         }
     });
 
-    // Incoming reader: only "metadata" requests are handled.
-    let entry_arc_for_read = entry_arc.clone();
-    let player_for_read = player.clone();
-    let stream_id_for_read = stream_id.clone();
-    tokio::spawn(async move {
-        while let Some(msg_result) = read.next().await {
-            if let Ok(msg) = msg_result {
-                if let Ok(text) = msg.into_text() {
-                    if text.as_str() == "metadata" {
-                        let current_name = entry_arc_for_read.read().await.config.name.clone();
-                        if let Ok(json) = get_metadata_json(&player_for_read, Some(&current_name), Some(&stream_id_for_read)).await {
-                            let _ = tx.send(json);
-                        }
+    while let Some(msg_result) = read.next().await {
+        if let Ok(msg) = msg_result {
+            if let Ok(text) = msg.into_text() {
+                if text.as_str() == "metadata" {
+                    if let Ok(metadata) = entry_metadata(&entry_arc).await {
+                        send_json(&tx, &metadata);
                     }
                 }
             }
         }
+    }
+
+    forwarder.abort();
+}
+
+/// Whether the handshake's credentials open the stream, if it is private.
+async fn may_listen(app: &AppState, entry: &Arc<RwLock<StreamEntry>>, stream_id: &str, credentials: &ListenerCredentials) -> bool {
+    entry.read().await.config.visibility != Visibility::Private
+        || app.auth.authorize_listener(stream_id, credentials).await.is_ok()
+}
+
+/// The versioned JSON protocol on `/ws`. One socket can follow any number of
+/// streams. A valid admin session in the handshake unlocks admin commands
+/// and events; other credentials in it open private streams.
+async fn run_protocol<S>(app: Arc<AppState>, ws_stream: WebSocketStream<S>, credentials: ListenerCredentials)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut read) = spawn_writer(ws_stream, app.shutdown.clone());
+    let mut subscriptions: HashMap<String, JoinHandle<()>> = HashMap::new();
+    let mut listener = SocketListener { credentials, opened: HashSet::new() };
+
+    let token = listener.credentials.token().map(str::to_string);
+    let admin = token.as_deref()
+        .and_then(|t| app.auth.validate(t))
+        .is_some_and(|user| user.can(Permission::View, Scope::AnyStream));
+    let session = if admin { token } else { None };
+    let admin_forwarder = session.clone().map(|session| {
+        tokio::spawn(forward_admin_events(app.clone(), session, app.admin_events.subscribe(), tx.clone()))
     });
 
-    let _ = stream_name; // not used here, but the WS handler may send it if desired
+    send_json(&tx, &ServerMessage::Welcome { protocol_version: PROTOCOL_VERSION, admin });
 
-    Ok(())
+    while let Some(msg_result) = read.next().await {
+        let text = match msg_result {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        // Re-check every time so logging out, or losing a role, also applies
+        // to open sockets.
+        let user = session.as_deref().and_then(|t| app.auth.validate(t));
+
+        match serde_json::from_str::<ClientMessage>(text.as_str()) {
+            Ok(message) if message.is_admin() => match &user {
+                Some(user) => handle_admin_message(&app, &Actor::new(user, listener.credentials.ip), user, message, &tx).await,
+                None => send_error(&tx, ErrorCode::Unauthorized, "admin session required"),
+            },
+            Ok(message) => handle_client_message(&app, message, &tx, &mut subscriptions, &mut listener, user.is_some()).await,
+            Err(e) => send_error(&tx, ErrorCode::InvalidMessage, e.to_string()),
+        }
+    }
+
+    for (_, forwarder) in subscriptions {
+        forwarder.abort();
+    }
+    if let Some(forwarder) = admin_forwarder {
+        forwarder.abort();
+    }
+}
+
+/// A socket's handshake credentials, and the private streams they opened so
+/// far, so basic auth isn't checked again on every message.
+struct SocketListener {
+    credentials: ListenerCredentials,
+    opened: HashSet<String>,
+}
+
+impl SocketListener {
+    /// The stream's entry, if it exists and the socket may follow it;
+    /// otherwise the error to send.
+    async fn entry(&mut self, app: &AppState, stream_id: &str) -> Result<Arc<RwLock<StreamEntry>>, (ErrorCode, String)> {
+        let entry = app.registry.read().await.get(stream_id).cloned()
+            .ok_or_else(|| (ErrorCode::UnknownStream, format!("unknown stream: {}", stream_id)))?;
+        if !self.opened.contains(stream_id) {
+            if !may_listen(app, &entry, stream_id, &self.credentials).await {
+                return Err((ErrorCode::Unauthorized, format!("stream '{}' is private", stream_id)));
+            }
+            self.opened.insert(stream_id.to_string());
+        }
+        Ok(entry)
+    }
 }
 
-pub async fn get_metadata_json(
+async fn handle_client_message(
+    app: &AppState,
+    message: ClientMessage,
+    tx: &mpsc::UnboundedSender<String>,
+    subscriptions: &mut HashMap<String, JoinHandle<()>>,
+    listener: &mut SocketListener,
+    admin: bool,
+) {
+    match message {
+        ClientMessage::Hello { version } if version != PROTOCOL_VERSION => {
+            send_error(
+                tx,
+                ErrorCode::UnsupportedVersion,
+                format!("protocol version {} is not supported, use {}", version, PROTOCOL_VERSION),
+            );
+        },
+        ClientMessage::Hello { .. } => {
+            send_json(tx, &ServerMessage::Welcome { protocol_version: PROTOCOL_VERSION, admin });
+        },
+        ClientMessage::Subscribe { streams } => {
+            // Check every id first so a typo doesn't leave a partial subscription.
+            let mut entries = Vec::with_capacity(streams.len());
+            for id in &streams {
+                match listener.entry(app, id).await {
+                    Ok(entry) => entries.push((id.clone(), entry)),
+                    Err((code, message)) => return send_error(tx, code, message),
+                }
+            }
+
+            let mut added = Vec::new();
+            for (id, entry) in entries {
+                if subscriptions.contains_key(&id) {
+                    continue;
+                }
+                let events_rx = entry.read().await.events_tx.subscribe();
+                subscriptions.insert(id.clone(), tokio::spawn(forward_events(events_rx, tx.clone())));
+                added.push((id, entry));
+            }
+
+            send_json(tx, &ServerMessage::Subscribed { streams: sorted_keys(subscriptions) });
+
+            // Give new subscribers the current state without a round trip.
+            for (id, entry) in added {
+                if let Ok(metadata) = entry_metadata(&entry).await {
+                    send_json(tx, &ServerMessage::Metadata { stream: id, metadata });
+                }
+            }
+        },
+        ClientMessage::Unsubscribe { streams } => {
+            for id in &streams {
+                if let Some(forwarder) = subscriptions.remove(id) {
+                    forwarder.abort();
+                }
+            }
+            send_json(tx, &ServerMessage::Unsubscribed { streams: sorted_keys(subscriptions) });
+        },
+        ClientMessage::GetMetadata { stream } => {
+            let entry = match listener.entry(app, &stream).await {
+                Ok(entry) => entry,
+                Err((code, message)) => return send_error(tx, code, message),
+            };
+            match entry_metadata(&entry).await {
+                Ok(metadata) => send_json(tx, &ServerMessage::Metadata { stream, metadata }),
+                Err(e) => send_error(tx, ErrorCode::Conflict, e),
+            }
+        },
+        ClientMessage::GetHistory { stream, limit } => {
+            // Simulcasts keep no history of their own; it lives with the
+            // stream that owns the player.
+            let source_id = match listener.entry(app, &stream).await {
+                Ok(entry) => entry.read().await.config.source_id().to_string(),
+                Err((code, message)) => return send_error(tx, code, message),
+            };
+            let tracks = match app.registry.read().await.get(&source_id).cloned() {
+                Some(entry) => {
+                    let e = entry.read().await;
+                    e.history.iter().rev().take(limit.unwrap_or(usize::MAX)).cloned().collect()
+                },
+                None => Vec::new(),
+            };
+            send_json(tx, &ServerMessage::History { stream, tracks });
+        },
+        ClientMessage::Ping => send_json(tx, &ServerMessage::Pong),
+        // Admin commands are dispatched by the caller.
+        _ => send_error(tx, ErrorCode::Unauthorized, "admin session required"),
+    }
+}
+
+async fn handle_admin_message(app: &AppState, actor: &Actor, user: &User, message: ClientMessage, tx: &mpsc::UnboundedSender<String>) {
+    let (permission, stream) = match &message {
+        ClientMessage::Rename { stream, .. } => (Permission::Manage, stream),
+        ClientMessage::GetQueue { stream } => (Permission::View, stream),
+        ClientMessage::Skip { stream }
+        | ClientMessage::Pause { stream }
+        | ClientMessage::Resume { stream }
+        | ClientMessage::Seek { stream, .. }
+        | ClientMessage::QueueAdd { stream, .. }
+        | ClientMessage::QueueRemove { stream, .. }
+        | ClientMessage::QueueClear { stream } => (Permission::Control, stream),
+        _ => return,
+    };
+    if !user.can(permission, Scope::Stream(stream)) {
+        return send_error(tx, ErrorCode::Forbidden, format!("not allowed on stream '{}'", stream));
+    }
+
+    let result = match message {
+        ClientMessage::Skip { stream } => control::skip(app, actor, user, &stream).await.map(|_| ServerMessage::Ok),
+        ClientMessage::Pause { stream } => control::pause(app, actor, user, &stream).await.map(|_| ServerMessage::Ok),
+        ClientMessage::Resume { stream } => control::resume(app, actor, user, &stream).await.map(|_| ServerMessage::Ok),
+        ClientMessage::Rename { stream, name } => control::rename(app, actor, &stream, name).await.map(|_| ServerMessage::Ok),
+        ClientMessage::Seek { stream, position_ms } => control::seek(app, actor, user, &stream, position_ms).await
+            .map(|position_ms| ServerMessage::Seeked { stream, position_ms }),
+        ClientMessage::GetQueue { stream } => control::get_queue(app, user, &stream).await
+            .map(|items| ServerMessage::Queue { stream, items }),
+        ClientMessage::QueueAdd { stream, path, index } => control::queue_add(app, actor, user, &stream, path, index).await
+            .map(|items| ServerMessage::Queue { stream, items }),
+        ClientMessage::QueueRemove { stream, index } => control::queue_remove(app, actor, user, &stream, index).await
+            .map(|items| ServerMessage::Queue { stream, items }),
+        ClientMessage::QueueClear { stream } => control::queue_clear(app, actor, user, &stream).await
+            .map(|_| ServerMessage::Queue { stream, items: Vec::new() }),
+        _ => return,
+    };
+
+    match result {
+        Ok(reply) => send_json(tx, &reply),
+        Err(e) => send_error(tx, e.code, e.message),
+    }
+}
+
+fn sorted_keys(subscriptions: &HashMap<String, JoinHandle<()>>) -> Vec<String> {
+    let mut keys: Vec<String> = subscriptions.keys().cloned().collect();
+    keys.sort();
+    keys
+}
+
+async fn forward_events<T: Serialize + Clone>(mut events_rx: broadcast::Receiver<T>, tx: mpsc::UnboundedSender<String>) {
+    loop {
+        match events_rx.recv().await {
+            Ok(event) => send_json(&tx, &event),
+            // A slow socket misses some events rather than being dropped.
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Admin events for the streams the session's user can see, looked up per
+/// event so grant changes apply straight away. Server-wide events only go to
+/// users with every stream.
+async fn forward_admin_events(
+    app: Arc<AppState>,
+    session: String,
+    mut events_rx: broadcast::Receiver<AdminEvent>,
+    tx: mpsc::UnboundedSender<String>,
+) {
+    loop {
+        match events_rx.recv().await {
+            Ok(event) => {
+                let Some(user) = app.auth.validate(&session) else {
+                    continue;
+                };
+                let visible = match event.stream() {
+                    Some(stream) => user.can_access(stream),
+                    None => user.has_all_streams(),
+                };
+                if visible {
+                    send_json(&tx, &event);
+                }
+            },
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// The stream's current metadata, under its own id and name and with its
+/// listener counts.
+pub async fn entry_metadata(entry: &Arc<RwLock<StreamEntry>>) -> Result<TrackMetadata, String> {
+    let (player, id, name, audience) = {
+        let e = entry.read().await;
+        (e.player.clone(), e.config.id.clone(), e.config.name.clone(), e.audience.clone())
+    };
+    get_metadata(&player, Some(&name), Some(&id), Some(&audience)).await
+}
+
+pub async fn get_metadata(
     player: &OpusPlayerHandle,
     stream_name: Option<&str>,
     stream_id: Option<&str>,
-) -> Result<String, String> {
+    audience: Option<&Audience>,
+) -> Result<TrackMetadata, String> {
     let metadata = player.get_metadata().await.map_err(|e| e.to_string())?
         .ok_or_else(|| "No active file metadata".to_string())?;
     let TimeData { start_time_ms, current_time_ms } = player.get_time_data().await.map_err(|e| e.to_string())?;
     let paused = player.is_paused().await.unwrap_or(false);
+    let overridden = player.is_overridden().await.unwrap_or(false);
+    let counts = audience.map(Audience::counts);
 
-    let stream_id_field = match stream_id {
-        Some(id) => format!(r#","stream_id":"{}""#, escape_json(id)),
-        None => String::new(),
-    };
-    let stream_name_field = match stream_name {
-        Some(name) => format!(r#","stream_name":"{}""#, escape_json(name)),
-        None => String::new(),
-    };
-    let image_field = match metadata.image {
-        Some(url) => format!(r#""{}""#, escape_json(&url)),
-        None => "null".to_string(),
+    Ok(TrackMetadata {
+        id: metadata.id.to_string(),
+        title: metadata.title,
+        author: metadata.author,
+        active_file_duration_ms: metadata.duration_ms,
+        active_file_start_time_ms: start_time_ms,
+        active_file_current_time_ms: current_time_ms,
+        buffer_size_ms: BUFFER_SIZE_MS as u64,
+        image: metadata.image,
+        paused,
+        overridden,
+        stream_id: stream_id.map(str::to_string),
+        stream_name: stream_name.map(str::to_string),
+        listeners: counts.map(|(current, _)| current),
+        peak_listeners: counts.map(|(_, peak)| peak),
+    })
+}
+
+/// Every stream playing from `stream_id`'s player, itself included.
+pub async fn simulcast_group(registry: &StreamRegistry, stream_id: &str) -> Vec<Arc<RwLock<StreamEntry>>> {
+    let registry = registry.read().await;
+    let source_id = match registry.get(stream_id) {
+        Some(entry) => entry.read().await.config.source_id().to_string(),
+        None => return Vec::new(),
     };
 
-    let json = format!(
-        r#"{{"id":"{}","title":"{}","author":"{}","active_file_duration_ms":{},"active_file_start_time_ms":{},"active_file_current_time_ms":{},"buffer_size_ms":{},"image":{},"paused":{}{}{}}}"#,
-        metadata.id,
-        escape_json(&metadata.title),
-        escape_json(&metadata.author),
-        metadata.duration_ms,
-        start_time_ms,
-        current_time_ms,
-        BUFFER_SIZE_MS,
-        image_field,
-        paused,
-        stream_id_field,
-        stream_name_field,
-    );
-
-    Ok(json)
-}
-
-fn escape_json(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for c in s.chars() {
-        match c {
-            '"' => out.push_str("\\\""),
-            '\\' => out.push_str("\\\\"),
-            '\n' => out.push_str("\\n"),
-            '\r' => out.push_str("\\r"),
-            '\t' => out.push_str("\\t"),
-            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
-            c => out.push(c),
+    let mut group = Vec::new();
+    for entry_arc in registry.values() {
+        if entry_arc.read().await.config.source_id() == source_id {
+            group.push(entry_arc.clone());
         }
     }
-    out
+    group
+}
+
+/// Sends the stream's current metadata to everyone following it. Streams
+/// sharing a player through simulcast are updated together, each under its
+/// own id and name.
+pub async fn broadcast_metadata(registry: &StreamRegistry, stream_id: &str) {
+    for entry in simulcast_group(registry, stream_id).await {
+        if let Ok(metadata) = entry_metadata(&entry).await {
+            let e = entry.read().await;
+            e.events_tx.send(StreamEvent::TrackChanged { stream: e.config.id.clone(), metadata });
+        }
+    }
+}
+
+/// Like `broadcast_metadata`, after a pause or resume.
+pub async fn broadcast_paused(registry: &StreamRegistry, stream_id: &str) {
+    for entry in simulcast_group(registry, stream_id).await {
+        if let Ok(metadata) = entry_metadata(&entry).await {
+            let e = entry.read().await;
+            let paused = metadata.paused;
+            e.events_tx.send(StreamEvent::Paused { stream: e.config.id.clone(), paused, metadata });
+        }
+    }
+}
+
+pub async fn broadcast_schedule(registry: &StreamRegistry, stream_id: &str, source: &str, fallback_level: usize) {
+    for entry in simulcast_group(registry, stream_id).await {
+        let e = entry.read().await;
+        e.events_tx.send(StreamEvent::ScheduleChanged {
+            stream: e.config.id.clone(),
+            source: source.to_string(),
+            fallback_level,
+        });
+    }
 }
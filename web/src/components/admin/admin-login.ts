@@ -1,6 +1,6 @@
 import { html, LitElement } from "lit";
 import { customElement, state } from "lit/decorators.js";
-import { login } from "../../lib/admin-api";
+import { login, TotpRequiredError } from "../../lib/admin-api";
 
 @customElement("admin-login")
 export class AdminLogin extends LitElement {
@@ -8,9 +8,19 @@ export class AdminLogin extends LitElement {
     return this;
   }
 
+  @state()
+  private username: string = "admin";
+
   @state()
   private password: string = "";
 
+  @state()
+  private totp: string = "";
+
+  /** Set once the server asks for a two-factor code. */
+  @state()
+  private needsTotp: boolean = false;
+
   @state()
   private error: string | null = null;
 
@@ -27,9 +37,12 @@ export class AdminLogin extends LitElement {
     this.error = null;
     this.submitting = true;
     try {
-      await login(this.password);
+      await login(this.username, this.password, this.needsTotp ? this.totp : undefined);
       this.dispatchEvent(new CustomEvent("admin-logged-in", { bubbles: true, composed: true }));
     } catch (err) {
+      if (err instanceof TotpRequiredError) {
+        this.needsTotp = true;
+      }
       this.error = err instanceof Error ? err.message : "Login failed";
     } finally {
       this.submitting = false;
@@ -41,6 +54,16 @@ export class AdminLogin extends LitElement {
       <main class="bg-gradient-to-b p-4 from-[#51756d] to-[#253330] flex-1 flex items-center justify-center">
         <form @submit=${this.handleSubmit} class="w-full max-w-sm bg-white/5 border border-white/10 rounded-xl p-6">
           <h1 class="text-white text-xl mb-4">Admin login</h1>
+          <label class="block text-white/70 text-sm mb-1" for="admin-username">Username</label>
+          <input
+            id="admin-username"
+            type="text"
+            autocomplete="username"
+            .value=${this.username}
+            @input=${(e: Event) => (this.username = (e.target as HTMLInputElement).value)}
+            class="w-full mb-3 bg-black/30 text-white border border-white/10 rounded-md px-3 py-2 focus:outline-none focus:border-white/30"
+            ?disabled=${this.submitting}
+          />
           <label class="block text-white/70 text-sm mb-1" for="admin-password">Password</label>
           <input
             id="admin-password"
@@ -51,10 +74,26 @@ export class AdminLogin extends LitElement {
             class="w-full bg-black/30 text-white border border-white/10 rounded-md px-3 py-2 focus:outline-none focus:border-white/30"
             ?disabled=${this.submitting}
           />
+          ${this.needsTotp
+            ? html`
+                <label class="block text-white/70 text-sm mt-3 mb-1" for="admin-totp">Two-factor code</label>
+                <input
+                  id="admin-totp"
+                  type="text"
+                  inputmode="numeric"
+                  autocomplete="one-time-code"
+                  placeholder="123456 or a recovery code"
+                  .value=${this.totp}
+                  @input=${(e: Event) => (this.totp = (e.target as HTMLInputElement).value)}
+                  class="w-full bg-black/30 text-white border border-white/10 rounded-md px-3 py-2 focus:outline-none focus:border-white/30"
+                  ?disabled=${this.submitting}
+                />
+              `
+            : ""}
           ${this.error ? html`<p class="mt-2 text-red-300 text-sm">${this.error}</p>` : ""}
           <button
             type="submit"
-            ?disabled=${this.submitting || this.password.length === 0}
+            ?disabled=${this.submitting || this.username.length === 0 || this.password.length === 0 || (this.needsTotp && this.totp.length === 0)}
             class="mt-4 w-full bg-white/15 hover:bg-white/25 disabled:opacity-50 text-white rounded-md py-2 transition-colors"
           >
             ${this.submitting ? "Signing in…" : "Sign in"}
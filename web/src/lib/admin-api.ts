@@ -28,15 +28,25 @@ async function call(path: string, init?: RequestInit): Promise<Response> {
   return response;
 }
 
-export async function login(password: string): Promise<void> {
+export class TotpRequiredError extends Error {
+  constructor() {
+    super("Enter the code from your authenticator app");
+    this.name = "TotpRequiredError";
+  }
+}
+
+export async function login(username: string, password: string, totp?: string): Promise<void> {
   const response = await fetch(adminUrl("/login"), {
     method: "POST",
     credentials: "include",
     headers: { "Content-Type": "application/json" },
-    body: JSON.stringify({ password }),
+    body: JSON.stringify({ username, password, totp: totp || undefined }),
   });
   if (response.status === 401) {
-    throw new Error("Invalid password");
+    const body = await response.json().catch(() => null);
+    if (body?.code === "totp_required") throw new TotpRequiredError();
+    if (body?.code === "invalid_totp") throw new Error("Invalid two-factor code");
+    throw new Error("Invalid username or password");
   }
   if (!response.ok) {
     throw new Error(`Login failed: ${response.status}`);
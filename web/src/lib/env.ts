@@ -1,8 +1,9 @@
 export const API_BASE_URL: string =
   import.meta.env.VITE_API_BASE_URL || "http://localhost:3000";
 
+// WebSockets share the API's port unless pointed at a separate host.
 export const WS_BASE_URL: string =
-  import.meta.env.VITE_WS_URL || "ws://localhost:3001";
+  import.meta.env.VITE_WS_URL || API_BASE_URL.replace(/^http/, "ws");
 
 export function streamsListUrl(): string {
   return `${API_BASE_URL}/streams`;